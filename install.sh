@@ -1,9 +1,30 @@
 set -e
 SERVICE_NAME="alttp-queue-bot"
 SERVICE_PATH="/lib/systemd/system/$SERVICE_NAME.service"
+NGINX_SOURCE_PATH="conf_files/etc/nginx/conf.d/$SERVICE_NAME.conf"
 NGINX_TARGET_PATH="/etc/nginx/conf.d/$SERVICE_NAME.conf"
+
+# the nginx config is only needed for the status page, the API, or HTTP interactions, so it's
+# only installed with --nginx
+INSTALL_NGINX=false
+for arg in "$@"; do
+    case "$arg" in
+        --nginx) INSTALL_NGINX=true ;;
+        *) echo "usage: $0 [--nginx]" >&2; exit 2 ;;
+    esac
+done
+if [ "$INSTALL_NGINX" = true ] && grep -q "example.com" "$NGINX_SOURCE_PATH"; then
+    echo "Set server_name in $NGINX_SOURCE_PATH before installing it" >&2
+    exit 1
+fi
+
 cargo build
 sudo cp "conf_files/lib/systemd/system/$SERVICE_NAME.service" $SERVICE_PATH
 sudo systemctl daemon-reload
 sudo systemctl restart "$SERVICE_NAME"
 sudo systemctl enable "$SERVICE_NAME"
+if [ "$INSTALL_NGINX" = true ]; then
+    sudo cp "$NGINX_SOURCE_PATH" $NGINX_TARGET_PATH
+    sudo nginx -t
+    sudo systemctl reload nginx
+fi
@@ -0,0 +1,3 @@
+ALTER TABLE runs DROP COLUMN weblink;
+ALTER TABLE runs DROP COLUMN stale_reminder_sent_at;
+ALTER TABLE runs DROP COLUMN left_queue_at;
@@ -0,0 +1,7 @@
+-- when we noticed the run was no longer in the SRC queue (NULL while it's still there)
+ALTER TABLE runs ADD COLUMN left_queue_at TEXT NULL;
+ALTER TABLE runs ADD COLUMN stale_reminder_sent_at TEXT NULL;
+ALTER TABLE runs ADD COLUMN weblink TEXT NULL;
+-- we don't know which of the runs we already have are still in the queue. say none of them are;
+-- the next poll un-departs the ones that are, and the rest don't get reminded about
+UPDATE runs SET left_queue_at = strftime('%Y-%m-%dT%H:%M:%SZ', 'now');
@@ -0,0 +1,5 @@
+DROP TABLE digests;
+ALTER TABLE runs DROP COLUMN decided_at;
+ALTER TABLE runs DROP COLUMN outcome;
+ALTER TABLE runs DROP COLUMN category_values;
+ALTER TABLE runs DROP COLUMN category_src_id;
@@ -0,0 +1,15 @@
+ALTER TABLE runs ADD COLUMN category_src_id TEXT NULL;
+-- JSON object of variable id -> value id, exactly as SRC gives it to us
+ALTER TABLE runs ADD COLUMN category_values TEXT NULL;
+-- what happened to the run after it left the queue: 'verified', 'rejected', or 'unknown'
+ALTER TABLE runs ADD COLUMN outcome TEXT NULL;
+ALTER TABLE runs ADD COLUMN decided_at TEXT NULL;
+-- runs that left the queue before we kept track of outcomes. looking them all up would be a lot
+-- of SRC calls, and would count years-old decisions as happening now
+UPDATE runs SET outcome = 'unknown' WHERE left_queue_at IS NOT NULL;
+
+CREATE TABLE IF NOT EXISTS digests (
+    id       INTEGER PRIMARY KEY NOT NULL,
+    period   TEXT NOT NULL,
+    sent_at  TEXT NOT NULL
+);
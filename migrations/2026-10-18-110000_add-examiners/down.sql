@@ -0,0 +1,3 @@
+ALTER TABLE runs DROP COLUMN wait_secs;
+ALTER TABLE runs DROP COLUMN examiner_name;
+ALTER TABLE runs DROP COLUMN examiner_src_id;
@@ -0,0 +1,4 @@
+ALTER TABLE runs ADD COLUMN examiner_src_id TEXT NULL;
+ALTER TABLE runs ADD COLUMN examiner_name TEXT NULL;
+-- seconds between the run being submitted and it being verified/rejected
+ALTER TABLE runs ADD COLUMN wait_secs INTEGER NULL;
@@ -0,0 +1,3 @@
+ALTER TABLE runs DROP COLUMN claimed_at;
+ALTER TABLE runs DROP COLUMN claimed_by_name;
+ALTER TABLE runs DROP COLUMN claimed_by_discord_id;
@@ -0,0 +1,4 @@
+-- the discord user who's said they're going to verify this run
+ALTER TABLE runs ADD COLUMN claimed_by_discord_id TEXT NULL;
+ALTER TABLE runs ADD COLUMN claimed_by_name TEXT NULL;
+ALTER TABLE runs ADD COLUMN claimed_at TEXT NULL;
@@ -0,0 +1,3 @@
+DROP TABLE run_reactions;
+ALTER TABLE runs DROP COLUMN snoozed_until;
+ALTER TABLE runs DROP COLUMN message_id;
@@ -0,0 +1,14 @@
+-- the id of the message we posted about this run, so that we can tell which run a reaction is on
+ALTER TABLE runs ADD COLUMN message_id TEXT NULL;
+-- don't remind the mods that this run is stale until after this
+ALTER TABLE runs ADD COLUMN snoozed_until TEXT NULL;
+
+CREATE TABLE IF NOT EXISTS run_reactions (
+    id              INTEGER PRIMARY KEY NOT NULL,
+    run_id          INTEGER NOT NULL REFERENCES runs(id),
+    discord_user_id TEXT NOT NULL,
+    -- 'verify', 'reject', or 'snooze'
+    reaction        TEXT NOT NULL,
+    reacted_at      TEXT NOT NULL,
+    UNIQUE (run_id, discord_user_id, reaction)
+);
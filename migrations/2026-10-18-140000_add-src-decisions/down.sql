@@ -0,0 +1 @@
+DROP TABLE src_decisions;
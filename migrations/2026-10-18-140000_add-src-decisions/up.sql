@@ -0,0 +1,13 @@
+-- every attempt to verify/reject a run on SRC from discord, whether or not it worked
+CREATE TABLE IF NOT EXISTS src_decisions (
+    id                INTEGER PRIMARY KEY NOT NULL,
+    run_id            TEXT NOT NULL,
+    discord_user_id   TEXT NOT NULL,
+    discord_user_name TEXT NOT NULL,
+    -- 'verified' or 'rejected'
+    decision          TEXT NOT NULL,
+    reason            TEXT NULL,
+    attempted_at      TEXT NOT NULL,
+    -- null if SRC accepted the change
+    error             TEXT NULL
+);
@@ -0,0 +1,2 @@
+DROP TABLE subscriptions;
+ALTER TABLE runs DROP COLUMN runner_name;
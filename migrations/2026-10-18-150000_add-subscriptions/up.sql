@@ -0,0 +1,15 @@
+-- as shown in the run's announcement, so that we can match it against subscriptions later
+ALTER TABLE runs ADD COLUMN runner_name TEXT NULL;
+
+CREATE TABLE IF NOT EXISTS subscriptions (
+    id              INTEGER PRIMARY KEY NOT NULL,
+    discord_user_id TEXT NOT NULL,
+    -- 'runner' or 'category'
+    kind            TEXT NOT NULL,
+    -- lowercased, so that matching is case-insensitive
+    target          TEXT NOT NULL,
+    -- whether to also DM when a matching run is verified
+    notify_verified BOOLEAN NOT NULL DEFAULT 0,
+    created_at      TEXT NOT NULL,
+    UNIQUE (discord_user_id, kind, target)
+);
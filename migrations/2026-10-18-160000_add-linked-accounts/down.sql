@@ -0,0 +1,2 @@
+DROP TABLE linked_accounts;
+ALTER TABLE runs DROP COLUMN runner_src_id;
@@ -0,0 +1,11 @@
+ALTER TABLE runs ADD COLUMN runner_src_id TEXT NULL;
+
+-- discord users who've told us which SRC account is theirs. an SRC account can only be linked
+-- to one discord user; whoever links it first keeps it until they (or a verifier) unlink it
+CREATE TABLE IF NOT EXISTS linked_accounts (
+    id              INTEGER PRIMARY KEY NOT NULL,
+    discord_user_id TEXT UNIQUE NOT NULL,
+    src_user_id     TEXT UNIQUE NOT NULL,
+    src_name        TEXT NOT NULL,
+    linked_at       TEXT NOT NULL
+);
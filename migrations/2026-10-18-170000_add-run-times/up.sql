@@ -0,0 +1,2 @@
+-- the run's primary time, in seconds, so that we can talk about it after it's left the queue
+ALTER TABLE runs ADD COLUMN primary_t DOUBLE NULL;
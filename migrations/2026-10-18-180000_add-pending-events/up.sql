@@ -0,0 +1,8 @@
+-- events we haven't finished sending yet, so that they survive a restart. one row per endpoint
+CREATE TABLE IF NOT EXISTS pending_events (
+    id         INTEGER PRIMARY KEY NOT NULL,
+    endpoint   TEXT NOT NULL,
+    -- the json we send, exactly as it was signed
+    body       TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
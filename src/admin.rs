@@ -0,0 +1,152 @@
+// argument parsing for the admin CLI (src/bin/admin.rs). it's small enough that it isn't worth
+// pulling in an argument parsing crate
+
+pub const USAGE: &str = "usage: admin <command>
+
+commands:
+    post <run-id>                         (re-)post a run, even if we've already posted it
+    forget <run-id>                       delete everything we know about a run, apart from the
+                                          decisions made on it
+    list                                  list the runs we think are in the queue
+    aliases add <category-id> <alias>     display a category under a nicer name
+    aliases remove <category-id>          go back to the category's SRC name
+    categories dump                       print the categories, subcategories and aliases
+    backfill [<game-id>] [--summary]      record everything in a watched game's queue (alttp's by
+                                          default) as seen without posting it, optionally posting
+                                          one summary instead
+    migrate up|down|status                run pending migrations / revert the last one / list them
+    help                                  print this";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MigrateCommand {
+    Up,
+    /// reverts the most recent migration only
+    Down,
+    Status,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Post {
+        run_id: String,
+    },
+    Forget {
+        run_id: String,
+    },
+    List,
+    AddAlias {
+        category_id: String,
+        alias: String,
+    },
+    RemoveAlias {
+        category_id: String,
+    },
+    DumpCategories,
+    /// `game_id` is `None` for alttp
+    Backfill {
+        game_id: Option<String>,
+        summary: bool,
+    },
+    Migrate(MigrateCommand),
+    Help,
+}
+
+/// `args` shouldn't include the program name. the error is what to tell the user
+pub fn parse_args(args: &[String]) -> Result<Command, String> {
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    match args.as_slice() {
+        ["post", run_id] => Ok(Command::Post {
+            run_id: run_id.to_string(),
+        }),
+        ["forget", run_id] => Ok(Command::Forget {
+            run_id: run_id.to_string(),
+        }),
+        ["list"] => Ok(Command::List),
+        // aliases can have spaces in, and it's easy to forget to quote them
+        ["aliases", "add", category_id, alias @ ..] if !alias.is_empty() => Ok(Command::AddAlias {
+            category_id: category_id.to_string(),
+            alias: alias.join(" "),
+        }),
+        ["aliases", "remove", category_id] => Ok(Command::RemoveAlias {
+            category_id: category_id.to_string(),
+        }),
+        ["categories", "dump"] => Ok(Command::DumpCategories),
+        ["backfill"] => Ok(Command::Backfill {
+            game_id: None,
+            summary: false,
+        }),
+        ["backfill", "--summary"] => Ok(Command::Backfill {
+            game_id: None,
+            summary: true,
+        }),
+        ["backfill", game_id] => Ok(Command::Backfill {
+            game_id: Some(game_id.to_string()),
+            summary: false,
+        }),
+        ["backfill", game_id, "--summary"] => Ok(Command::Backfill {
+            game_id: Some(game_id.to_string()),
+            summary: true,
+        }),
+        ["migrate", "up"] => Ok(Command::Migrate(MigrateCommand::Up)),
+        ["migrate", "down"] => Ok(Command::Migrate(MigrateCommand::Down)),
+        ["migrate", "status"] => Ok(Command::Migrate(MigrateCommand::Status)),
+        [] | ["help"] | ["--help"] | ["-h"] => Ok(Command::Help),
+        _ => Err(format!("unrecognized arguments: {}", args.join(" "))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::admin::{parse_args, Command, MigrateCommand};
+
+    fn parse(args: &str) -> Result<Command, String> {
+        parse_args(
+            &args
+                .split_whitespace()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            Ok(Command::Post {
+                run_id: "y8dwozoj".to_string()
+            }),
+            parse("post y8dwozoj")
+        );
+        assert_eq!(Ok(Command::List), parse("list"));
+        assert_eq!(
+            Ok(Command::AddAlias {
+                category_id: "9d8jgv7k".to_string(),
+                alias: "No Major Glitches".to_string()
+            }),
+            parse("aliases add 9d8jgv7k No Major Glitches")
+        );
+        assert_eq!(
+            Ok(Command::Migrate(MigrateCommand::Status)),
+            parse("migrate status")
+        );
+        assert_eq!(
+            Ok(Command::Backfill {
+                game_id: None,
+                summary: true
+            }),
+            parse("backfill --summary")
+        );
+        assert_eq!(
+            Ok(Command::Backfill {
+                game_id: Some("o1y9wo6q".to_string()),
+                summary: false
+            }),
+            parse("backfill o1y9wo6q")
+        );
+        assert_eq!(Ok(Command::Help), parse(""));
+
+        assert!(parse("post").is_err());
+        assert!(parse("aliases add 9d8jgv7k").is_err());
+        assert!(parse("migrate sideways").is_err());
+        assert!(parse("list everything").is_err());
+    }
+}
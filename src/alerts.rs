@@ -0,0 +1,233 @@
+use crate::discord_client::BotDiscordClient;
+use crate::embeds::truncate;
+use crate::error::BotError;
+use chrono::{DateTime, Duration, Utc};
+use log::{info, warn};
+use std::collections::HashMap;
+use std::env;
+use std::env::VarError;
+use std::num::NonZeroU64;
+use twilight_model::channel::message::AllowedMentions;
+use twilight_model::id::marker::{ChannelMarker, UserMarker};
+use twilight_model::id::Id;
+
+/// discord's limit on a message's content, in characters. error messages can include whole
+/// response bodies
+const MESSAGE_LIMIT: usize = 2000;
+
+/// where (and how often) to tell the admins that polling is broken
+pub struct AlertConfig {
+    pub channel_id: Option<Id<ChannelMarker>>,
+    /// DM'd as well as (or instead of) posting in `channel_id`
+    pub user_id: Option<Id<UserMarker>>,
+    /// how many polls in a row have to fail before we say anything. one failure is usually
+    /// just SRC having a moment
+    pub failures_before_alert: u32,
+    /// how long to wait before alerting about the same kind of error again
+    pub cooldown: Duration,
+}
+
+impl AlertConfig {
+    /// returns `None` if alerts aren't configured (i.e. neither `ADMIN_ALERT_CHANNEL_ID` nor
+    /// `ADMIN_ALERT_USER_ID` is set)
+    pub fn from_env() -> Result<Option<Self>, BotError> {
+        let id_var = |key: &str| match env::var(key) {
+            Ok(v) => Ok(Some(v.parse::<NonZeroU64>()?)),
+            Err(VarError::NotPresent) => Ok(None),
+            Err(e) => Err(BotError::from(e)),
+        };
+        let channel_id = id_var("ADMIN_ALERT_CHANNEL_ID")?.map(Id::<ChannelMarker>::from);
+        let user_id = id_var("ADMIN_ALERT_USER_ID")?.map(Id::<UserMarker>::from);
+        if channel_id.is_none() && user_id.is_none() {
+            return Ok(None);
+        }
+        let failures_before_alert = match env::var("ALERT_AFTER_FAILURES") {
+            Ok(n) => n.parse::<u32>()?,
+            Err(_) => 3,
+        };
+        let cooldown_mins = match env::var("ALERT_COOLDOWN_MINS") {
+            Ok(m) => m.parse::<u32>()?,
+            Err(_) => 60,
+        };
+        Ok(Some(Self {
+            channel_id,
+            user_id,
+            failures_before_alert,
+            cooldown: Duration::minutes(cooldown_mins as i64),
+        }))
+    }
+}
+
+/// keeps track of how polling has been going, and decides when that's worth bothering an admin
+/// about
+pub struct Alerter {
+    config: AlertConfig,
+    consecutive_failures: u32,
+    failing_since: Option<DateTime<Utc>>,
+    /// `BotError` variant -> when we last alerted about it. cleared on recovery
+    last_alerted: HashMap<&'static str, DateTime<Utc>>,
+}
+
+impl Alerter {
+    pub fn new(config: AlertConfig) -> Self {
+        Self {
+            config,
+            consecutive_failures: 0,
+            failing_since: None,
+            last_alerted: HashMap::new(),
+        }
+    }
+
+    /// returns the alert to send, if this failure warrants one
+    fn on_failure(&mut self, error: &BotError, now: DateTime<Utc>) -> Option<String> {
+        self.consecutive_failures += 1;
+        let since = *self.failing_since.get_or_insert(now);
+        if self.consecutive_failures < self.config.failures_before_alert {
+            return None;
+        }
+        let variant = error.variant_name();
+        if let Some(last) = self.last_alerted.get(variant) {
+            if now - *last < self.config.cooldown {
+                return None;
+            }
+        }
+        self.last_alerted.insert(variant, now);
+        let message = format!(
+            "\u{26a0}\u{fe0f} Polling has failed {} times in a row (since <t:{}:R>) with {}: {}",
+            self.consecutive_failures,
+            since.timestamp(),
+            variant,
+            error
+        );
+        // the error comes last, so that's what gets cut off
+        Some(truncate(&message, MESSAGE_LIMIT))
+    }
+
+    /// returns the all-clear to send, if we'd raised an alert about this outage
+    fn on_success(&mut self, now: DateTime<Utc>) -> Option<String> {
+        let failures = self.consecutive_failures;
+        let since = self.failing_since.take();
+        self.consecutive_failures = 0;
+        if self.last_alerted.is_empty() {
+            return None;
+        }
+        self.last_alerted.clear();
+        let down_for = since.map(|s| (now - s).num_minutes()).unwrap_or_default();
+        Some(format!(
+            "\u{2705} Polling has recovered after {} failures ({} minutes)",
+            failures, down_for
+        ))
+    }
+
+    /// call this after every poll, with the errors for any runs it couldn't post about. a poll
+    /// that couldn't post anything is as broken as one that didn't happen, so those count as
+    /// failures too
+    pub async fn record(
+        &mut self,
+        result: &Result<Vec<BotError>, BotError>,
+        discord_client: &BotDiscordClient,
+    ) {
+        let error = match result {
+            Ok(run_failures) => run_failures.first(),
+            Err(e) => Some(e),
+        };
+        let message = match error {
+            None => self.on_success(Utc::now()),
+            Some(e) => self.on_failure(e, Utc::now()),
+        };
+        if let Some(m) = message {
+            info!("Sending admin alert: {}", m);
+            self.send(&m, discord_client).await;
+        }
+    }
+
+    /// best effort: if discord is what's broken, this won't get through either
+    async fn send(&self, message: &str, discord_client: &BotDiscordClient) {
+        // error messages could contain anything, so don't let them ping anyone
+        let no_pings = AllowedMentions::default();
+        let mut channels = vec![];
+        if let Some(c) = self.config.channel_id {
+            channels.push(c);
+        }
+        if let Some(u) = self.config.user_id {
+            match discord_client.create_dm_channel(u).await {
+                Ok(c) => channels.push(c),
+                Err(e) => warn!("Unable to open a DM with {} to alert them: {}", u, e),
+            }
+        }
+        for channel_id in channels {
+            match discord_client
+                .create_message_in(channel_id, Some(message), vec![], &no_pings)
+                .await
+            {
+                Ok(rli) => rli.sleep().await,
+                Err(e) => warn!("Unable to send admin alert to {}: {}", channel_id, e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::alerts::{AlertConfig, Alerter, MESSAGE_LIMIT};
+    use crate::error::BotError;
+    use crate::utils::parse_timestamp;
+    use chrono::Duration;
+    use std::env::VarError;
+
+    fn alerter() -> Alerter {
+        Alerter::new(AlertConfig {
+            channel_id: None,
+            user_id: None,
+            failures_before_alert: 2,
+            cooldown: Duration::minutes(60),
+        })
+    }
+
+    #[test]
+    fn test_alerts() {
+        let mut alerter = alerter();
+        let start = parse_timestamp("2022-09-01T12:00:00Z").unwrap();
+        let at = |mins: i64| start + Duration::minutes(mins);
+        let db_error = BotError::InvalidState("database is locked".to_string());
+        let env_error = BotError::VariableMissing(VarError::NotPresent);
+
+        // one failure isn't worth an alert
+        assert!(alerter.on_failure(&db_error, at(0)).is_none());
+        assert!(alerter.on_failure(&db_error, at(1)).is_some());
+        // same kind of error within the cooldown
+        assert!(alerter.on_failure(&db_error, at(2)).is_none());
+        assert!(alerter.on_failure(&db_error, at(59)).is_none());
+        // a different kind of error gets its own alert
+        assert!(alerter.on_failure(&env_error, at(3)).is_some());
+        assert!(alerter.on_failure(&db_error, at(61)).is_some());
+
+        let recovered = alerter.on_success(at(90)).unwrap();
+        assert!(recovered.contains("6 failures"));
+        assert!(recovered.contains("90 minutes"));
+        // nothing to recover from
+        assert!(alerter.on_success(at(91)).is_none());
+    }
+
+    #[test]
+    fn test_long_error() {
+        let mut alerter = alerter();
+        let now = parse_timestamp("2022-09-01T12:00:00Z").unwrap();
+        let error = BotError::InvalidState("x".repeat(5000));
+        alerter.on_failure(&error, now);
+        let alert = alerter.on_failure(&error, now).unwrap();
+        assert_eq!(MESSAGE_LIMIT, alert.chars().count());
+    }
+
+    #[test]
+    fn test_blip_without_alert() {
+        let mut alerter = alerter();
+        let now = parse_timestamp("2022-09-01T12:00:00Z").unwrap();
+        let error = BotError::InvalidState("oops".to_string());
+        assert!(alerter.on_failure(&error, now).is_none());
+        // we never alerted, so there's no need for an all-clear
+        assert!(alerter.on_success(now).is_none());
+        // and the count starts over
+        assert!(alerter.on_failure(&error, now).is_none());
+    }
+}
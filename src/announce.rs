@@ -0,0 +1,133 @@
+// the post we make when a run shows up in the queue
+
+use crate::claims::run_components;
+use crate::embeds::{truncate_list, EmbedBuilder, FIELD_VALUE_LIMIT};
+use crate::error::BotError;
+use crate::links::{linked_discord_user, runner_mention};
+use crate::notifier::{Notifier, RunPost};
+use crate::src::{CategoriesRepository, SRCRun};
+use crate::utils::{format_hms, parse_timestamp};
+use diesel::SqliteConnection;
+use rand::rng;
+use rand::seq::IndexedRandom;
+use twilight_model::channel::message::AllowedMentions;
+use twilight_model::id::marker::MessageMarker;
+use twilight_model::id::Id;
+
+/// sleeps off discord time
+/// posts through whichever `notifier` is configured
+/// returns the id of the message we posted
+/// `decision_buttons` adds buttons for approving/rejecting the run on SRC
+/// mentions the runner if they've linked their SRC account
+pub async fn create_run_message(
+    src_run: &SRCRun<'_>,
+    notifier: &dyn Notifier,
+    categories: &CategoriesRepository<'_>,
+    decision_buttons: bool,
+    conn: &mut SqliteConnection,
+) -> Result<Id<MessageMarker>, BotError> {
+    let titles = vec![
+        "New PB arrived!",
+        "Anotha one",
+        "🚨 PB ALERT 🚨",
+        "Meowski get on this one",
+        "gaming?!",
+        "someone was eating their wheaties",
+        "get a load of this guy",
+        "of all the runs I've seen...",
+        "an ostentatious display of skill",
+        "absolutely cracked",
+        "this run is built different",
+        "speed incarnate",
+        "a true gamer moment",
+        "unreal gaming skills",
+        "this is peak performance",
+        "legendary run",
+        "a masterpiece of speedrunning",
+        "this run deserves a medal",
+        "phenomenal execution",
+        "a run for the ages",
+        "next-level gaming",
+        "a true display of mastery",
+        "this run is fire",
+        "insane gameplay",
+        "elite speedrunning",
+        "this run is art 🎨",
+        "unbelievable performance",
+        "a run to remember",
+        "probably spliced",
+        "always check helma/arrghus!",
+        "some people have all the luck!",
+        "how many capespins in this one?",
+        "doomtaDisdainfulDonny",
+        "swifARTISTE",
+        "will this one start a fight?",
+        "is this WR pace?",
+        "did someone say 'poggers'?",
+        "this run is bussin' fr fr",
+        "a certified hood classic",
+        "MY GOAT",
+        "absolutely no cap",
+        "this run is sus 🕵️",
+        "a true sigma grindset",
+        "chef's kiss 👨‍🍳💋",
+        "GoatEmotey",
+        "superm209Eyes",
+        "now THIS is a 24/7 Andy Watch Party",
+        "its lmos league",
+        "don't forget to show your keybinds!",
+        "someone's hogging all the PB paste",
+        "this needs to be retimed",
+    ];
+
+    let mut rng = rng();
+    let title = titles.choose(&mut rng).map(|s| s.to_string());
+
+    let runners = src_run.player_names();
+    let mut embed = EmbedBuilder::new()
+        .field(
+            "Runner",
+            if runners.is_empty() {
+                "Unknown".to_string()
+            } else {
+                truncate_list(&runners, FIELD_VALUE_LIMIT)
+            },
+            true,
+        )
+        .field(
+            "Category",
+            categories
+                .category_name_from_run(src_run)
+                .unwrap_or("Unknown".to_string()),
+            true,
+        )
+        .field("Time", format_hms(src_run.times.primary_t), true)
+        .url(src_run.weblink.to_string());
+    if let Some(title) = title {
+        embed = embed.title(title);
+    }
+    if let Some(submitted) = src_run.submitted.as_deref().and_then(parse_timestamp) {
+        embed = embed.timestamp(submitted);
+    }
+    let embeds = vec![embed.build()];
+
+    let components = run_components(&src_run.id.to_string(), &src_run.weblink, decision_buttons);
+    let linked_runner = match src_run.player_id() {
+        Some(id) => linked_discord_user(id, conn)?,
+        None => None,
+    };
+    let (content, allowed_mentions) = match linked_runner {
+        Some(user) => {
+            let (content, allowed) = runner_mention(user);
+            (Some(content), allowed)
+        }
+        None => (None, AllowedMentions::default()),
+    };
+    let post = RunPost {
+        content,
+        embeds,
+        components,
+        allowed_mentions,
+    };
+    Ok(notifier.post(&post).await?)
+}
@@ -0,0 +1,180 @@
+// seeding the database with whatever's already in a queue, so that pointing the bot at a fresh
+// database (or a new game) doesn't announce every run in it one by one
+
+use crate::embeds::EmbedBuilder;
+use crate::error::BotError;
+use crate::models::runs::NewRun;
+use crate::notifier::RunPost;
+use crate::schema;
+use crate::src::{get_game_runs, CategoriesRepository, RunsListing, SRCClient, SRCRun};
+use crate::utils::format_hms;
+use crate::ALTTP_GAME_ID;
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+use log::info;
+use std::collections::HashSet;
+use std::env;
+use std::env::VarError;
+use twilight_model::channel::message::AllowedMentions;
+
+/// how many runs a summary lists before it just gives a count
+const SUMMARY_MAX_RUNS: usize = 20;
+
+/// reads `BACKFILL_SUMMARY`: whether to post one message about the runs we backfilled
+pub fn summary_from_env() -> Result<bool, BotError> {
+    match env::var("BACKFILL_SUMMARY") {
+        Ok(s) => match s.as_str() {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            other => Err(BotError::InvalidConfig(format!(
+                "BACKFILL_SUMMARY must be true or false, got {}",
+                other
+            ))),
+        },
+        Err(VarError::NotPresent) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// records every run in `game_id`'s queue (which has to be one the poll loop watches) that we don't already know about as seen, without
+/// posting about any of them. returns the runs we recorded. if we can't get the whole queue, this
+/// fails without recording anything
+pub async fn backfill<'a>(
+    game_id: &'a str,
+    src_client: &'a SRCClient,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<SRCRun<'a>>, BotError> {
+    // runs don't record which game they're from, so the poll loop would take another game's runs
+    // for alttp runs that had left the queue
+    if game_id != ALTTP_GAME_ID {
+        return Err(BotError::InvalidConfig(format!(
+            "The bot only watches {}'s queue, so it can't backfill {}",
+            ALTTP_GAME_ID, game_id
+        )));
+    }
+    let queue = match get_game_runs(src_client, game_id).await? {
+        RunsListing::Complete(runs) => runs,
+        // whatever we missed would get announced one by one, which is what this is here to avoid.
+        // the error's retryable, so it's better to try the whole thing again
+        RunsListing::Partial { error, .. } => return Err(error.into()),
+    };
+    let known = schema::runs::table
+        .select(schema::runs::run_id)
+        .load::<String>(conn)?
+        .into_iter()
+        .collect::<HashSet<_>>();
+    let new_runs = queue
+        .into_iter()
+        .filter(|r| !known.contains(&r.id.to_string()))
+        .collect::<Vec<_>>();
+    // all or nothing, so that a failure partway through doesn't leave the rest to be announced
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        for run in &new_runs {
+            diesel::insert_into(schema::runs::table)
+                .values(NewRun::from_src_run(run, None))
+                .execute(conn)?;
+        }
+        Ok(())
+    })?;
+    info!("Backfilled {} runs for {}", new_runs.len(), game_id);
+    Ok(new_runs)
+}
+
+/// one post standing in for all the runs `backfill` didn't announce
+pub fn summary_post(runs: &[SRCRun<'_>], categories: &CategoriesRepository<'_>) -> RunPost {
+    let mut lines = runs
+        .iter()
+        .take(SUMMARY_MAX_RUNS)
+        .map(|r| {
+            format!(
+                "[{}]({}): {} in {}",
+                r.player().unwrap_or("Unknown"),
+                r.weblink,
+                categories
+                    .category_name_from_run(r)
+                    .unwrap_or_else(|| "Unknown".to_string()),
+                format_hms(r.times.primary_t)
+            )
+        })
+        .collect::<Vec<_>>();
+    if runs.len() > SUMMARY_MAX_RUNS {
+        lines.push(format!("...and {} more", runs.len() - SUMMARY_MAX_RUNS));
+    }
+    RunPost {
+        content: None,
+        embeds: vec![EmbedBuilder::new()
+            .title(format!("{} runs were already in the queue", runs.len()))
+            .description(lines.join("\n"))
+            .build()],
+        components: vec![],
+        allowed_mentions: AllowedMentions::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backfill::{backfill, summary_post};
+    use crate::schema;
+    use crate::src::{CategoriesRepository, SRCClient, SRCRun};
+    use crate::{test_conn, ALTTP_GAME_ID};
+    use diesel::prelude::*;
+    use speedrun_api::api::Root;
+    use speedrun_api::SpeedrunApiClientAsync;
+
+    #[tokio::test]
+    async fn test_unwatched_game() {
+        let client = SRCClient::new(SpeedrunApiClientAsync::new().unwrap());
+        let mut conn = test_conn();
+        // refused before we'd ask SRC for anything
+        assert!(backfill("o1y9wo6q", &client, &mut conn).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_partial_queue() {
+        let dirs = ["runs_first_page", "runs_second_page_fails"]
+            .map(|f| format!("{}/api_responses/replay/{}", env!("CARGO_MANIFEST_DIR"), f));
+        let client = SRCClient::replaying(SpeedrunApiClientAsync::new().unwrap(), dirs).unwrap();
+        let mut conn = test_conn();
+        assert!(backfill(ALTTP_GAME_ID, &client, &mut conn).await.is_err());
+        // not even the first page, or the rest would get announced
+        let recorded = schema::runs::table
+            .count()
+            .get_result::<i64>(&mut conn)
+            .unwrap();
+        assert_eq!(0, recorded);
+    }
+
+    #[test]
+    fn test_summary_post() {
+        let runs = serde_json::from_str::<Root<Vec<SRCRun>>>(include_str!(
+            "../api_responses/runs_embedded_players.json"
+        ))
+        .unwrap()
+        .data;
+        let categories = CategoriesRepository::new(vec![], vec![]);
+
+        let post = summary_post(&runs[..3], &categories);
+        let embed = &post.embeds[0];
+        assert_eq!(
+            Some("3 runs were already in the queue"),
+            embed.title.as_deref()
+        );
+        let description = embed.description.as_deref().unwrap();
+        assert_eq!(3, description.lines().count());
+        assert!(description.starts_with(
+            "[robjbeasley](https://www.speedrun.com/alttp/run/m3xg0vwm): Unknown in "
+        ));
+
+        let mut many = runs;
+        let extra = serde_json::from_str::<Root<Vec<SRCRun>>>(include_str!(
+            "../api_responses/runs_embedded_players.json"
+        ))
+        .unwrap()
+        .data;
+        many.extend(extra);
+        let post = summary_post(&many, &categories);
+        let description = post.embeds[0].description.as_deref().unwrap();
+        assert_eq!(21, description.lines().count());
+        assert!(description.ends_with("...and 10 more"));
+    }
+}
@@ -0,0 +1,271 @@
+extern crate diesel;
+extern crate diesel_migrations;
+
+use alttp_queue_bot::admin::{parse_args, Command, MigrateCommand, USAGE};
+use alttp_queue_bot::announce::create_run_message;
+use alttp_queue_bot::backfill::{backfill, summary_post};
+use alttp_queue_bot::decisions::DecisionConfig;
+use alttp_queue_bot::error::BotError;
+use alttp_queue_bot::interactions::InteractionTransport;
+use alttp_queue_bot::models::aliases::{CategoryAlias, NewCategoryAlias};
+use alttp_queue_bot::models::runs::{NewRun, Run};
+use alttp_queue_bot::notifier::{bot_client_from_env, notifier_from_env, Notifier};
+use alttp_queue_bot::src::{get_categories, get_full_run, CategoriesRepository, SRCClient};
+use alttp_queue_bot::utils::{env_var, format_hms};
+use alttp_queue_bot::web::api::queued_runs;
+use alttp_queue_bot::{get_conn, schema, ALTTP_GAME_ID};
+use diesel::migration::{Migration, MigrationSource};
+use diesel::prelude::*;
+use diesel::sqlite::Sqlite;
+use diesel::SqliteConnection;
+use diesel_migrations::{FileBasedMigrations, MigrationHarness};
+use speedrun_api::SpeedrunApiClientAsync;
+
+fn src_client() -> SRCClient {
+    SRCClient::new(SpeedrunApiClientAsync::new().expect("Unable to create SRC client"))
+}
+
+/// posts about a run the same way the bot would, whether or not we've posted about it before.
+/// useful if a post got deleted, or the bot missed a run
+async fn post(src_id: &str, conn: &mut SqliteConnection) -> Result<(), BotError> {
+    let src_client = src_client();
+    let discord_client = bot_client_from_env()?;
+    // same rule as the bot: without interactions, nobody could press the buttons
+    let decision_buttons = discord_client.is_some()
+        && InteractionTransport::from_env()?.is_some()
+        && DecisionConfig::from_env()?.is_some();
+    let notifier = notifier_from_env(discord_client)?;
+    let categories = CategoriesRepository::new_with_fetch(ALTTP_GAME_ID, &src_client, conn).await?;
+    let src_run = get_full_run(&src_client, src_id).await?;
+    let message_id = create_run_message(
+        &src_run,
+        notifier.as_ref(),
+        &categories,
+        decision_buttons,
+        conn,
+    )
+    .await?;
+
+    let existing = schema::runs::table
+        .filter(schema::runs::run_id.eq(src_id))
+        .first::<Run>(conn)
+        .optional()?;
+    match existing {
+        // point claims, reactions etc at the new post
+        Some(r) => diesel::update(&r)
+            .set(schema::runs::message_id.eq(Some(message_id.to_string())))
+            .execute(conn)?,
+        None => diesel::insert_into(schema::runs::table)
+            .values(NewRun::from_src_run(&src_run, Some(message_id)))
+            .execute(conn)?,
+    };
+    println!("Posted {} as message {}", src_run.weblink, message_id);
+    Ok(())
+}
+
+/// deletes a run and everything hanging off it, except for the decisions made on it: those are a
+/// record of who did what on SRC, and stay put. if it's still in the queue, the bot will post about
+/// it again next time it polls
+fn forget(src_id: &str, conn: &mut SqliteConnection) -> Result<(), BotError> {
+    let forgotten = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        let run = match schema::runs::table
+            .filter(schema::runs::run_id.eq(src_id))
+            .first::<Run>(conn)
+            .optional()?
+        {
+            Some(r) => r,
+            None => return Ok(false),
+        };
+        diesel::delete(
+            schema::run_reactions::table.filter(schema::run_reactions::run_id.eq(run.id)),
+        )
+        .execute(conn)?;
+        diesel::delete(&run).execute(conn)?;
+        Ok(true)
+    })?;
+    if forgotten {
+        println!("Forgot run {}", src_id);
+    } else {
+        println!("We don't know about run {}", src_id);
+    }
+    Ok(())
+}
+
+fn list(conn: &mut SqliteConnection) -> Result<(), BotError> {
+    let queued = queued_runs(conn)?;
+    if queued.is_empty() {
+        println!("Nothing in the queue");
+    }
+    let unknown = || "?".to_string();
+    for run in queued {
+        println!(
+            "{}\t{}\t{}\t{}\t{}",
+            run.run_id,
+            run.submitted.unwrap_or_else(unknown),
+            run.runner_name.unwrap_or_else(unknown),
+            run.primary_t.map(format_hms).unwrap_or_else(unknown),
+            run.claimed_by_name
+                .map(|c| format!("claimed by {}", c))
+                .unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+fn add_alias(category_id: &str, alias: &str, conn: &mut SqliteConnection) -> Result<(), BotError> {
+    // a category can only have one alias, so this replaces any existing one
+    diesel::replace_into(schema::category_aliases::table)
+        .values(NewCategoryAlias {
+            game_src_id: ALTTP_GAME_ID,
+            category_src_id: category_id,
+            alias,
+        })
+        .execute(conn)?;
+    println!(
+        "Category {} will be shown as \"{}\" once the bot restarts",
+        category_id, alias
+    );
+    Ok(())
+}
+
+fn remove_alias(category_id: &str, conn: &mut SqliteConnection) -> Result<(), BotError> {
+    let deleted = diesel::delete(
+        CategoryAlias::by_game_id(ALTTP_GAME_ID)
+            .filter(schema::category_aliases::category_src_id.eq(category_id)),
+    )
+    .execute(conn)?;
+    if deleted == 0 {
+        println!("Category {} doesn't have an alias", category_id);
+    } else {
+        println!(
+            "Removed the alias for category {}; this takes effect once the bot restarts",
+            category_id
+        );
+    }
+    Ok(())
+}
+
+/// everything you need to know to write an alias (or make sense of `category_values`)
+async fn dump_categories(conn: &mut SqliteConnection) -> Result<(), BotError> {
+    let categories = get_categories(ALTTP_GAME_ID, &src_client()).await?;
+    let aliases = CategoryAlias::by_game_id(ALTTP_GAME_ID).load::<CategoryAlias>(conn)?;
+    for category in categories {
+        let id = category.id.to_string();
+        match aliases.iter().find(|a| a.category_src_id == id) {
+            Some(a) => println!("{}\t{} (shown as \"{}\")", id, category.name, a.alias),
+            None => println!("{}\t{}", id, category.name),
+        }
+        for var in category.variables.data.iter().filter(|v| v.is_subcategory) {
+            println!("    subcategory variable {}", var.id);
+            let mut values = var.values.values.iter().collect::<Vec<_>>();
+            values.sort_by(|(_, a), (_, b)| a.label.cmp(&b.label));
+            for (value_id, value) in values {
+                println!("        {}\t{}", value_id, value.label);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// for when the database is behind the queue (e.g. it's been restored from an old backup, or we've
+/// just started watching another game), and announcing everything we missed would flood the channel
+async fn backfill_queue(
+    game_id: &str,
+    summary: bool,
+    conn: &mut SqliteConnection,
+) -> Result<(), BotError> {
+    let src_client = src_client();
+    let backfilled = backfill(game_id, &src_client, conn).await?;
+    println!("Recorded {} runs without posting them", backfilled.len());
+    if summary && !backfilled.is_empty() {
+        let notifier = notifier_from_env(bot_client_from_env()?)?;
+        let categories = CategoriesRepository::new_with_fetch(game_id, &src_client, conn).await?;
+        notifier
+            .post(&summary_post(&backfilled, &categories))
+            .await?;
+        println!("Posted a summary");
+    }
+    Ok(())
+}
+
+fn migrate(command: MigrateCommand, conn: &mut SqliteConnection) -> Result<(), BotError> {
+    let migration_error = |e: Box<dyn std::error::Error + Send + Sync>| {
+        BotError::InvalidState(format!("Migration error: {}", e))
+    };
+    let migrations = FileBasedMigrations::find_migrations_directory()
+        .map_err(|e| BotError::InvalidState(format!("Unable to find migrations: {}", e)))?;
+    match command {
+        MigrateCommand::Up => {
+            let applied = conn
+                .run_pending_migrations(migrations)
+                .map_err(migration_error)?;
+            if applied.is_empty() {
+                println!("No pending migrations");
+            }
+            for version in applied {
+                println!("Applied {}", version);
+            }
+        }
+        MigrateCommand::Down => {
+            let reverted = conn
+                .revert_last_migration(migrations)
+                .map_err(migration_error)?;
+            println!("Reverted {}", reverted);
+        }
+        MigrateCommand::Status => {
+            let applied = conn.applied_migrations().map_err(migration_error)?;
+            let all: Vec<Box<dyn Migration<Sqlite>>> =
+                migrations.migrations().map_err(migration_error)?;
+            for m in all {
+                let version = m.name().version();
+                let state = if applied.iter().any(|a| *a == version) {
+                    "applied"
+                } else {
+                    "pending"
+                };
+                println!("{}\t{}", state, m.name());
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn run(command: Command) -> Result<(), BotError> {
+    if command == Command::Help {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+    let mut conn = get_conn(&env_var("DATABASE_URL"))?;
+    match command {
+        Command::Post { run_id } => post(&run_id, &mut conn).await,
+        Command::Forget { run_id } => forget(&run_id, &mut conn),
+        Command::List => list(&mut conn),
+        Command::AddAlias { category_id, alias } => add_alias(&category_id, &alias, &mut conn),
+        Command::RemoveAlias { category_id } => remove_alias(&category_id, &mut conn),
+        Command::DumpCategories => dump_categories(&mut conn).await,
+        Command::Backfill { game_id, summary } => {
+            let game_id = game_id.as_deref().unwrap_or(ALTTP_GAME_ID);
+            backfill_queue(game_id, summary, &mut conn).await
+        }
+        Command::Migrate(m) => migrate(m, &mut conn),
+        Command::Help => Ok(()),
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    // same config as the bot
+    dotenv::dotenv().ok();
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let command = match parse_args(&args) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+    if let Err(e) = run(command).await {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
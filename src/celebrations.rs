@@ -0,0 +1,199 @@
+use crate::discord_client::BotDiscordClient;
+use crate::embeds::EmbedBuilder;
+use crate::error::BotError;
+use crate::links::{linked_discord_user, runner_mention};
+use crate::metrics::METRICS;
+use crate::models::runs::Run;
+use crate::src::{get_leaderboard_placement, CategoriesRepository, Placement, SRCClient};
+use crate::utils::format_hms;
+use diesel::SqliteConnection;
+use log::{info, warn};
+use std::env;
+use std::env::VarError;
+use std::num::NonZeroU64;
+use twilight_model::channel::embed::Embed;
+use twilight_model::channel::message::AllowedMentions;
+use twilight_model::id::marker::ChannelMarker;
+use twilight_model::id::Id;
+
+/// settings for congratulating runners on their newly verified runs
+pub struct CelebrationConfig {
+    pub channel_id: Id<ChannelMarker>,
+}
+
+impl CelebrationConfig {
+    /// returns `None` if celebrations aren't configured (i.e. `CELEBRATION_CHANNEL_ID` is unset)
+    pub fn from_env() -> Result<Option<Self>, BotError> {
+        let channel_id = match env::var("CELEBRATION_CHANNEL_ID") {
+            Ok(c) => Id::<ChannelMarker>::from(c.parse::<NonZeroU64>()?),
+            Err(VarError::NotPresent) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Some(Self { channel_id }))
+    }
+}
+
+/// how much of a fuss to make
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Celebration {
+    PersonalBest,
+    TopTen,
+    WorldRecord,
+}
+
+impl Celebration {
+    fn from_place(place: u32) -> Self {
+        match place {
+            1 => Celebration::WorldRecord,
+            2..=10 => Celebration::TopTen,
+            _ => Celebration::PersonalBest,
+        }
+    }
+
+    fn color(&self) -> u32 {
+        match self {
+            // gold, silver, and a nice green
+            Celebration::WorldRecord => 0xffd700,
+            Celebration::TopTen => 0xc0c0c0,
+            Celebration::PersonalBest => 0x2ecc71,
+        }
+    }
+}
+
+/// 1st, 2nd, 3rd, 4th, ..., 11th, 12th, 13th, ..., 21st, ...
+fn ordinal(n: u32) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
+}
+
+fn celebration_embed(run: &Run, category: Option<&str>, placement: &Placement) -> Embed {
+    let celebration = Celebration::from_place(placement.place);
+    let runner = run.runner_name.as_deref().unwrap_or("Someone");
+    let category = category.unwrap_or("Unknown category");
+    let title = match celebration {
+        Celebration::WorldRecord => format!("\u{1f3c6} World record! {} in {}", runner, category),
+        Celebration::TopTen => format!(
+            "\u{1f389} {} placed {} in {}!",
+            runner,
+            ordinal(placement.place),
+            category
+        ),
+        Celebration::PersonalBest => format!("New PB for {} in {}", runner, category),
+    };
+    let embed = EmbedBuilder::new()
+        .title(title)
+        .colour(celebration.color())
+        .field("Time", format_hms(placement.primary_t), true)
+        .field("Place", ordinal(placement.place), true);
+    let embed = match &run.weblink {
+        Some(link) => embed.url(link),
+        None => embed,
+    };
+    embed.build()
+}
+
+/// posts a congratulations message for each run that's on its leaderboard. runs that aren't
+/// (e.g. they've already been beaten by the same runner) are skipped quietly, and failing to
+/// celebrate one run (SRC being unhelpful, discord refusing the post) doesn't stop us
+/// celebrating the rest
+pub async fn celebrate_verified_runs(
+    config: &CelebrationConfig,
+    runs: &[Run],
+    src_client: &SRCClient,
+    discord_client: &BotDiscordClient,
+    categories: &CategoriesRepository<'_>,
+    conn: &mut SqliteConnection,
+) {
+    for run in runs {
+        if let Err(e) =
+            celebrate_run(config, run, src_client, discord_client, categories, conn).await
+        {
+            METRICS.record_error(&e);
+            warn!("Unable to celebrate run {}: {}", run.run_id, e);
+        }
+    }
+}
+
+async fn celebrate_run(
+    config: &CelebrationConfig,
+    run: &Run,
+    src_client: &SRCClient,
+    discord_client: &BotDiscordClient,
+    categories: &CategoriesRepository<'_>,
+    conn: &mut SqliteConnection,
+) -> Result<(), BotError> {
+    let category_id = match &run.category_src_id {
+        Some(c) => c,
+        None => return Ok(()),
+    };
+    let subcategory = categories.subcategory_values_from_db_run(run);
+    let placement =
+        match get_leaderboard_placement(src_client, category_id, &subcategory, &run.run_id).await {
+            Ok(Some(p)) => p,
+            Ok(None) => return Ok(()),
+            Err(e) => {
+                warn!(
+                    "Unable to find leaderboard placement for {}: {:?}",
+                    run.run_id, e
+                );
+                return Ok(());
+            }
+        };
+    info!("Celebrating run {} ({:?})", run.run_id, placement);
+    let mention = match &run.runner_src_id {
+        Some(src_id) => linked_discord_user(src_id, conn)?.map(runner_mention),
+        None => None,
+    };
+    let (content, allowed_mentions) = match mention {
+        Some((c, am)) => (Some(c), am),
+        None => (None, AllowedMentions::default()),
+    };
+    let embed = celebration_embed(
+        run,
+        categories.category_name_from_db_run(run).as_deref(),
+        &placement,
+    );
+    discord_client
+        .create_message_in(
+            config.channel_id,
+            content.as_deref(),
+            vec![embed],
+            &allowed_mentions,
+        )
+        .await?
+        .sleep()
+        .await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::celebrations::{ordinal, Celebration};
+
+    #[test]
+    fn test_from_place() {
+        assert_eq!(Celebration::WorldRecord, Celebration::from_place(1));
+        assert_eq!(Celebration::TopTen, Celebration::from_place(2));
+        assert_eq!(Celebration::TopTen, Celebration::from_place(10));
+        assert_eq!(Celebration::PersonalBest, Celebration::from_place(11));
+    }
+
+    #[test]
+    fn test_ordinal() {
+        assert_eq!("1st", ordinal(1));
+        assert_eq!("2nd", ordinal(2));
+        assert_eq!("3rd", ordinal(3));
+        assert_eq!("4th", ordinal(4));
+        assert_eq!("11th", ordinal(11));
+        assert_eq!("12th", ordinal(12));
+        assert_eq!("13th", ordinal(13));
+        assert_eq!("21st", ordinal(21));
+        assert_eq!("112th", ordinal(112));
+    }
+}
@@ -0,0 +1,266 @@
+use crate::decisions::decision_components;
+use crate::embeds::EmbedBuilder;
+use crate::error::BotError;
+use crate::models::runs::Run;
+use crate::schema;
+use crate::utils::timestamp_now;
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+use twilight_model::application::component::button::ButtonStyle;
+use twilight_model::application::component::{ActionRow, Button, Component};
+use twilight_model::channel::embed::Embed;
+use twilight_model::id::marker::UserMarker;
+use twilight_model::id::Id;
+
+const CLAIM_PREFIX: &str = "claim:";
+const UNCLAIM_PREFIX: &str = "unclaim:";
+const CLAIMED_BY_FIELD: &str = "Claimed by";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClaimAction {
+    Claim,
+    Unclaim,
+}
+
+/// the buttons that go on a run announcement. the approve/reject buttons are only included if
+/// `decisions` is set, i.e. if we're pushing decisions to SRC
+pub fn run_components(run_id: &str, weblink: &str, decisions: bool) -> Vec<Component> {
+    let button = |custom_id: String, label: &str, style: ButtonStyle| {
+        Component::Button(Button {
+            custom_id: Some(custom_id),
+            disabled: false,
+            emoji: None,
+            label: Some(label.to_string()),
+            style,
+            url: None,
+        })
+    };
+    let mut rows = vec![Component::ActionRow(ActionRow {
+        components: vec![
+            button(
+                format!("{}{}", CLAIM_PREFIX, run_id),
+                "Claim",
+                ButtonStyle::Primary,
+            ),
+            button(
+                format!("{}{}", UNCLAIM_PREFIX, run_id),
+                "Unclaim",
+                ButtonStyle::Secondary,
+            ),
+            Component::Button(Button {
+                custom_id: None,
+                disabled: false,
+                emoji: None,
+                label: Some("Open on SRC".to_string()),
+                style: ButtonStyle::Link,
+                url: Some(weblink.to_string()),
+            }),
+        ],
+    })];
+    if decisions {
+        rows.push(decision_components(run_id));
+    }
+    rows
+}
+
+/// figures out which button was pressed, and on which run
+pub fn parse_custom_id(custom_id: &str) -> Option<(ClaimAction, &str)> {
+    if let Some(run_id) = custom_id.strip_prefix(CLAIM_PREFIX) {
+        Some((ClaimAction::Claim, run_id))
+    } else {
+        custom_id
+            .strip_prefix(UNCLAIM_PREFIX)
+            .map(|run_id| (ClaimAction::Unclaim, run_id))
+    }
+}
+
+/// what happened when someone pressed a claim button
+#[derive(Debug, PartialEq, Eq)]
+pub enum ClaimOutcome {
+    /// the claim changed; the announcement should be updated to show the new claimant (if any)
+    Updated(Option<Id<UserMarker>>),
+    /// nothing changed, and the user should be told why
+    Refused(String),
+}
+
+/// the check and the update happen in one statement, so that two people pressing claim at the
+/// same time can't both end up thinking they've got it
+pub fn apply_claim(
+    action: ClaimAction,
+    src_run_id: &str,
+    user_id: Id<UserMarker>,
+    user_name: &str,
+    conn: &mut SqliteConnection,
+) -> Result<ClaimOutcome, BotError> {
+    use schema::runs::dsl::*;
+    let me = user_id.to_string();
+    let updated = match action {
+        ClaimAction::Claim => diesel::update(
+            runs.filter(run_id.eq(src_run_id)).filter(
+                claimed_by_discord_id
+                    .is_null()
+                    .or(claimed_by_discord_id.eq(&me)),
+            ),
+        )
+        .set((
+            claimed_by_discord_id.eq(&me),
+            claimed_by_name.eq(user_name),
+            claimed_at.eq(timestamp_now()),
+        ))
+        .execute(conn)?,
+        ClaimAction::Unclaim => diesel::update(
+            runs.filter(run_id.eq(src_run_id))
+                .filter(claimed_by_discord_id.eq(&me)),
+        )
+        .set((
+            claimed_by_discord_id.eq(None::<String>),
+            claimed_by_name.eq(None::<String>),
+            claimed_at.eq(None::<String>),
+        ))
+        .execute(conn)?,
+    };
+    if updated > 0 {
+        return Ok(ClaimOutcome::Updated(match action {
+            ClaimAction::Claim => Some(user_id),
+            ClaimAction::Unclaim => None,
+        }));
+    }
+
+    // we're refusing, so it doesn't matter if this has changed since; it's just to say why
+    let run = match runs
+        .filter(run_id.eq(src_run_id))
+        .first::<Run>(conn)
+        .optional()?
+    {
+        Some(r) => r,
+        None => {
+            return Ok(ClaimOutcome::Refused(
+                "I don't know about that run yet; try again in a minute".to_string(),
+            ))
+        }
+    };
+    let why = match (action, run.claimed_by_name.as_deref()) {
+        (ClaimAction::Claim, name) => format!(
+            "{} has already claimed this run",
+            name.unwrap_or("Somebody")
+        ),
+        (ClaimAction::Unclaim, _) if run.claimed_by_discord_id.is_none() => {
+            "Nobody has claimed this run".to_string()
+        }
+        (ClaimAction::Unclaim, name) => format!(
+            "Only {} can unclaim this run",
+            name.unwrap_or("whoever claimed it")
+        ),
+    };
+    Ok(ClaimOutcome::Refused(why))
+}
+
+/// shows (or stops showing) who has claimed the run on its announcement embed
+pub fn with_claimant(embed: Embed, claimant: Option<Id<UserMarker>>) -> Embed {
+    let embed = EmbedBuilder::from(embed).remove_field(CLAIMED_BY_FIELD);
+    let embed = match claimant {
+        Some(c) => embed.field(CLAIMED_BY_FIELD, format!("<@{}>", c), false),
+        None => embed,
+    };
+    embed.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::claims::{
+        apply_claim, parse_custom_id, run_components, with_claimant, ClaimAction, ClaimOutcome,
+    };
+    use crate::embeds::EmbedBuilder;
+    use crate::schema;
+    use crate::test_conn;
+    use diesel::prelude::*;
+    use twilight_model::application::component::Component;
+    use twilight_model::id::Id;
+
+    #[test]
+    fn test_custom_ids_round_trip() {
+        let components =
+            run_components("abc123", "https://www.speedrun.com/alttp/run/abc123", false);
+        let row = match &components[0] {
+            Component::ActionRow(row) => row,
+            other => panic!("Expected an action row, got {:?}", other),
+        };
+        let parsed = row
+            .components
+            .iter()
+            .filter_map(|c| match c {
+                Component::Button(b) => b.custom_id.as_deref().and_then(parse_custom_id),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (ClaimAction::Claim, "abc123"),
+                (ClaimAction::Unclaim, "abc123")
+            ],
+            parsed
+        );
+        assert_eq!(None, parse_custom_id("something:else"));
+        assert_eq!(
+            2,
+            run_components("abc123", "https://www.speedrun.com/alttp/run/abc123", true).len()
+        );
+    }
+
+    #[test]
+    fn test_with_claimant() {
+        let embed = EmbedBuilder::new()
+            .field("Runner", "somebody", true)
+            .build();
+        let claimed = with_claimant(embed, Some(Id::new(1234)));
+        assert_eq!(2, claimed.fields.len());
+        assert_eq!("<@1234>", claimed.fields[1].value);
+        // claiming again replaces the field rather than adding another one
+        let reclaimed = with_claimant(claimed, Some(Id::new(5678)));
+        assert_eq!(2, reclaimed.fields.len());
+        assert_eq!("<@5678>", reclaimed.fields[1].value);
+        let unclaimed = with_claimant(reclaimed, None);
+        assert_eq!(1, unclaimed.fields.len());
+    }
+
+    #[test]
+    fn test_apply_claim() {
+        let mut conn = test_conn();
+        diesel::insert_into(schema::runs::table)
+            .values(schema::runs::run_id.eq("abc123"))
+            .execute(&mut conn)
+            .unwrap();
+        let (alice, bob) = (Id::new(1), Id::new(2));
+        let mut claim = |action, user, name| apply_claim(action, "abc123", user, name, &mut conn);
+
+        assert_eq!(
+            ClaimOutcome::Updated(Some(alice)),
+            claim(ClaimAction::Claim, alice, "alice").unwrap()
+        );
+        assert_eq!(
+            ClaimOutcome::Refused("alice has already claimed this run".to_string()),
+            claim(ClaimAction::Claim, bob, "bob").unwrap()
+        );
+        assert_eq!(
+            ClaimOutcome::Refused("Only alice can unclaim this run".to_string()),
+            claim(ClaimAction::Unclaim, bob, "bob").unwrap()
+        );
+        // claiming your own run again is fine
+        assert_eq!(
+            ClaimOutcome::Updated(Some(alice)),
+            claim(ClaimAction::Claim, alice, "alice").unwrap()
+        );
+        assert_eq!(
+            ClaimOutcome::Updated(None),
+            claim(ClaimAction::Unclaim, alice, "alice").unwrap()
+        );
+        assert_eq!(
+            ClaimOutcome::Refused("Nobody has claimed this run".to_string()),
+            claim(ClaimAction::Unclaim, alice, "alice").unwrap()
+        );
+        assert_eq!(
+            ClaimOutcome::Updated(Some(bob)),
+            claim(ClaimAction::Claim, bob, "bob").unwrap()
+        );
+    }
+}
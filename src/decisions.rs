@@ -0,0 +1,336 @@
+use crate::discord_client::BotDiscordClient;
+use crate::embeds::EmbedBuilder;
+use crate::error::BotError;
+use crate::get_conn;
+use crate::models::decisions::NewSrcDecision;
+use crate::schema;
+use crate::src::{NewStatus, SRCClient, SRCError, StatusUpdater, DEFAULT_BASE_URL};
+use crate::utils::timestamp_now;
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+use log::{info, warn};
+use std::env;
+use std::env::VarError;
+use std::sync::Arc;
+use std::time::Duration;
+use twilight_model::application::component::button::ButtonStyle;
+use twilight_model::application::component::text_input::TextInputStyle;
+use twilight_model::application::component::{ActionRow, Button, Component, TextInput};
+use twilight_model::application::interaction::modal::ModalInteractionData;
+use twilight_model::channel::embed::Embed;
+use twilight_model::http::interaction::{
+    InteractionResponse, InteractionResponseData, InteractionResponseType,
+};
+use twilight_model::id::marker::UserMarker;
+use twilight_model::id::Id;
+use url::Url;
+
+const APPROVE_PREFIX: &str = "approve:";
+const REJECT_PREFIX: &str = "reject:";
+const REASON_MODAL_PREFIX: &str = "reject-reason:";
+const REASON_INPUT_ID: &str = "reason";
+const DECISION_FIELD: &str = "Decision";
+/// we've already answered the interaction by the time we ask SRC, so this only needs to be short
+/// enough that the verifier isn't left wondering
+const SRC_TIMEOUT: Duration = Duration::from_secs(10);
+/// a decision's `error` until we hear back from SRC. if it's still this, the bot stopped before
+/// it found out whether the change went through
+const NO_ANSWER_YET: &str = "No answer from SRC (yet)";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecisionAction {
+    Approve,
+    Reject,
+}
+
+/// the approve/reject buttons that go on a run announcement, as their own row
+pub fn decision_components(run_id: &str) -> Component {
+    let button = |custom_id: String, label: &str, style: ButtonStyle| {
+        Component::Button(Button {
+            custom_id: Some(custom_id),
+            disabled: false,
+            emoji: None,
+            label: Some(label.to_string()),
+            style,
+            url: None,
+        })
+    };
+    Component::ActionRow(ActionRow {
+        components: vec![
+            button(
+                format!("{}{}", APPROVE_PREFIX, run_id),
+                "Approve on SRC",
+                ButtonStyle::Success,
+            ),
+            button(
+                format!("{}{}", REJECT_PREFIX, run_id),
+                "Reject on SRC",
+                ButtonStyle::Danger,
+            ),
+        ],
+    })
+}
+
+pub fn parse_decision_custom_id(custom_id: &str) -> Option<(DecisionAction, &str)> {
+    if let Some(run_id) = custom_id.strip_prefix(APPROVE_PREFIX) {
+        Some((DecisionAction::Approve, run_id))
+    } else {
+        custom_id
+            .strip_prefix(REJECT_PREFIX)
+            .map(|run_id| (DecisionAction::Reject, run_id))
+    }
+}
+
+/// the run a submitted rejection reason is for
+pub fn parse_reason_modal_id(custom_id: &str) -> Option<&str> {
+    custom_id.strip_prefix(REASON_MODAL_PREFIX)
+}
+
+/// asks the verifier why they're rejecting the run
+pub fn reason_modal(run_id: &str) -> InteractionResponse {
+    InteractionResponse {
+        kind: InteractionResponseType::Modal,
+        data: Some(InteractionResponseData {
+            custom_id: Some(format!("{}{}", REASON_MODAL_PREFIX, run_id)),
+            title: Some("Reject run".to_string()),
+            components: Some(vec![Component::ActionRow(ActionRow {
+                components: vec![Component::TextInput(TextInput {
+                    custom_id: REASON_INPUT_ID.to_string(),
+                    label: "Reason (shown to the runner on SRC)".to_string(),
+                    max_length: Some(1000),
+                    min_length: Some(1),
+                    placeholder: None,
+                    required: Some(true),
+                    style: TextInputStyle::Paragraph,
+                    value: None,
+                })],
+            })]),
+            ..Default::default()
+        }),
+    }
+}
+
+/// pulls the reason out of a submitted `reason_modal`
+pub fn modal_reason(data: &ModalInteractionData) -> Option<&str> {
+    data.components
+        .iter()
+        .flat_map(|row| row.components.iter())
+        .find(|c| c.custom_id == REASON_INPUT_ID)
+        .map(|c| c.value.trim())
+        .filter(|r| !r.is_empty())
+}
+
+/// settings for verifying/rejecting runs on SRC from discord
+pub struct DecisionConfig {
+    /// the SRC API key to act as. decisions show up on SRC as having been made by its owner
+    pub api_key: String,
+    pub base_url: Url,
+}
+
+impl DecisionConfig {
+    /// returns `None` unless `PUSH_DECISIONS_TO_SRC` is "true"
+    pub fn from_env() -> Result<Option<Self>, BotError> {
+        match env::var("PUSH_DECISIONS_TO_SRC") {
+            Ok(f) => match f.as_str() {
+                "true" => {}
+                "false" => return Ok(None),
+                other => {
+                    return Err(BotError::InvalidConfig(format!(
+                        "PUSH_DECISIONS_TO_SRC must be true or false, got {}",
+                        other
+                    )))
+                }
+            },
+            Err(VarError::NotPresent) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let api_key = env::var("SRC_API_KEY")?;
+        let base_url =
+            env::var("SRC_API_BASE_URL").unwrap_or_else(|_| DEFAULT_BASE_URL.to_string());
+        let base_url = Url::parse(&base_url).map_err(|e| {
+            BotError::InvalidConfig(format!("Invalid SRC_API_BASE_URL {}: {}", base_url, e))
+        })?;
+        Ok(Some(Self { api_key, base_url }))
+    }
+}
+
+/// pushes verifiers' decisions to SRC
+pub struct Decisions {
+    updater: StatusUpdater,
+    src_client: Arc<SRCClient>,
+    /// for telling the verifier how it went
+    discord_client: Arc<BotDiscordClient>,
+    /// decisions are pushed in the background, so they need their own connection
+    database_url: String,
+}
+
+impl Decisions {
+    pub fn new(
+        config: DecisionConfig,
+        src_client: Arc<SRCClient>,
+        discord_client: Arc<BotDiscordClient>,
+        database_url: String,
+    ) -> Self {
+        Self {
+            updater: StatusUpdater::new(config.api_key, config.base_url),
+            src_client,
+            discord_client,
+            database_url,
+        }
+    }
+
+    /// pushes the decision, then either adds it to the announcement's `embeds` or tells the
+    /// verifier why it didn't work. the interaction has to have already been answered with a
+    /// deferred update, since this can take longer than discord will wait for an answer
+    pub async fn push_and_follow_up(
+        &self,
+        interaction_token: &str,
+        src_run_id: &str,
+        status: &NewStatus,
+        user_id: Id<UserMarker>,
+        user_name: &str,
+        embeds: Vec<Embed>,
+    ) {
+        let result = match get_conn(&self.database_url) {
+            Ok(mut conn) => {
+                self.push(src_run_id, status, user_id, user_name, &mut conn)
+                    .await
+            }
+            Err(e) => Err(e.into()),
+        };
+        let follow_up = match result {
+            Ok(None) => {
+                let embeds = embeds
+                    .into_iter()
+                    .map(|e| with_decision(e, status, user_id))
+                    .collect::<Vec<_>>();
+                self.discord_client
+                    .update_interaction_message(interaction_token, &embeds)
+                    .await
+            }
+            Ok(Some(why)) => {
+                self.discord_client
+                    .create_ephemeral_followup(interaction_token, &why)
+                    .await
+            }
+            Err(e) => {
+                warn!("Error pushing decision on run {}: {}", src_run_id, e);
+                self.discord_client
+                    .create_ephemeral_followup(
+                        interaction_token,
+                        "Something went wrong. Check the run on SRC before trying again",
+                    )
+                    .await
+            }
+        };
+        if let Err(e) = follow_up {
+            warn!(
+                "Unable to tell {} how setting run {} to {:?} went: {}",
+                user_name, src_run_id, status, e
+            );
+        }
+    }
+
+    /// tries to set the run's status on SRC, and records the attempt and how it went. returns
+    /// an explanation for the verifier if it didn't work
+    async fn push(
+        &self,
+        src_run_id: &str,
+        status: &NewStatus,
+        user_id: Id<UserMarker>,
+        user_name: &str,
+        conn: &mut SqliteConnection,
+    ) -> Result<Option<String>, BotError> {
+        info!(
+            "{} ({}) is setting run {} to {:?}",
+            user_name, user_id, src_run_id, status
+        );
+        // write the attempt down before making it, so that it's on record even if we never
+        // find out how it went (e.g. we're shut down while waiting on SRC)
+        let decision_id = diesel::insert_into(schema::src_decisions::table)
+            .values(NewSrcDecision {
+                run_id: src_run_id,
+                discord_user_id: user_id.to_string(),
+                discord_user_name: user_name,
+                decision: status.as_str(),
+                reason: status.reason(),
+                attempted_at: timestamp_now(),
+                error: Some(NO_ANSWER_YET.to_string()),
+            })
+            .returning(schema::src_decisions::id)
+            .get_result::<i32>(conn)?;
+        // a retry could land after we've told the verifier it failed, and it's better for them
+        // to see the failure and decide than for it to go through behind their back
+        let client = self.src_client.single_attempt();
+        let update = self.updater.set_status(&client, src_run_id, status);
+        let error = match tokio::time::timeout(SRC_TIMEOUT, update).await {
+            Ok(Ok(())) => None,
+            Ok(Err(SRCError::StatusUpdateRefused {
+                status, message, ..
+            })) => Some(format!("SRC refused ({}): {}", status, message)),
+            Ok(Err(e)) => {
+                warn!("Error setting status of run {}: {}", src_run_id, e);
+                Some("Couldn't reach SRC; try again in a bit".to_string())
+            }
+            // the request might still have gone through
+            Err(_) => Some(
+                "SRC didn't answer in time. Check the run on SRC before trying again".to_string(),
+            ),
+        };
+        diesel::update(schema::src_decisions::table.find(decision_id))
+            .set(schema::src_decisions::error.eq(error.clone()))
+            .execute(conn)?;
+        Ok(error)
+    }
+}
+
+/// shows who verified/rejected the run on its announcement embed
+/// a long reject reason gets cut off to fit, but it's on SRC in full
+pub fn with_decision(embed: Embed, status: &NewStatus, user_id: Id<UserMarker>) -> Embed {
+    let value = match status {
+        NewStatus::Verified => format!("Verified by <@{}>", user_id),
+        NewStatus::Rejected { reason } => format!("Rejected by <@{}>: {}", user_id, reason),
+    };
+    EmbedBuilder::from(embed)
+        .remove_field(DECISION_FIELD)
+        .field(DECISION_FIELD, value, false)
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decisions::{
+        decision_components, parse_decision_custom_id, parse_reason_modal_id, reason_modal,
+        DecisionAction,
+    };
+    use twilight_model::application::component::Component;
+
+    #[test]
+    fn test_custom_ids_round_trip() {
+        let row = match decision_components("abc123") {
+            Component::ActionRow(row) => row,
+            other => panic!("Expected an action row, got {:?}", other),
+        };
+        let parsed = row
+            .components
+            .iter()
+            .filter_map(|c| match c {
+                Component::Button(b) => b.custom_id.as_deref().and_then(parse_decision_custom_id),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (DecisionAction::Approve, "abc123"),
+                (DecisionAction::Reject, "abc123")
+            ],
+            parsed
+        );
+        // claim buttons aren't decisions
+        assert_eq!(None, parse_decision_custom_id("claim:abc123"));
+
+        let modal_id = reason_modal("abc123").data.unwrap().custom_id.unwrap();
+        assert_eq!(Some("abc123"), parse_reason_modal_id(&modal_id));
+        assert_eq!(None, parse_decision_custom_id(&modal_id));
+    }
+}
@@ -0,0 +1,293 @@
+use crate::discord_client::BotDiscordClient;
+use crate::embeds::EmbedBuilder;
+use crate::error::BotError;
+use crate::models::digests::{Digest, NewDigest};
+use crate::models::runs::{Outcome, Run};
+use crate::schema;
+use crate::src::CategoriesRepository;
+use crate::utils::{format_timestamp, parse_timestamp, timestamp_now};
+use crate::verifier_stats::{format_verifier_stats, verifier_stats, VerifierStats};
+use chrono::{DateTime, Datelike, Duration, Utc};
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+use log::info;
+use std::collections::HashMap;
+use std::env;
+use std::env::VarError;
+use std::num::NonZeroU64;
+use twilight_model::channel::embed::Embed;
+use twilight_model::channel::message::AllowedMentions;
+use twilight_model::id::marker::ChannelMarker;
+use twilight_model::id::Id;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestPeriod {
+    Daily,
+    /// posted on mondays
+    Weekly,
+}
+
+impl DigestPeriod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DigestPeriod::Daily => "daily",
+            DigestPeriod::Weekly => "weekly",
+        }
+    }
+
+    fn from_str(s: &str) -> Option<Self> {
+        match s.trim() {
+            "daily" => Some(DigestPeriod::Daily),
+            "weekly" => Some(DigestPeriod::Weekly),
+            _ => None,
+        }
+    }
+
+    pub fn length(&self) -> Duration {
+        match self {
+            DigestPeriod::Daily => Duration::days(1),
+            DigestPeriod::Weekly => Duration::weeks(1),
+        }
+    }
+
+    /// the most recent time (at or before `now`) that this digest was scheduled to be posted
+    fn most_recent_slot(&self, now: DateTime<Utc>, hour_utc: u32) -> DateTime<Utc> {
+        // hour_utc is validated when we load config
+        let today = now.date_naive().and_hms_opt(hour_utc, 0, 0).unwrap();
+        let mut slot = DateTime::<Utc>::from_utc(today, Utc);
+        if slot > now {
+            slot = slot - Duration::days(1);
+        }
+        if *self == DigestPeriod::Weekly {
+            slot = slot - Duration::days(slot.weekday().num_days_from_monday() as i64);
+        }
+        slot
+    }
+}
+
+/// settings for the scheduled queue summary posts
+pub struct DigestConfig {
+    pub channel_id: Id<ChannelMarker>,
+    pub periods: Vec<DigestPeriod>,
+    /// the hour of the day (UTC) that digests get posted at
+    pub hour_utc: u32,
+}
+
+impl DigestConfig {
+    /// returns `None` if digests aren't configured (i.e. `DIGEST_CHANNEL_ID` is unset)
+    pub fn from_env() -> Result<Option<Self>, BotError> {
+        let channel_id = match env::var("DIGEST_CHANNEL_ID") {
+            Ok(c) => Id::<ChannelMarker>::from(c.parse::<NonZeroU64>()?),
+            Err(VarError::NotPresent) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let periods = env::var("DIGEST_PERIODS")
+            .unwrap_or_else(|_| "daily,weekly".to_string())
+            .split(',')
+            .map(|p| {
+                DigestPeriod::from_str(p)
+                    .ok_or_else(|| BotError::InvalidConfig(format!("Unknown digest period: {}", p)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let hour_utc = match env::var("DIGEST_HOUR_UTC") {
+            Ok(h) => h.parse::<u32>()?,
+            Err(_) => 0,
+        };
+        if hour_utc > 23 {
+            return Err(BotError::InvalidConfig(format!(
+                "DIGEST_HOUR_UTC must be between 0 and 23, got {}",
+                hour_utc
+            )));
+        }
+        Ok(Some(Self {
+            channel_id,
+            periods,
+            hour_utc,
+        }))
+    }
+}
+
+/// everything that goes into a digest post
+#[derive(Debug)]
+pub struct DigestStats {
+    pub period: DigestPeriod,
+    pub submitted: i64,
+    pub verified: i64,
+    pub rejected: i64,
+    pub queue_length: usize,
+    pub oldest: Option<Run>,
+    /// category name -> number of runs of that category currently in the queue, busiest first
+    pub queue_by_category: Vec<(String, usize)>,
+    pub verifiers: Vec<VerifierStats>,
+}
+
+fn gather_stats(
+    period: DigestPeriod,
+    now: DateTime<Utc>,
+    categories: &CategoriesRepository<'_>,
+    conn: &mut SqliteConnection,
+) -> Result<DigestStats, BotError> {
+    use schema::runs::dsl::*;
+    let since = format_timestamp(now - period.length());
+    let submitted_count = runs
+        .filter(submitted.ge(&since))
+        .count()
+        .get_result::<i64>(conn)?;
+    let verified = runs
+        .filter(outcome.eq(Outcome::Verified.as_str()))
+        .filter(decided_at.ge(&since))
+        .count()
+        .get_result::<i64>(conn)?;
+    let rejected = runs
+        .filter(outcome.eq(Outcome::Rejected.as_str()))
+        .filter(decided_at.ge(&since))
+        .count()
+        .get_result::<i64>(conn)?;
+    let queued = runs
+        .filter(left_queue_at.is_null())
+        .order(submitted.asc())
+        .load::<Run>(conn)?;
+
+    let mut by_category: HashMap<String, usize> = HashMap::new();
+    for r in &queued {
+        let name = categories
+            .category_name_from_db_run(r)
+            .unwrap_or_else(|| "Unknown".to_string());
+        *by_category.entry(name).or_default() += 1;
+    }
+    let mut queue_by_category = by_category.into_iter().collect::<Vec<_>>();
+    queue_by_category.sort_by(|(n1, c1), (n2, c2)| c2.cmp(c1).then(n1.cmp(n2)));
+
+    let verifiers = verifier_stats(Some(&since), conn)?;
+
+    let queue_length = queued.len();
+    // sqlite sorts nulls first, and we don't know how old those are
+    let oldest = queued.into_iter().find(|r| r.submitted.is_some());
+    Ok(DigestStats {
+        period,
+        submitted: submitted_count,
+        verified,
+        rejected,
+        queue_length,
+        oldest,
+        queue_by_category,
+        verifiers,
+    })
+}
+
+fn digest_embed(stats: &DigestStats, now: DateTime<Utc>) -> Embed {
+    let title = match stats.period {
+        DigestPeriod::Daily => "Queue digest: the last 24 hours",
+        DigestPeriod::Weekly => "Queue digest: the last week",
+    };
+    let oldest = match &stats.oldest {
+        Some(r) => {
+            let age = r
+                .submitted
+                .as_deref()
+                .and_then(parse_timestamp)
+                .map(|s| format!(" ({} days)", (now - s).num_days()))
+                .unwrap_or_default();
+            match &r.weblink {
+                Some(link) => format!("[{}]({}){}", r.run_id, link, age),
+                None => format!("{}{}", r.run_id, age),
+            }
+        }
+        None => "Nothing waiting!".to_string(),
+    };
+    let by_category = if stats.queue_by_category.is_empty() {
+        "Queue is empty!".to_string()
+    } else {
+        stats
+            .queue_by_category
+            .iter()
+            .map(|(name, count)| format!("{}: {}", name, count))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    EmbedBuilder::new()
+        .title(title)
+        .field("Submitted", stats.submitted.to_string(), true)
+        .field("Verified", stats.verified.to_string(), true)
+        .field("Rejected", stats.rejected.to_string(), true)
+        .field("In queue", stats.queue_length.to_string(), true)
+        .field("Oldest run", oldest, true)
+        .field("Queue by category", by_category, false)
+        .field("Verifiers", format_verifier_stats(&stats.verifiers), false)
+        .timestamp(now)
+        .build()
+}
+
+/// posts any digests that are due. safe to call as often as you like
+pub async fn post_due_digests(
+    config: &DigestConfig,
+    discord_client: &BotDiscordClient,
+    categories: &CategoriesRepository<'_>,
+    conn: &mut SqliteConnection,
+) -> Result<(), BotError> {
+    use schema::digests::dsl::*;
+    let now = Utc::now();
+    for p in &config.periods {
+        let last = digests
+            .filter(period.eq(p.as_str()))
+            .order(sent_at.desc())
+            .first::<Digest>(conn)
+            .optional()?;
+        let slot = p.most_recent_slot(now, config.hour_utc);
+        let due = match last.as_ref().and_then(|d| parse_timestamp(&d.sent_at)) {
+            Some(last_sent) => last_sent < slot,
+            None => true,
+        };
+        if !due {
+            continue;
+        }
+        info!("Posting {} digest", p.as_str());
+        let stats = gather_stats(*p, now, categories, conn)?;
+        let rli = discord_client
+            .create_message_in(
+                config.channel_id,
+                None,
+                vec![digest_embed(&stats, now)],
+                &AllowedMentions::default(),
+            )
+            .await?;
+        diesel::insert_into(digests)
+            .values(NewDigest {
+                period: p.as_str(),
+                sent_at: timestamp_now(),
+            })
+            .execute(conn)?;
+        rli.sleep().await;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::digest::DigestPeriod;
+    use crate::utils::parse_timestamp;
+
+    #[test]
+    fn test_most_recent_slot() {
+        // a wednesday
+        let now = parse_timestamp("2022-09-07T10:30:00Z").unwrap();
+        assert_eq!(
+            parse_timestamp("2022-09-07T09:00:00Z").unwrap(),
+            DigestPeriod::Daily.most_recent_slot(now, 9)
+        );
+        assert_eq!(
+            parse_timestamp("2022-09-06T12:00:00Z").unwrap(),
+            DigestPeriod::Daily.most_recent_slot(now, 12)
+        );
+        assert_eq!(
+            parse_timestamp("2022-09-05T09:00:00Z").unwrap(),
+            DigestPeriod::Weekly.most_recent_slot(now, 9)
+        );
+        // monday, but before the scheduled hour: last week's slot
+        let monday_morning = parse_timestamp("2022-09-05T08:00:00Z").unwrap();
+        assert_eq!(
+            parse_timestamp("2022-08-29T09:00:00Z").unwrap(),
+            DigestPeriod::Weekly.most_recent_slot(monday_morning, 9)
+        );
+    }
+}
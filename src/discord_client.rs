@@ -1,14 +1,22 @@
-use crate::error::BotError;
+use crate::error::{BotError, ErrorClass};
+use crate::metrics::METRICS;
 use crate::utils::secs_to_millis;
 use std::env;
 use std::num::NonZeroU64;
 use std::time::Duration;
+use twilight_http::api_error::ApiError;
 use twilight_http::error::ErrorType;
 use twilight_http::response::{DeserializeBodyError, HeaderIter};
 use twilight_http::{Client, Error, Response};
+use twilight_model::application::command::Command;
+use twilight_model::application::component::Component;
 use twilight_model::channel::embed::Embed;
+use twilight_model::channel::message::{AllowedMentions, MessageFlags};
 use twilight_model::channel::Channel;
-use twilight_model::id::marker::{ApplicationMarker, ChannelMarker};
+use twilight_model::http::interaction::InteractionResponse;
+use twilight_model::id::marker::{
+    ApplicationMarker, ChannelMarker, InteractionMarker, MessageMarker, UserMarker,
+};
 use twilight_model::id::Id;
 
 pub struct BotDiscordClient {
@@ -32,6 +40,21 @@ pub enum DiscordError {
     /// caller provided bad input
     #[error("Programmer error invalid input: {0}")]
     InvalidInput(#[from] InvalidInputError),
+    /// error posting to (or editing through) a webhook
+    #[error("Webhook error: {0}")]
+    WebhookError(String),
+    /// error writing out what we would have posted
+    #[error("Dry run error: {0}")]
+    DryRunError(String),
+    /// the message was posted, but we couldn't tell what its id was
+    #[error("Message posted, but its id couldn't be read: {0}")]
+    PostedWithoutId(String),
+}
+
+/// the only part of a message we just posted that we care about
+#[derive(serde::Deserialize)]
+struct PostedMessage {
+    id: Id<MessageMarker>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -41,6 +64,54 @@ pub enum InvalidInputError {
 }
 
 impl DiscordError {
+    /// how long discord told us to wait, if this is us being rate limited
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            DiscordError::HttpError(httpe) => match httpe.kind() {
+                ErrorType::Response {
+                    error: ApiError::Ratelimited(rl),
+                    ..
+                } => Some(Duration::from_millis(secs_to_millis(rl.retry_after))),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn class(&self) -> ErrorClass {
+        if let Some(retry_after) = self.retry_after() {
+            return ErrorClass::Retryable {
+                retry_after: Some(retry_after),
+            };
+        }
+        match self {
+            DiscordError::HttpError(httpe) => match httpe.kind() {
+                ErrorType::Response { status, .. } => match status.get() {
+                    // the token's wrong, or the bot's missing permissions in the channel
+                    401 | 403 => ErrorClass::Configuration,
+                    429 | 500..=599 => ErrorClass::Retryable { retry_after: None },
+                    _ => ErrorClass::Permanent,
+                },
+                ErrorType::RequestError
+                | ErrorType::RequestTimedOut
+                | ErrorType::RequestCanceled
+                | ErrorType::ServiceUnavailable { .. } => {
+                    ErrorClass::Retryable { retry_after: None }
+                }
+                ErrorType::Unauthorized => ErrorClass::Configuration,
+                _ => ErrorClass::Permanent,
+            },
+            // mostly the webhook not answering, rather than us sending something bad
+            DiscordError::WebhookError(_) => ErrorClass::Retryable { retry_after: None },
+            DiscordError::ValidationError(_)
+            | DiscordError::DeserializeBodyError(_)
+            | DiscordError::InvalidInput(_)
+            | DiscordError::DryRunError(_)
+            // trying again would post it twice
+            | DiscordError::PostedWithoutId(_) => ErrorClass::Permanent,
+        }
+    }
+
     pub fn is_404(&self) -> bool {
         match self {
             DiscordError::HttpError(httpe) => match httpe.kind() {
@@ -158,6 +229,7 @@ impl<T> WithRateLimitInfo<T> {
     /// sleeps for the amount of time discord told us to, if any
     pub async fn sleep(&self) {
         if let Some(sleep_time) = self.sleep_time() {
+            METRICS.record_rate_limit_sleep(sleep_time);
             tokio::time::sleep(sleep_time).await;
         }
     }
@@ -177,23 +249,270 @@ impl BotDiscordClient {
         })
     }
 
+    /// sends everything to `base_url` (e.g. `FakeDiscord`'s) instead of discord. twilight's own rate
+    /// limiter is switched off, so that tests can script rate limits without waiting them out
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn new_with_base_url(
+        token: String,
+        application_id: Id<ApplicationMarker>,
+        channel_id: Id<ChannelMarker>,
+        base_url: &url::Url,
+    ) -> Result<Self, BotError> {
+        let host = base_url.host_str().ok_or_else(|| {
+            BotError::InvalidConfig(format!("Discord base url {} has no host", base_url))
+        })?;
+        let proxy = match base_url.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        };
+        let client = Client::builder()
+            .token(token)
+            .proxy(proxy, base_url.scheme() == "http")
+            .ratelimiter(None)
+            .build();
+        Ok(Self {
+            client,
+            application_id,
+            channel_id,
+        })
+    }
+
     /// Fetches a channel from discord by ID (no caching)
     pub async fn fetch_channel(&self, id: Id<ChannelMarker>) -> Result<Channel, DiscordError> {
         let resp = self.client.channel(id).exec().await?;
         Ok(resp.model().await?)
     }
 
+    /// posts to the main channel. like `create_message_in`, nothing in `content` will ping
+    /// anyone unless it's allowed by `allowed_mentions`
     pub async fn create_message(
         &self,
+        content: Option<&str>,
         embeds: Vec<Embed>,
-    ) -> Result<WithRateLimitInfo<()>, DiscordError> {
-        let resp = self
+        components: Vec<Component>,
+        allowed_mentions: &AllowedMentions,
+    ) -> Result<WithRateLimitInfo<Id<MessageMarker>>, DiscordError> {
+        let mut req = self
             .client
             .create_message(self.channel_id)
             .embeds(&embeds)
             .map_err(|e| DiscordError::ValidationError(e.to_string()))?
+            .components(&components)
+            .map_err(|e| DiscordError::ValidationError(e.to_string()))?
+            .allowed_mentions(Some(allowed_mentions));
+        if let Some(c) = content {
+            req = req
+                .content(c)
+                .map_err(|e| DiscordError::ValidationError(e.to_string()))?;
+        }
+        let resp = req.exec().await?;
+        let rli = RateLimitInfo::from_headers(resp.headers());
+        // the message has been posted by now, so this mustn't fail on something we don't need
+        // (e.g. some field twilight doesn't know about). all we want is the id
+        let body = resp.bytes().await?;
+        let message = serde_json::from_slice::<PostedMessage>(&body)
+            .map_err(|e| DiscordError::PostedWithoutId(e.to_string()))?;
+        Ok(WithRateLimitInfo {
+            rli,
+            item: message.id,
+        })
+    }
+
+    /// posts a message to an arbitrary channel. nothing in `content` will ping anyone unless
+    /// it's allowed by `allowed_mentions`
+    pub async fn create_message_in(
+        &self,
+        channel_id: Id<ChannelMarker>,
+        content: Option<&str>,
+        embeds: Vec<Embed>,
+        allowed_mentions: &AllowedMentions,
+    ) -> Result<WithRateLimitInfo<()>, DiscordError> {
+        let mut req = self
+            .client
+            .create_message(channel_id)
+            .embeds(&embeds)
+            .map_err(|e| DiscordError::ValidationError(e.to_string()))?
+            .allowed_mentions(Some(allowed_mentions));
+        if let Some(c) = content {
+            req = req
+                .content(c)
+                .map_err(|e| DiscordError::ValidationError(e.to_string()))?;
+        }
+        let resp = req.exec().await?;
+        Ok(WithRateLimitInfo::new((), &resp))
+    }
+
+    /// edits one of our messages in the main channel. `content` replaces the existing content,
+    /// including removing it if it's `None`
+    pub async fn update_message(
+        &self,
+        message_id: Id<MessageMarker>,
+        content: Option<&str>,
+        embeds: &[Embed],
+        components: &[Component],
+        allowed_mentions: &AllowedMentions,
+    ) -> Result<WithRateLimitInfo<()>, DiscordError> {
+        let resp = self
+            .client
+            .update_message(self.channel_id, message_id)
+            .content(content)
+            .map_err(|e| DiscordError::ValidationError(e.to_string()))?
+            .embeds(Some(embeds))
+            .map_err(|e| DiscordError::ValidationError(e.to_string()))?
+            .components(Some(components))
+            .map_err(|e| DiscordError::ValidationError(e.to_string()))?
+            .allowed_mentions(Some(allowed_mentions))
             .exec()
             .await?;
         Ok(WithRateLimitInfo::new((), &resp))
     }
+
+    /// gets (or opens) the DM channel between us and `user_id`. discord hands back the same
+    /// channel every time, so there's no need to remember it
+    pub async fn create_dm_channel(
+        &self,
+        user_id: Id<UserMarker>,
+    ) -> Result<Id<ChannelMarker>, DiscordError> {
+        let resp = self.client.create_private_channel(user_id).exec().await?;
+        Ok(resp.model().await?.id)
+    }
+
+    /// replaces all of our global slash commands with `commands`
+    pub async fn set_commands(&self, commands: &[Command]) -> Result<(), DiscordError> {
+        self.client
+            .interaction(self.application_id)
+            .set_global_commands(commands)
+            .exec()
+            .await?;
+        Ok(())
+    }
+
+    pub async fn create_interaction_response(
+        &self,
+        interaction_id: Id<InteractionMarker>,
+        interaction_token: &str,
+        response: &InteractionResponse,
+    ) -> Result<(), DiscordError> {
+        self.client
+            .interaction(self.application_id)
+            .create_response(interaction_id, interaction_token, response)
+            .exec()
+            .await?;
+        Ok(())
+    }
+
+    /// replaces the embeds on the message a button was pressed on, once we've answered the
+    /// interaction with a deferred update
+    pub async fn update_interaction_message(
+        &self,
+        interaction_token: &str,
+        embeds: &[Embed],
+    ) -> Result<(), DiscordError> {
+        self.client
+            .interaction(self.application_id)
+            .update_response(interaction_token)
+            .embeds(Some(embeds))
+            .map_err(|e| DiscordError::ValidationError(e.to_string()))?
+            .exec()
+            .await?;
+        Ok(())
+    }
+
+    /// a message only the user who triggered the interaction can see, sent after we've already
+    /// answered it
+    pub async fn create_ephemeral_followup(
+        &self,
+        interaction_token: &str,
+        content: &str,
+    ) -> Result<(), DiscordError> {
+        self.client
+            .interaction(self.application_id)
+            .create_followup(interaction_token)
+            .content(content)
+            .map_err(|e| DiscordError::ValidationError(e.to_string()))?
+            .flags(MessageFlags::EPHEMERAL)
+            .exec()
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ErrorClass;
+    use crate::fake_discord::{FakeDiscord, FakeResponse};
+    use hyper::{Method, StatusCode};
+    use std::time::Duration;
+    use twilight_model::channel::message::AllowedMentions;
+    use twilight_model::id::Id;
+
+    #[tokio::test]
+    async fn test_rate_limit_info() {
+        let discord = FakeDiscord::start().await;
+        let client = discord.client(Id::new(10));
+        let no_pings = AllowedMentions::default();
+
+        let rli = client
+            .create_message(Some("hello"), vec![], vec![], &no_pings)
+            .await
+            .unwrap();
+        // there's plenty left in the bucket
+        assert_eq!(None, rli.sleep_time());
+
+        discord.script(FakeResponse::LastInBucket { reset_after: 1.5 });
+        let rli = client
+            .create_message(Some("hello again"), vec![], vec![], &no_pings)
+            .await
+            .unwrap();
+        assert_eq!(Some(Duration::from_millis(1500)), rli.sleep_time());
+
+        let requests = discord.requests();
+        assert_eq!(2, requests.len());
+        assert_eq!(Method::POST, requests[1].method);
+        assert_eq!("/channels/10/messages", requests[1].path);
+        assert_eq!("hello again", requests[1].body.as_ref().unwrap()["content"]);
+    }
+
+    #[tokio::test]
+    async fn test_create_message_only_needs_the_id() {
+        let discord = FakeDiscord::start().await;
+        let client = discord.client(Id::new(10));
+        discord.script(FakeResponse::BareMessage);
+        let posted = client
+            .create_message(Some("hello"), vec![], vec![], &AllowedMentions::default())
+            .await
+            .unwrap();
+        assert_eq!(Id::new(1001), posted.item);
+    }
+
+    #[tokio::test]
+    async fn test_is_404() {
+        let discord = FakeDiscord::start().await;
+        let client = discord.client(Id::new(10));
+        let no_pings = AllowedMentions::default();
+
+        discord.script(FakeResponse::NotFound);
+        discord.script(FakeResponse::ServerError(StatusCode::BAD_GATEWAY));
+        let deleted = client
+            .update_message(Id::new(20), Some("edited"), &[], &[], &no_pings)
+            .await
+            .unwrap_err();
+        assert!(deleted.is_404());
+        assert_eq!(ErrorClass::Permanent, deleted.class());
+        let unavailable = client
+            .update_message(Id::new(20), Some("edited"), &[], &[], &no_pings)
+            .await
+            .unwrap_err();
+        assert!(!unavailable.is_404());
+        assert_eq!(
+            ErrorClass::Retryable { retry_after: None },
+            unavailable.class()
+        );
+        // and back to normal
+        client
+            .update_message(Id::new(20), Some("edited"), &[], &[], &no_pings)
+            .await
+            .unwrap();
+        assert_eq!("/channels/10/messages/20", discord.requests()[2].path);
+    }
 }
@@ -0,0 +1,262 @@
+// `--dry-run`: run the real poll loop against live SRC data, but write what we'd have posted to
+// the terminal (or a file) instead of discord
+
+use crate::discord_client::DiscordError;
+use crate::error::BotError;
+use crate::get_conn;
+use crate::notifier::{Notifier, RunPost};
+use async_trait::async_trait;
+use diesel::sql_types::Text;
+use diesel::RunQueryDsl;
+use serde::Serialize;
+use std::env;
+use std::env::VarError;
+use std::fs::File;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use twilight_model::application::component::Component;
+use twilight_model::channel::embed::Embed;
+use twilight_model::channel::message::AllowedMentions;
+use twilight_model::id::marker::MessageMarker;
+use twilight_model::id::Id;
+
+pub struct DryRunConfig {
+    /// write posts here, one JSON object per line, instead of printing them
+    pub output: Option<PathBuf>,
+    /// work on a scratch copy of the database, so that the real one is left alone. on unless
+    /// `DRY_RUN_SKIP_DB_WRITES` is explicitly "false"
+    pub skip_db_writes: bool,
+}
+
+impl DryRunConfig {
+    /// returns `None` unless we were started with `--dry-run`
+    pub fn from_env() -> Result<Option<Self>, BotError> {
+        if !env::args().skip(1).any(|a| a == "--dry-run") {
+            return Ok(None);
+        }
+        let skip_db_writes = match env::var("DRY_RUN_SKIP_DB_WRITES") {
+            Ok(s) => match s.as_str() {
+                "true" => true,
+                "false" => false,
+                other => {
+                    return Err(BotError::InvalidConfig(format!(
+                        "DRY_RUN_SKIP_DB_WRITES must be true or false, got {}",
+                        other
+                    )))
+                }
+            },
+            Err(VarError::NotPresent) => true,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Some(Self {
+            output: env::var("DRY_RUN_OUTPUT").ok().map(PathBuf::from),
+            skip_db_writes,
+        }))
+    }
+}
+
+/// copies the database somewhere we can scribble on it, and returns the copy's url. the copy
+/// is overwritten every time, so each dry run starts from the real database's current state
+pub fn scratch_database(database_url: &str) -> Result<String, BotError> {
+    let scratch = env::temp_dir().join("alttp-queue-bot-dry-run.db3");
+    let scratch = scratch.to_string_lossy().into_owned();
+    // sqlite would replay a -wal left over from the last dry run on top of the fresh copy
+    for suffix in ["", "-wal", "-shm"] {
+        let path = format!("{}{}", scratch, suffix);
+        match std::fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(BotError::InvalidState(format!(
+                    "Unable to remove {}: {}",
+                    path, e
+                )))
+            }
+        }
+    }
+    // copying the file itself would miss anything that's only in the real database's -wal so
+    // far, so have sqlite make the copy
+    let mut conn = get_conn(database_url)?;
+    diesel::sql_query("VACUUM INTO ?")
+        .bind::<Text, _>(&scratch)
+        .execute(&mut conn)?;
+    Ok(scratch)
+}
+
+/// what a line of `DRY_RUN_OUTPUT` looks like
+#[derive(Serialize)]
+struct DryRunRecord<'a> {
+    /// "post" or "edit"
+    action: &'a str,
+    message_id: Id<MessageMarker>,
+    content: Option<&'a str>,
+    embeds: &'a [Embed],
+    components: &'a [Component],
+    allowed_mentions: &'a AllowedMentions,
+}
+
+/// a notifier that doesn't notify anyone
+pub struct DryRunNotifier {
+    /// `None` means the terminal
+    file: Option<Mutex<File>>,
+    /// we have to hand back *some* id, so that edits have something to refer to
+    next_message_id: AtomicU64,
+}
+
+impl DryRunNotifier {
+    /// `output` is truncated if it already exists
+    pub fn new(output: Option<&Path>) -> Result<Self, BotError> {
+        let file = match output {
+            Some(path) => Some(Mutex::new(File::create(path).map_err(|e| {
+                BotError::InvalidState(format!("Unable to create {}: {}", path.display(), e))
+            })?)),
+            None => None,
+        };
+        Ok(Self {
+            file,
+            next_message_id: AtomicU64::new(1),
+        })
+    }
+
+    fn write(
+        &self,
+        action: &str,
+        message_id: Id<MessageMarker>,
+        post: &RunPost,
+    ) -> Result<(), DiscordError> {
+        let file = match &self.file {
+            Some(f) => f,
+            None => {
+                println!("{}", render(action, message_id, post));
+                return Ok(());
+            }
+        };
+        let record = DryRunRecord {
+            action,
+            message_id,
+            content: post.content.as_deref(),
+            embeds: &post.embeds,
+            components: &post.components,
+            allowed_mentions: &post.allowed_mentions,
+        };
+        let line =
+            serde_json::to_string(&record).map_err(|e| DiscordError::DryRunError(e.to_string()))?;
+        let mut file = file
+            .lock()
+            .map_err(|e| DiscordError::DryRunError(e.to_string()))?;
+        writeln!(file, "{}", line).map_err(|e| DiscordError::DryRunError(e.to_string()))
+    }
+}
+
+#[async_trait]
+impl Notifier for DryRunNotifier {
+    async fn post(&self, post: &RunPost) -> Result<Id<MessageMarker>, DiscordError> {
+        let id = self.next_message_id.fetch_add(1, Ordering::Relaxed);
+        // the counter starts at 1, so this can't be 0
+        let message_id = Id::new_checked(id).unwrap_or_else(|| Id::new(1));
+        self.write("post", message_id, post)?;
+        Ok(message_id)
+    }
+
+    async fn edit(
+        &self,
+        message_id: Id<MessageMarker>,
+        post: &RunPost,
+    ) -> Result<(), DiscordError> {
+        self.write("edit", message_id, post)
+    }
+}
+
+/// roughly what the post would look like in discord
+fn render(action: &str, message_id: Id<MessageMarker>, post: &RunPost) -> String {
+    let mut lines = vec![format!("[dry run] {} message {}", action, message_id)];
+    if let Some(content) = &post.content {
+        lines.push(format!("  {}", content));
+    }
+    for embed in &post.embeds {
+        match (&embed.title, &embed.url) {
+            (Some(title), Some(url)) => lines.push(format!("  # {} <{}>", title, url)),
+            (Some(title), None) => lines.push(format!("  # {}", title)),
+            (None, Some(url)) => lines.push(format!("  <{}>", url)),
+            (None, None) => {}
+        }
+        if let Some(description) = &embed.description {
+            lines.push(format!("  {}", description));
+        }
+        for field in &embed.fields {
+            lines.push(format!("  {}: {}", field.name, field.value));
+        }
+    }
+    if !post.components.is_empty() {
+        lines.push(format!("  ({} rows of buttons)", post.components.len()));
+    }
+    if !post.allowed_mentions.users.is_empty() {
+        let pinged = post
+            .allowed_mentions
+            .users
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        lines.push(format!("  (would ping {})", pinged.join(", ")));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dry_run::{render, DryRunNotifier};
+    use crate::embeds::EmbedBuilder;
+    use crate::notifier::{Notifier, RunPost};
+    use crate::test_path;
+    use twilight_model::channel::message::AllowedMentions;
+    use twilight_model::id::Id;
+
+    fn post() -> RunPost {
+        RunPost {
+            content: Some("<@1>".to_string()),
+            embeds: vec![EmbedBuilder::new()
+                .title("New PB arrived!")
+                .url("https://www.speedrun.com/alttp/run/abc")
+                .field("Runner", "someone", true)
+                .build()],
+            components: vec![],
+            allowed_mentions: AllowedMentions {
+                users: vec![Id::new(1)],
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            "[dry run] post message 3\n  <@1>\n  # New PB arrived! <https://www.speedrun.com/alttp/run/abc>\n  Runner: someone\n  (would ping 1)",
+            render("post", Id::new(3), &post())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_jsonl_output() {
+        let path = test_path("dry-run.jsonl");
+        let notifier = DryRunNotifier::new(Some(&path)).unwrap();
+        let first = notifier.post(&post()).await.unwrap();
+        let second = notifier.post(&post()).await.unwrap();
+        assert_ne!(first, second);
+        notifier.edit(first, &post()).await.unwrap();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        let lines = written
+            .lines()
+            .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(3, lines.len());
+        assert_eq!("post", lines[0]["action"]);
+        assert_eq!("edit", lines[2]["action"]);
+        assert_ne!(lines[0]["message_id"], lines[1]["message_id"]);
+        assert_eq!(lines[0]["message_id"], lines[2]["message_id"]);
+        assert_eq!("New PB arrived!", lines[1]["embeds"][0]["title"]);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,329 @@
+// building embeds that discord will accept. discord rejects the whole message if anything in an
+// embed is over its limit, so rather than lose the message we cut things down to fit
+
+use chrono::{DateTime, Utc};
+use twilight_model::channel::embed::{Embed, EmbedField, EmbedFooter, EmbedThumbnail};
+use twilight_model::util::Timestamp;
+
+// https://discord.com/developers/docs/resources/channel#embed-object-embed-limits
+// these are all in characters, not bytes
+pub const TITLE_LIMIT: usize = 256;
+pub const DESCRIPTION_LIMIT: usize = 4096;
+pub const FIELD_COUNT_LIMIT: usize = 25;
+pub const FIELD_NAME_LIMIT: usize = 256;
+pub const FIELD_VALUE_LIMIT: usize = 1024;
+pub const FOOTER_LIMIT: usize = 2048;
+pub const AUTHOR_NAME_LIMIT: usize = 256;
+/// for everything above put together
+pub const TOTAL_LIMIT: usize = 6000;
+
+const ELLIPSIS: char = '\u{2026}';
+
+pub struct EmbedBuilder {
+    embed: Embed,
+}
+
+impl EmbedBuilder {
+    pub fn new() -> Self {
+        Self {
+            embed: Embed {
+                author: None,
+                color: None,
+                description: None,
+                fields: vec![],
+                footer: None,
+                image: None,
+                kind: "rich".to_string(),
+                provider: None,
+                thumbnail: None,
+                timestamp: None,
+                title: None,
+                url: None,
+                video: None,
+            },
+        }
+    }
+
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.embed.title = Some(title.into());
+        self
+    }
+
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.embed.url = Some(url.into());
+        self
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.embed.description = Some(description.into());
+        self
+    }
+
+    /// 0xRRGGBB
+    pub fn colour(mut self, colour: u32) -> Self {
+        self.embed.color = Some(colour);
+        self
+    }
+
+    /// discord shows this in the viewer's timezone
+    pub fn timestamp(mut self, timestamp: DateTime<Utc>) -> Self {
+        self.embed.timestamp = Timestamp::from_secs(timestamp.timestamp()).ok();
+        self
+    }
+
+    pub fn footer(mut self, text: impl Into<String>) -> Self {
+        self.embed.footer = Some(EmbedFooter {
+            icon_url: None,
+            proxy_icon_url: None,
+            text: text.into(),
+        });
+        self
+    }
+
+    pub fn thumbnail(mut self, url: impl Into<String>) -> Self {
+        self.embed.thumbnail = Some(EmbedThumbnail {
+            height: None,
+            proxy_url: None,
+            url: url.into(),
+            width: None,
+        });
+        self
+    }
+
+    pub fn field(
+        mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+        inline: bool,
+    ) -> Self {
+        self.embed.fields.push(EmbedField {
+            inline,
+            name: name.into(),
+            value: value.into(),
+        });
+        self
+    }
+
+    /// removes every field called `name`, e.g. to replace it with an up to date one
+    pub fn remove_field(mut self, name: &str) -> Self {
+        self.embed.fields.retain(|f| f.name != name);
+        self
+    }
+
+    /// the finished embed, cut down to fit within discord's limits if it had to be
+    pub fn build(self) -> Embed {
+        let mut embed = self.embed;
+        truncate_opt(&mut embed.title, TITLE_LIMIT);
+        truncate_opt(&mut embed.description, DESCRIPTION_LIMIT);
+        if let Some(footer) = &mut embed.footer {
+            truncate_in_place(&mut footer.text, FOOTER_LIMIT);
+        }
+        if let Some(author) = &mut embed.author {
+            truncate_in_place(&mut author.name, AUTHOR_NAME_LIMIT);
+        }
+        embed.fields.truncate(FIELD_COUNT_LIMIT);
+        for field in &mut embed.fields {
+            truncate_in_place(&mut field.name, FIELD_NAME_LIMIT);
+            truncate_in_place(&mut field.value, FIELD_VALUE_LIMIT);
+            // discord won't take empty fields either
+            if field.name.is_empty() {
+                field.name = "\u{200b}".to_string();
+            }
+            if field.value.is_empty() {
+                field.value = "\u{200b}".to_string();
+            }
+        }
+
+        // if it's still too long overall, take it out of the description first, then the fields
+        // from the bottom up, since that's the least important stuff in all of our embeds
+        let mut excess = total_length(&embed).saturating_sub(TOTAL_LIMIT);
+        if let Some(description) = &mut embed.description {
+            excess = shrink(description, excess);
+        }
+        for field in embed.fields.iter_mut().rev() {
+            excess = shrink(&mut field.value, excess);
+        }
+        // that can still leave 25 full length field names, so drop whole fields until it fits.
+        // with no fields left, everything else together is well under the limit
+        while excess > 0 {
+            match embed.fields.pop() {
+                Some(field) => excess = excess.saturating_sub(field_length(&field)),
+                None => break,
+            }
+        }
+        embed
+    }
+}
+
+impl Default for EmbedBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<Embed> for EmbedBuilder {
+    /// for editing an embed we've already posted
+    fn from(embed: Embed) -> Self {
+        Self { embed }
+    }
+}
+
+/// everything in `embed` that counts towards `TOTAL_LIMIT`
+pub fn total_length(embed: &Embed) -> usize {
+    let len = |s: &str| s.chars().count();
+    embed.title.as_deref().map_or(0, len)
+        + embed.description.as_deref().map_or(0, len)
+        + embed.fields.iter().map(field_length).sum::<usize>()
+        + embed.footer.as_ref().map_or(0, |f| len(&f.text))
+        + embed.author.as_ref().map_or(0, |a| len(&a.name))
+}
+
+fn field_length(field: &EmbedField) -> usize {
+    field.name.chars().count() + field.value.chars().count()
+}
+
+/// cuts `s` down to at most `max` characters, ending in an ellipsis if anything was cut off.
+/// never splits a character
+pub fn truncate(s: &str, max: usize) -> String {
+    if s.chars().count() <= max {
+        return s.to_string();
+    }
+    let mut truncated = s.chars().take(max.saturating_sub(1)).collect::<String>();
+    if max > 0 {
+        truncated.push(ELLIPSIS);
+    }
+    truncated
+}
+
+/// `items`, comma separated, with as many as will fit in `max` characters followed by a count
+/// of the rest. e.g. "a, b, c and 4 more"
+pub fn truncate_list(items: &[&str], max: usize) -> String {
+    let all = items.join(", ");
+    if all.chars().count() <= max {
+        return all;
+    }
+    for shown in (1..items.len()).rev() {
+        let listed = format!(
+            "{} and {} more",
+            items[..shown].join(", "),
+            items.len() - shown
+        );
+        if listed.chars().count() <= max {
+            return listed;
+        }
+    }
+    // even one item is too long; at least show some of it
+    truncate(&all, max)
+}
+
+fn truncate_in_place(s: &mut String, max: usize) {
+    if s.chars().count() > max {
+        *s = truncate(s, max);
+    }
+}
+
+fn truncate_opt(s: &mut Option<String>, max: usize) {
+    if let Some(s) = s {
+        truncate_in_place(s, max);
+    }
+}
+
+/// takes up to `excess` characters off the end of `s` (leaving at least one), and returns how
+/// many still need taking off elsewhere
+fn shrink(s: &mut String, excess: usize) -> usize {
+    if excess == 0 {
+        return 0;
+    }
+    let len = s.chars().count();
+    let keep = len.saturating_sub(excess).max(1).min(len);
+    if keep < len {
+        *s = truncate(s, keep);
+    }
+    excess - (len - keep)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::embeds::{
+        total_length, truncate, truncate_list, EmbedBuilder, DESCRIPTION_LIMIT, FIELD_COUNT_LIMIT,
+        FIELD_NAME_LIMIT, FIELD_VALUE_LIMIT, TITLE_LIMIT, TOTAL_LIMIT,
+    };
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!("short", truncate("short", 5));
+        assert_eq!("shor\u{2026}", truncate("shorter", 5));
+        // multi-byte characters count as one, and don't get cut in half
+        assert_eq!(
+            "\u{1f3c6}\u{1f3c6}\u{2026}",
+            truncate("\u{1f3c6}\u{1f3c6}\u{1f3c6}\u{1f3c6}", 3)
+        );
+    }
+
+    #[test]
+    fn test_truncate_list() {
+        let runners = ["first", "second", "third", "fourth"];
+        assert_eq!("first, second, third, fourth", truncate_list(&runners, 100));
+        assert_eq!("first, second and 2 more", truncate_list(&runners, 25));
+        assert_eq!("fir\u{2026}", truncate_list(&["first runner"], 4));
+        assert_eq!("", truncate_list(&[], 10));
+    }
+
+    #[test]
+    fn test_build_within_limits() {
+        let embed = EmbedBuilder::new()
+            .title("a".repeat(TITLE_LIMIT + 10))
+            .field("Reason", "", false)
+            .field("Decision", "b".repeat(FIELD_VALUE_LIMIT * 2), false)
+            .colour(0xffd700)
+            .footer("footer")
+            .build();
+        assert_eq!(TITLE_LIMIT, embed.title.as_ref().unwrap().chars().count());
+        assert!(embed.title.unwrap().ends_with('\u{2026}'));
+        assert_eq!("\u{200b}", embed.fields[0].value);
+        assert_eq!(FIELD_VALUE_LIMIT, embed.fields[1].value.chars().count());
+        assert_eq!(Some(0xffd700), embed.color);
+        assert_eq!("footer", embed.footer.unwrap().text);
+    }
+
+    #[test]
+    fn test_build_total_limit() {
+        let mut builder = EmbedBuilder::new().description("d".repeat(DESCRIPTION_LIMIT));
+        for i in 0..FIELD_COUNT_LIMIT + 5 {
+            builder = builder.field(format!("field {}", i), "v".repeat(FIELD_VALUE_LIMIT), true);
+        }
+        let embed = builder.build();
+        assert_eq!(FIELD_COUNT_LIMIT, embed.fields.len());
+        assert!(total_length(&embed) <= TOTAL_LIMIT);
+        // the first fields are left alone
+        assert_eq!(FIELD_VALUE_LIMIT, embed.fields[0].value.chars().count());
+    }
+
+    #[test]
+    fn test_build_long_field_names() {
+        let mut builder = EmbedBuilder::new().title("t".repeat(TITLE_LIMIT));
+        for i in 0..FIELD_COUNT_LIMIT {
+            builder = builder.field(format!("{}", i).repeat(FIELD_NAME_LIMIT), "v", false);
+        }
+        let embed = builder.build();
+        assert!(total_length(&embed) <= TOTAL_LIMIT);
+        // it's the last fields that go
+        assert!(embed.fields.len() < FIELD_COUNT_LIMIT);
+        assert!(embed.fields[0].name.starts_with('0'));
+        assert_eq!(TITLE_LIMIT, embed.title.unwrap().chars().count());
+    }
+
+    #[test]
+    fn test_remove_field() {
+        let embed = EmbedBuilder::new()
+            .field("Runner", "somebody", true)
+            .field("Claimed by", "<@1>", false)
+            .build();
+        let edited = EmbedBuilder::from(embed)
+            .remove_field("Claimed by")
+            .field("Claimed by", "<@2>", false)
+            .build();
+        assert_eq!(2, edited.fields.len());
+        assert_eq!("<@2>", edited.fields[1].value);
+    }
+}
@@ -1,45 +1,130 @@
 use crate::discord_client::DiscordError;
 use crate::src::SRCError;
 use diesel::result::Error;
+use diesel::ConnectionError;
 use std::env::VarError;
 use std::num::ParseIntError;
+use std::time::Duration;
 
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 pub enum BotError {
-    VariableMissing(VarError),
-    VariableParseError(ParseIntError),
-    DatabaseError(Error),
-    SRCError(SRCError),
-    DiscordError(DiscordError),
+    #[error("Missing environment variable: {0}")]
+    VariableMissing(#[from] VarError),
+    #[error("Unable to parse environment variable: {0}")]
+    VariableParseError(#[from] ParseIntError),
+    /// a setting is present but doesn't make sense
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] Error),
+    #[error("Unable to connect to the database: {0}")]
+    DatabaseConnectionError(#[from] ConnectionError),
+    #[error("SRC error: {0}")]
+    SRCError(#[from] SRCError),
+    #[error("Discord error: {0}")]
+    DiscordError(#[from] DiscordError),
+    #[error("Invalid state: {0}")]
     InvalidState(String),
 }
 
-impl From<VarError> for BotError {
-    fn from(ve: VarError) -> Self {
-        Self::VariableMissing(ve)
-    }
+/// what should be done about an error
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorClass {
+    /// might work if we try again later. `retry_after` is set when we've been told how long to
+    /// wait (i.e. we were rate limited)
+    Retryable { retry_after: Option<Duration> },
+    /// trying again won't help
+    Permanent,
+    /// we've been set up wrong, and nothing's going to work until someone fixes it
+    Configuration,
 }
 
-impl From<ParseIntError> for BotError {
-    fn from(pie: ParseIntError) -> Self {
-        Self::VariableParseError(pie)
+impl BotError {
+    /// which kind of error this is, for grouping similar errors together
+    pub fn variant_name(&self) -> &'static str {
+        match self {
+            BotError::VariableMissing(_) => "VariableMissing",
+            BotError::VariableParseError(_) => "VariableParseError",
+            BotError::InvalidConfig(_) => "InvalidConfig",
+            BotError::DatabaseError(_) => "DatabaseError",
+            BotError::DatabaseConnectionError(_) => "DatabaseConnectionError",
+            BotError::SRCError(_) => "SRCError",
+            BotError::DiscordError(_) => "DiscordError",
+            BotError::InvalidState(_) => "InvalidState",
+        }
     }
-}
 
-impl From<Error> for BotError {
-    fn from(e: Error) -> Self {
-        Self::DatabaseError(e)
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            BotError::VariableMissing(_)
+            | BotError::VariableParseError(_)
+            | BotError::InvalidConfig(_)
+            | BotError::DatabaseConnectionError(_) => ErrorClass::Configuration,
+            BotError::SRCError(e) => e.class(),
+            BotError::DiscordError(e) => e.class(),
+            // another connection (e.g. the gateway's) held the lock for longer than busy_timeout
+            BotError::DatabaseError(Error::DatabaseError(_, info))
+                if info.message().contains("database is locked") =>
+            {
+                ErrorClass::Retryable { retry_after: None }
+            }
+            BotError::DatabaseError(_) | BotError::InvalidState(_) => ErrorClass::Permanent,
+        }
     }
 }
 
-impl From<SRCError> for BotError {
-    fn from(e: SRCError) -> Self {
-        Self::SRCError(e)
+#[cfg(test)]
+mod tests {
+    use crate::discord_client::DiscordError;
+    use crate::error::{BotError, ErrorClass};
+    use crate::src::SRCError;
+    use diesel::result::{DatabaseErrorKind, Error};
+    use std::env::VarError;
+
+    #[test]
+    fn test_class() {
+        let refused = BotError::SRCError(SRCError::StatusUpdateRefused {
+            run_id: "abc123".to_string(),
+            status: 403,
+            message: "You are not a moderator of this game.".to_string(),
+        });
+        assert_eq!(ErrorClass::Configuration, refused.class());
+        let unavailable = BotError::SRCError(SRCError::UnexpectedResponse {
+            endpoint: "leaderboards/9d3rr0dl/category/9d8jgv7k".to_string(),
+            status: 503,
+            body: "".to_string(),
+        });
+        assert_eq!(
+            ErrorClass::Retryable { retry_after: None },
+            unavailable.class()
+        );
+        let too_long = BotError::DiscordError(DiscordError::ValidationError("title".to_string()));
+        assert_eq!(ErrorClass::Permanent, too_long.class());
+        assert_eq!(
+            ErrorClass::Configuration,
+            BotError::VariableMissing(VarError::NotPresent).class()
+        );
+        let busy = BotError::DatabaseError(Error::DatabaseError(
+            DatabaseErrorKind::Unknown,
+            Box::new("database is locked".to_string()),
+        ));
+        assert_eq!(ErrorClass::Retryable { retry_after: None }, busy.class());
+        assert_eq!(
+            ErrorClass::Permanent,
+            BotError::DatabaseError(Error::NotFound).class()
+        );
     }
-}
 
-impl From<DiscordError> for BotError {
-    fn from(e: DiscordError) -> Self {
-        Self::DiscordError(e)
+    #[test]
+    fn test_display() {
+        let error = BotError::SRCError(SRCError::StatusUpdateRefused {
+            run_id: "abc123".to_string(),
+            status: 403,
+            message: "You are not a moderator of this game.".to_string(),
+        });
+        assert_eq!(
+            "SRC error: SRC refused to update run abc123 (403): You are not a moderator of this game.",
+            error.to_string()
+        );
     }
 }
@@ -0,0 +1,477 @@
+use crate::error::BotError;
+use crate::models::pending_events::{NewPendingEvent, PendingEvent};
+use crate::models::runs::Run;
+use crate::shutdown::IN_FLIGHT;
+use crate::src::{CategoriesRepository, SRCRun};
+use crate::utils::{format_hms, timestamp_now};
+use crate::{get_conn, schema};
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+use hmac::{Hmac, Mac};
+use log::{debug, info, warn};
+use serde::Serialize;
+use sha2::Sha256;
+use std::env;
+use std::env::VarError;
+use std::sync::Arc;
+use std::time::Duration;
+use url::Url;
+
+/// the body is signed with `EVENT_SECRET`, so receivers can tell it came from us
+pub const SIGNATURE_HEADER: &str = "x-signature-256";
+/// how many times we'll try to deliver an event to an endpoint before giving up on it
+const MAX_ATTEMPTS: u32 = 5;
+/// backoff before the first retry; doubles on each subsequent one
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    RunSubmitted,
+    RunVerified,
+    RunRejected,
+}
+
+/// what we send to other tools when something happens in the queue. this has the same
+/// information as our run posts
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueEvent {
+    pub event: EventKind,
+    pub run_id: String,
+    pub runner: Option<String>,
+    pub category: Option<String>,
+    /// formatted like our posts, e.g. 1:23:45
+    pub time: Option<String>,
+    pub time_secs: Option<f64>,
+    pub weblink: Option<String>,
+    /// when we noticed
+    pub timestamp: String,
+}
+
+impl QueueEvent {
+    pub fn from_src_run(
+        event: EventKind,
+        run: &SRCRun<'_>,
+        categories: &CategoriesRepository<'_>,
+    ) -> Self {
+        Self {
+            event,
+            run_id: run.id.to_string(),
+            runner: run.player().map(ToString::to_string),
+            category: categories.category_name_from_run(run),
+            time: Some(format_hms(run.times.primary_t)),
+            time_secs: Some(run.times.primary_t),
+            weblink: Some(run.weblink.to_string()),
+            timestamp: timestamp_now(),
+        }
+    }
+
+    pub fn from_db_run(event: EventKind, run: &Run, categories: &CategoriesRepository<'_>) -> Self {
+        Self {
+            event,
+            run_id: run.run_id.clone(),
+            runner: run.runner_name.clone(),
+            category: categories.category_name_from_db_run(run),
+            time: run.primary_t.map(format_hms),
+            time_secs: run.primary_t,
+            weblink: run.weblink.clone(),
+            timestamp: timestamp_now(),
+        }
+    }
+}
+
+/// where to send events, and what to sign them with
+pub struct EventConfig {
+    pub endpoints: Vec<Url>,
+    pub secret: String,
+}
+
+impl EventConfig {
+    /// returns `None` if events aren't configured (i.e. `EVENT_ENDPOINTS` is unset). endpoints
+    /// are comma-separated
+    pub fn from_env() -> Result<Option<Self>, BotError> {
+        let endpoints = match env::var("EVENT_ENDPOINTS") {
+            Ok(e) => e
+                .split(',')
+                .map(str::trim)
+                .filter(|e| !e.is_empty())
+                .map(|e| {
+                    Url::parse(e).map_err(|err| {
+                        BotError::InvalidConfig(format!("Invalid event endpoint {}: {}", e, err))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            Err(VarError::NotPresent) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let secret = env::var("EVENT_SECRET")?;
+        Ok(Some(Self { endpoints, secret }))
+    }
+}
+
+/// sends events to every configured endpoint in the background, so that a slow or dead
+/// endpoint doesn't hold up the poll loop. every event is saved before we start sending it and
+/// deleted once it's delivered (or refused), so that neither stopping partway through nor an
+/// endpoint being down for a while loses any. those get sent again the next time we start
+pub struct EventEmitter {
+    client: reqwest::Client,
+    config: Arc<EventConfig>,
+    initial_backoff: Duration,
+    /// each delivery opens its own connection to clear its event once it's done
+    database_url: String,
+}
+
+impl EventEmitter {
+    pub fn new(config: EventConfig, database_url: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            config: Arc::new(config),
+            initial_backoff: INITIAL_BACKOFF,
+            database_url: database_url.to_string(),
+        }
+    }
+
+    pub fn emit(&self, event: &QueueEvent, conn: &mut SqliteConnection) {
+        let body = match serde_json::to_string(event) {
+            Ok(b) => b,
+            Err(e) => {
+                warn!("Unable to serialize event {:?}: {}", event, e);
+                return;
+            }
+        };
+        for endpoint in &self.config.endpoints {
+            let saved = diesel::insert_into(schema::pending_events::table)
+                .values(NewPendingEvent {
+                    endpoint: endpoint.as_str(),
+                    body: &body,
+                    created_at: timestamp_now(),
+                })
+                .returning(schema::pending_events::id)
+                .get_result::<i32>(conn);
+            // better to send it without a safety net than not at all
+            let pending_id = match saved {
+                Ok(id) => Some(id),
+                Err(e) => {
+                    warn!("Unable to save event for {}: {}", endpoint, e);
+                    None
+                }
+            };
+            self.send(endpoint.clone(), body.clone(), pending_id);
+        }
+    }
+
+    /// sends whatever we didn't finish sending last time we ran. anything for an endpoint that's
+    /// no longer configured is dropped
+    pub fn resend_pending(&self, conn: &mut SqliteConnection) -> Result<(), BotError> {
+        let pending = schema::pending_events::table
+            .order(schema::pending_events::id)
+            .load::<PendingEvent>(conn)?;
+        for event in pending {
+            match self
+                .config
+                .endpoints
+                .iter()
+                .find(|e| e.as_str() == event.endpoint)
+            {
+                Some(endpoint) => self.send(endpoint.clone(), event.body, Some(event.id)),
+                None => {
+                    info!(
+                        "Dropping an event for {}, which we don't send events to any more",
+                        event.endpoint
+                    );
+                    diesel::delete(schema::pending_events::table.find(event.id)).execute(conn)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// delivers `body` in the background, then clears `pending_id` if the endpoint got it or
+    /// doesn't want it. otherwise it's left for `resend_pending`
+    fn send(&self, endpoint: Url, body: String, pending_id: Option<i32>) {
+        let client = self.client.clone();
+        let signature = sign(&self.config.secret, body.as_bytes());
+        let backoff = self.initial_backoff;
+        let database_url = self.database_url.clone();
+        IN_FLIGHT.spawn(async move {
+            match deliver(&client, &endpoint, body.into_bytes(), &signature, backoff).await {
+                Ok(()) => {}
+                Err(e @ DeliveryError::Refused(_)) => {
+                    warn!("Dropping an event for {}: {}", endpoint, e);
+                }
+                Err(e @ DeliveryError::GaveUp { .. }) => {
+                    warn!(
+                        "Giving up on sending an event to {} until we restart: {}",
+                        endpoint, e
+                    );
+                    return;
+                }
+            }
+            if let Some(id) = pending_id {
+                if let Err(e) = clear_pending(&database_url, id) {
+                    warn!("Unable to clear sent event {}: {}", id, e);
+                }
+            }
+        });
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+enum DeliveryError {
+    /// the endpoint answered with a 4xx other than 429, so trying again won't help
+    #[error("Event refused with {0}")]
+    Refused(reqwest::StatusCode),
+    /// it might work later, just not now
+    #[error("Event not delivered after {attempts} attempts: {last_error}")]
+    GaveUp { attempts: u32, last_error: String },
+}
+
+/// forgets a saved event once we're done with it
+fn clear_pending(database_url: &str, id: i32) -> Result<(), BotError> {
+    let mut conn = get_conn(database_url)?;
+    diesel::delete(schema::pending_events::table.find(id)).execute(&mut conn)?;
+    Ok(())
+}
+
+/// `sha256=` followed by the hex HMAC-SHA256 of `body`
+fn sign(secret: &str, body: &[u8]) -> String {
+    // HMAC takes keys of any length, so this can't fail
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// POSTs `body` to `endpoint`, retrying with exponential backoff if it couldn't be reached or
+/// had a problem on its end. a 4xx other than 429 means it doesn't want the event, so we don't
+/// retry those
+async fn deliver(
+    client: &reqwest::Client,
+    endpoint: &Url,
+    body: Vec<u8>,
+    signature: &str,
+    initial_backoff: Duration,
+) -> Result<(), DeliveryError> {
+    let mut backoff = initial_backoff;
+    let mut attempt = 1;
+    loop {
+        let result = client
+            .post(endpoint.clone())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, signature)
+            .body(body.clone())
+            .send()
+            .await;
+        let error = match result {
+            Ok(resp) if resp.status().is_success() => return Ok(()),
+            Ok(resp) if resp.status().is_client_error() && resp.status().as_u16() != 429 => {
+                return Err(DeliveryError::Refused(resp.status()));
+            }
+            Ok(resp) => format!("status {}", resp.status()),
+            Err(e) => e.to_string(),
+        };
+        if attempt >= MAX_ATTEMPTS {
+            return Err(DeliveryError::GaveUp {
+                attempts: attempt,
+                last_error: error,
+            });
+        }
+        debug!(
+            "Event delivery to {} failed ({}); retrying in {:?}",
+            endpoint, error, backoff
+        );
+        tokio::time::sleep(backoff).await;
+        backoff *= 2;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::events::{
+        deliver, sign, DeliveryError, EventConfig, EventEmitter, EventKind, QueueEvent,
+    };
+    use crate::get_conn;
+    use crate::mock_server::{response, MockServer};
+    use crate::models::pending_events::NewPendingEvent;
+    use crate::schema;
+    use crate::shutdown::IN_FLIGHT;
+    use crate::test_path;
+    use diesel::prelude::*;
+    use diesel_migrations::{FileBasedMigrations, MigrationHarness};
+    use hmac::{Hmac, Mac};
+    use hyper::{Body, StatusCode};
+    use sha2::Sha256;
+    use std::time::Duration;
+
+    /// stands in for an endpoint that wants our events. answers the nth request with
+    /// `statuses[n]`, or 200 once it runs out
+    fn mock_endpoint(statuses: Vec<StatusCode>) -> MockServer {
+        MockServer::start(move |_, n| {
+            let status = statuses.get(n).copied().unwrap_or(StatusCode::OK);
+            response(status, Body::empty())
+        })
+    }
+
+    #[test]
+    fn test_sign() {
+        let body = br#"{"event":"run_verified"}"#;
+        let signature = sign("hunter2", body);
+        let hex_sig = signature.strip_prefix("sha256=").unwrap();
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"hunter2").unwrap();
+        mac.update(body);
+        assert!(mac.verify_slice(&hex::decode(hex_sig).unwrap()).is_ok());
+        assert_ne!(signature, sign("hunter3", body));
+    }
+
+    #[tokio::test]
+    async fn test_deliver_retries() {
+        let endpoint = mock_endpoint(vec![
+            StatusCode::INTERNAL_SERVER_ERROR,
+            StatusCode::TOO_MANY_REQUESTS,
+        ]);
+        deliver(
+            &reqwest::Client::new(),
+            &endpoint.url("/events"),
+            b"{}".to_vec(),
+            "sha256=abc",
+            Duration::from_millis(1),
+        )
+        .await
+        .unwrap();
+        let seen = endpoint.seen();
+        assert_eq!(3, seen.len());
+        assert!(seen
+            .iter()
+            .all(|r| r.header("x-signature-256") == Some("sha256=abc")));
+    }
+
+    #[tokio::test]
+    async fn test_deliver_refused() {
+        let endpoint = mock_endpoint(vec![StatusCode::BAD_REQUEST]);
+        let result = deliver(
+            &reqwest::Client::new(),
+            &endpoint.url("/events"),
+            b"{}".to_vec(),
+            "sha256=abc",
+            Duration::from_millis(1),
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(DeliveryError::Refused(StatusCode::BAD_REQUEST))
+        ));
+        assert_eq!(1, endpoint.seen().len());
+    }
+
+    #[tokio::test]
+    async fn test_deliver_gives_up() {
+        let endpoint = mock_endpoint(vec![StatusCode::BAD_GATEWAY; 10]);
+        let result = deliver(
+            &reqwest::Client::new(),
+            &endpoint.url("/events"),
+            b"{}".to_vec(),
+            "sha256=abc",
+            Duration::from_millis(1),
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(DeliveryError::GaveUp { attempts: 5, .. })
+        ));
+        assert_eq!(5, endpoint.seen().len());
+    }
+
+    #[tokio::test]
+    async fn test_resend_pending() {
+        // deliveries clear their events through their own connection, so this can't be in memory
+        let path = test_path("events.db3");
+        let database_url = path.to_str().unwrap();
+        let mut conn = get_conn(database_url).unwrap();
+        conn.run_pending_migrations(FileBasedMigrations::find_migrations_directory().unwrap())
+            .unwrap();
+        let endpoint = mock_endpoint(vec![]);
+        let url = endpoint.url("/events");
+        for pending_for in [url.as_str(), "http://127.0.0.1:1/removed"] {
+            diesel::insert_into(schema::pending_events::table)
+                .values(NewPendingEvent {
+                    endpoint: pending_for,
+                    body: r#"{"event":"run_verified"}"#,
+                    created_at: "2022-09-01T12:00:00Z".to_string(),
+                })
+                .execute(&mut conn)
+                .unwrap();
+        }
+        let emitter = EventEmitter::new(
+            EventConfig {
+                endpoints: vec![url],
+                secret: "hunter2".to_string(),
+            },
+            database_url,
+        );
+
+        emitter.resend_pending(&mut conn).unwrap();
+        IN_FLIGHT.wait().await;
+        {
+            let seen = endpoint.seen();
+            assert_eq!(1, seen.len());
+            assert_eq!(
+                Some(serde_json::json!({"event": "run_verified"})),
+                seen[0].body
+            );
+            assert_eq!(
+                Some(sign("hunter2", br#"{"event":"run_verified"}"#).as_str()),
+                seen[0].header("x-signature-256")
+            );
+        }
+        let left = schema::pending_events::table
+            .count()
+            .get_result::<i64>(&mut conn)
+            .unwrap();
+        assert_eq!(0, left);
+        drop(conn);
+        std::fs::remove_file(&path).unwrap();
+    }
+    #[tokio::test]
+    async fn test_keeps_undelivered_events() {
+        let path = test_path("undelivered.db3");
+        let database_url = path.to_str().unwrap();
+        let mut conn = get_conn(database_url).unwrap();
+        conn.run_pending_migrations(FileBasedMigrations::find_migrations_directory().unwrap())
+            .unwrap();
+        let refuses = mock_endpoint(vec![StatusCode::GONE]);
+        let down = mock_endpoint(vec![StatusCode::BAD_GATEWAY; 10]);
+        let emitter = EventEmitter {
+            initial_backoff: Duration::from_millis(1),
+            ..EventEmitter::new(
+                EventConfig {
+                    endpoints: vec![refuses.url("/events"), down.url("/events")],
+                    secret: "hunter2".to_string(),
+                },
+                database_url,
+            )
+        };
+
+        let event = QueueEvent {
+            event: EventKind::RunVerified,
+            run_id: "abc123".to_string(),
+            runner: None,
+            category: None,
+            time: None,
+            time_secs: None,
+            weblink: None,
+            timestamp: "2022-09-01T12:00:00Z".to_string(),
+        };
+        emitter.emit(&event, &mut conn);
+        IN_FLIGHT.wait().await;
+        assert_eq!(1, refuses.seen().len());
+        assert_eq!(5, down.seen().len());
+        // only the one that might still go through later is kept
+        let left = schema::pending_events::table
+            .select(schema::pending_events::endpoint)
+            .load::<String>(&mut conn)
+            .unwrap();
+        assert_eq!(vec![down.url("/events").to_string()], left);
+        drop(conn);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
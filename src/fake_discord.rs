@@ -0,0 +1,265 @@
+// an in-process stand-in for the parts of discord's REST API that we use, for tests. point a
+// `BotDiscordClient` at it with `FakeDiscord::client`
+
+use crate::discord_client::BotDiscordClient;
+use crate::mock_server::{MockServer, SeenRequest};
+use hyper::{Body, Method, Response, StatusCode};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use twilight_model::id::marker::ChannelMarker;
+use twilight_model::id::Id;
+use url::Url;
+
+/// a request the fake was sent
+#[derive(Debug, Clone)]
+pub struct FakeRequest {
+    pub method: Method,
+    /// without the `/api/v10` prefix, e.g. `/channels/123/messages`
+    pub path: String,
+    pub body: Option<serde_json::Value>,
+}
+
+/// something other than the usual success to answer a request with
+#[derive(Debug, Clone)]
+pub enum FakeResponse {
+    /// a 429, with the headers and body discord sends along with one
+    RateLimited {
+        retry_after: f64,
+        global: bool,
+    },
+    NotFound,
+    ServerError(StatusCode),
+    /// the usual success, but telling us we've used up the rate limit bucket
+    LastInBucket {
+        reset_after: f64,
+    },
+    /// the usual success, but with only the id of the message we posted, rather than everything
+    /// twilight expects to find in one
+    BareMessage,
+}
+
+#[derive(Default)]
+struct FakeState {
+    requests: Vec<FakeRequest>,
+    /// answers for the next requests, in order, before we go back to succeeding
+    script: VecDeque<FakeResponse>,
+    next_id: u64,
+}
+
+impl FakeState {
+    /// ids for things we create. discord would never give out ids this small
+    fn next_id(&mut self) -> String {
+        self.next_id += 1;
+        (1000 + self.next_id).to_string()
+    }
+}
+
+pub struct FakeDiscord {
+    server: MockServer,
+    state: Arc<Mutex<FakeState>>,
+}
+
+impl FakeDiscord {
+    /// starts listening on a random local port. has to be called from within a tokio runtime
+    pub async fn start() -> Self {
+        let state = Arc::new(Mutex::new(FakeState::default()));
+        let server_state = state.clone();
+        let server = MockServer::start(move |req, _| handle(req, &server_state));
+        Self { server, state }
+    }
+
+    pub fn base_url(&self) -> Url {
+        self.server.url("")
+    }
+
+    /// a client that posts to `channel_id` on this fake
+    pub fn client(&self, channel_id: Id<ChannelMarker>) -> BotDiscordClient {
+        BotDiscordClient::new_with_base_url(
+            "fake-token".to_string(),
+            Id::new(1),
+            channel_id,
+            &self.base_url(),
+        )
+        .expect("Unable to create a client for fake discord")
+    }
+
+    /// answers the next request with `response`. calling this repeatedly queues them up
+    pub fn script(&self, response: FakeResponse) {
+        self.state.lock().unwrap().script.push_back(response);
+    }
+
+    /// everything we've been sent so far, oldest first
+    pub fn requests(&self) -> Vec<FakeRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+fn handle(req: &SeenRequest, state: &Mutex<FakeState>) -> Response<Body> {
+    let method = req.method.clone();
+    // twilight puts the api version in front of everything
+    let path = req
+        .path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .skip_while(|s| *s == "api" || s.starts_with('v'))
+        .collect::<Vec<_>>()
+        .join("/");
+    let body = req.body.clone();
+
+    let mut state = state.lock().unwrap();
+    state.requests.push(FakeRequest {
+        method: method.clone(),
+        path: format!("/{}", path),
+        body: body.clone(),
+    });
+    let mut remaining = 4.0;
+    let mut reset_after = 1.0;
+    let mut bare = false;
+    match state.script.pop_front() {
+        Some(FakeResponse::RateLimited {
+            retry_after,
+            global,
+        }) => {
+            let mut resp = json_response(
+                StatusCode::TOO_MANY_REQUESTS,
+                serde_json::json!({
+                    "message": "You are being rate limited.",
+                    "retry_after": retry_after,
+                    "global": global,
+                }),
+                0.0,
+                retry_after,
+            );
+            let headers = resp.headers_mut();
+            headers.insert("retry-after", header_value(retry_after.ceil()));
+            if global {
+                headers.insert("x-ratelimit-global", "true".parse().unwrap());
+            }
+            return resp;
+        }
+        Some(FakeResponse::NotFound) => {
+            return json_response(
+                StatusCode::NOT_FOUND,
+                serde_json::json!({"message": "Unknown Message", "code": 10008}),
+                remaining,
+                reset_after,
+            )
+        }
+        Some(FakeResponse::ServerError(status)) => {
+            return json_response(
+                status,
+                serde_json::json!({"message": "Internal Server Error", "code": 0}),
+                remaining,
+                reset_after,
+            )
+        }
+        Some(FakeResponse::LastInBucket { reset_after: r }) => {
+            remaining = 0.0;
+            reset_after = r;
+        }
+        Some(FakeResponse::BareMessage) => bare = true,
+        None => {}
+    }
+
+    let segments = path.split('/').collect::<Vec<_>>();
+    let content = body
+        .as_ref()
+        .and_then(|b| b.get("content"))
+        .cloned()
+        .unwrap_or_else(|| serde_json::json!(""));
+    let ok = |json| json_response(StatusCode::OK, json, remaining, reset_after);
+    match (&method, segments.as_slice()) {
+        (&Method::POST, ["channels", channel_id, "messages"]) => {
+            let id = state.next_id();
+            if bare {
+                return ok(serde_json::json!({ "id": id }));
+            }
+            ok(message(&id, channel_id, content))
+        }
+        (&Method::PATCH, ["channels", channel_id, "messages", message_id]) => {
+            ok(message(message_id, channel_id, content))
+        }
+        (&Method::DELETE, ["channels", _, "messages", _])
+        | (&Method::POST, ["interactions", _, _, "callback"]) => Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Body::empty())
+            .unwrap(),
+        (&Method::POST, ["channels", channel_id, "messages", _, "threads"])
+        | (&Method::POST, ["channels", channel_id, "threads"]) => {
+            let id = state.next_id();
+            ok(serde_json::json!({
+                "id": id,
+                "type": 11,
+                "parent_id": channel_id,
+                "name": body.as_ref().and_then(|b| b.get("name")).cloned(),
+            }))
+        }
+        (&Method::GET, ["channels", channel_id]) => {
+            ok(serde_json::json!({"id": channel_id, "type": 0, "name": "queue"}))
+        }
+        (&Method::POST, ["users", "@me", "channels"]) => {
+            let id = state.next_id();
+            ok(serde_json::json!({"id": id, "type": 1}))
+        }
+        (&Method::PUT, ["applications", _, "commands"]) => ok(body.unwrap_or_default()),
+        _ => json_response(
+            StatusCode::NOT_FOUND,
+            serde_json::json!({"message": "404: Not Found", "code": 0}),
+            remaining,
+            reset_after,
+        ),
+    }
+}
+
+fn header_value(v: f64) -> hyper::header::HeaderValue {
+    // numbers are always valid header values
+    v.to_string().parse().unwrap()
+}
+
+fn json_response(
+    status: StatusCode,
+    json: serde_json::Value,
+    remaining: f64,
+    reset_after: f64,
+) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .header("x-ratelimit-bucket", "fake-bucket")
+        .header("x-ratelimit-limit", "5")
+        .header("x-ratelimit-remaining", header_value(remaining))
+        .header("x-ratelimit-reset-after", header_value(reset_after))
+        .body(Body::from(json.to_string()))
+        .unwrap()
+}
+
+/// just enough of a message for twilight to deserialize it
+fn message(id: &str, channel_id: &str, content: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({
+        "id": id,
+        "channel_id": channel_id,
+        "author": {
+            "id": "1",
+            "username": "queue bot",
+            "discriminator": "0001",
+            "avatar": null,
+            "bot": true,
+        },
+        "content": content,
+        "timestamp": "2022-09-01T12:00:00.000000+00:00",
+        "edited_timestamp": null,
+        "tts": false,
+        "mention_everyone": false,
+        "mentions": [],
+        "mention_roles": [],
+        "mention_channels": [],
+        "attachments": [],
+        "embeds": [],
+        "components": [],
+        "reactions": [],
+        "sticker_items": [],
+        "pinned": false,
+        "type": 0,
+        "flags": 0,
+    })
+}
@@ -0,0 +1,90 @@
+use crate::discord_client::BotDiscordClient;
+use crate::error::BotError;
+use crate::get_conn;
+use crate::interactions::InteractionHandler;
+use crate::reactions::{record_reaction, remove_reaction, ReactionConfig};
+use crate::shutdown::stopped;
+use futures_util::StreamExt;
+use log::{info, warn};
+use std::env;
+use std::sync::Arc;
+use tokio::sync::watch;
+use twilight_gateway::{Event, Intents, Shard};
+use twilight_model::application::interaction::Interaction;
+
+/// holds a gateway connection open and handles whatever discord sends us over it, until `stop`
+/// says to disconnect. the event we're handling when that happens is finished first
+///
+/// interactions are only handled if `interactions` is set (they might be coming in over HTTP
+/// instead), and reactions only if `reactions` is.
+pub async fn run_gateway(
+    discord_client: Arc<BotDiscordClient>,
+    database_url: String,
+    interactions: Option<InteractionHandler>,
+    reactions: Option<ReactionConfig>,
+    stop: watch::Receiver<bool>,
+) -> Result<(), BotError> {
+    let token = env::var("BOT_TOKEN")?;
+    // the gateway gets its own connection so that it doesn't have to wait on the poll loop
+    let mut conn = get_conn(&database_url)?;
+    let intents = if reactions.is_some() {
+        Intents::GUILD_MESSAGE_REACTIONS
+    } else {
+        Intents::empty()
+    };
+    let (shard, mut events) = Shard::new(token, intents);
+    shard
+        .start()
+        .await
+        .map_err(|e| BotError::InvalidState(format!("Unable to start gateway: {}", e)))?;
+    info!("Gateway connected");
+    let stopped = stopped(stop);
+    tokio::pin!(stopped);
+    loop {
+        let event = tokio::select! {
+            _ = &mut stopped => {
+                info!("Disconnecting from the gateway");
+                shard.shutdown();
+                return Ok(());
+            }
+            event = events.next() => match event {
+                Some(e) => e,
+                None => break,
+            },
+        };
+        match (event, &interactions, &reactions) {
+            (Event::InteractionCreate(ic), Some(handler), _) => {
+                if let Err(e) = respond(&ic.0, handler, &discord_client, &mut conn).await {
+                    warn!("Error handling interaction {:?}: {}", ic.0, e);
+                }
+            }
+            (Event::ReactionAdd(ra), _, Some(config)) => {
+                if let Err(e) = record_reaction(config, &ra.0, &mut conn) {
+                    warn!("Error recording reaction {:?}: {}", ra.0, e);
+                }
+            }
+            (Event::ReactionRemove(rr), _, Some(_)) => {
+                if let Err(e) = remove_reaction(&rr.0, &mut conn) {
+                    warn!("Error removing reaction {:?}: {}", rr.0, e);
+                }
+            }
+            _ => {}
+        }
+    }
+    warn!("Gateway event stream ended");
+    Ok(())
+}
+
+async fn respond(
+    interaction: &Interaction,
+    handler: &InteractionHandler,
+    discord_client: &BotDiscordClient,
+    conn: &mut diesel::SqliteConnection,
+) -> Result<(), BotError> {
+    if let Some(response) = handler.handle(interaction, conn).await? {
+        discord_client
+            .create_interaction_response(interaction.id, &interaction.token, &response)
+            .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,613 @@
+use crate::claims::{apply_claim, parse_custom_id, with_claimant, ClaimAction, ClaimOutcome};
+use crate::decisions::{
+    modal_reason, parse_decision_custom_id, parse_reason_modal_id, reason_modal, DecisionAction,
+    DecisionConfig, Decisions,
+};
+use crate::discord_client::BotDiscordClient;
+use crate::error::BotError;
+use crate::links::{link_account, unlink_account};
+use crate::shutdown::IN_FLIGHT;
+use crate::src::{NewStatus, SRCClient};
+use crate::subscriptions::{subscribe, subscriptions_for, unsubscribe, SubscriptionKind};
+use crate::utils::format_timestamp;
+use crate::verifier_stats::{verifier_stats, verifier_stats_embed};
+use chrono::{Duration, Utc};
+use diesel::SqliteConnection;
+use log::info;
+use std::env;
+use std::env::VarError;
+use std::num::NonZeroU64;
+use std::sync::Arc;
+use twilight_model::application::command::{Command, CommandType};
+use twilight_model::application::interaction::application_command::{
+    CommandData, CommandOptionValue,
+};
+use twilight_model::application::interaction::{Interaction, InteractionData, InteractionType};
+use twilight_model::channel::message::MessageFlags;
+use twilight_model::http::interaction::{
+    InteractionResponse, InteractionResponseData, InteractionResponseType,
+};
+use twilight_model::id::marker::{RoleMarker, UserMarker};
+use twilight_model::id::Id;
+use twilight_model::user::User;
+use twilight_util::builder::command::{
+    BooleanBuilder, CommandBuilder, IntegerBuilder, StringBuilder, UserBuilder,
+};
+
+pub const VERIFIERS_COMMAND: &str = "verifiers";
+pub const SUBSCRIBE_COMMAND: &str = "subscribe";
+pub const UNSUBSCRIBE_COMMAND: &str = "unsubscribe";
+pub const SUBSCRIPTIONS_COMMAND: &str = "subscriptions";
+pub const LINK_COMMAND: &str = "link";
+pub const UNLINK_COMMAND: &str = "unlink";
+
+/// the most `/verifiers days` can be. a lot more than this and the date arithmetic overflows
+const MAX_VERIFIERS_DAYS: i64 = 3650;
+
+/// how we receive interactions (slash commands etc) from discord
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InteractionTransport {
+    /// over a gateway connection
+    Gateway,
+    /// as signed HTTP requests from discord, to the interactions endpoint URL set in the
+    /// developer portal
+    Http,
+}
+
+impl InteractionTransport {
+    /// returns `None` if we shouldn't listen for interactions at all (i.e. `INTERACTIONS` is
+    /// unset)
+    pub fn from_env() -> Result<Option<Self>, BotError> {
+        match env::var("INTERACTIONS") {
+            Ok(t) => match t.as_str() {
+                "gateway" => Ok(Some(Self::Gateway)),
+                "http" => Ok(Some(Self::Http)),
+                other => Err(BotError::InvalidConfig(format!(
+                    "Unknown interaction transport: {}",
+                    other
+                ))),
+            },
+            Err(VarError::NotPresent) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// every slash command the bot supports
+pub fn commands() -> Vec<Command> {
+    let runner = || StringBuilder::new("runner", "A runner's SRC name");
+    let category =
+        || StringBuilder::new("category", "A category, as it's shown in run announcements");
+    vec![
+        CommandBuilder::new(
+            VERIFIERS_COMMAND,
+            "How many runs each verifier has judged, and how long they waited",
+            CommandType::ChatInput,
+        )
+        .option(
+            IntegerBuilder::new(
+                "days",
+                "Only count runs judged in this many days (default: all time)",
+            )
+            .min_value(1)
+            .max_value(MAX_VERIFIERS_DAYS),
+        )
+        .build(),
+        CommandBuilder::new(
+            SUBSCRIBE_COMMAND,
+            "Get a DM when a runner (or a category) submits a run",
+            CommandType::ChatInput,
+        )
+        .option(runner())
+        .option(category())
+        .option(BooleanBuilder::new(
+            "verified",
+            "Also DM me when their runs are verified (default: no)",
+        ))
+        .build(),
+        CommandBuilder::new(
+            UNSUBSCRIBE_COMMAND,
+            "Stop getting DMs about a runner or category",
+            CommandType::ChatInput,
+        )
+        .option(runner())
+        .option(category())
+        .build(),
+        CommandBuilder::new(
+            SUBSCRIPTIONS_COMMAND,
+            "List the runners and categories you get DMs about",
+            CommandType::ChatInput,
+        )
+        .build(),
+        CommandBuilder::new(
+            LINK_COMMAND,
+            "Tell me your SRC account, so I can mention you when your runs show up",
+            CommandType::ChatInput,
+        )
+        .option(StringBuilder::new("src", "Your SRC username").required(true))
+        .build(),
+        CommandBuilder::new(
+            UNLINK_COMMAND,
+            "Stop being mentioned when your runs show up",
+            CommandType::ChatInput,
+        )
+        .option(UserBuilder::new(
+            "user",
+            "Verifiers only: unlink someone else, e.g. if they linked an account that isn't theirs",
+        ))
+        .build(),
+    ]
+}
+
+/// works out how to respond to interactions (slash commands, button presses, etc)
+pub struct InteractionHandler {
+    src_client: Arc<SRCClient>,
+    /// if set, only members with this role can claim runs (or approve/reject them)
+    verifier_role_id: Option<Id<RoleMarker>>,
+    /// `None` unless we're pushing decisions to SRC
+    decisions: Option<Arc<Decisions>>,
+}
+
+impl InteractionHandler {
+    pub fn new(
+        src_client: Arc<SRCClient>,
+        verifier_role_id: Option<Id<RoleMarker>>,
+        decisions: Option<Decisions>,
+    ) -> Self {
+        Self {
+            src_client,
+            verifier_role_id,
+            decisions: decisions.map(Arc::new),
+        }
+    }
+
+    pub fn new_from_env(
+        src_client: Arc<SRCClient>,
+        discord_client: Arc<BotDiscordClient>,
+        database_url: &str,
+    ) -> Result<Self, BotError> {
+        let verifier_role_id = match env::var("VERIFIER_ROLE_ID") {
+            Ok(r) => Some(Id::<RoleMarker>::from(r.parse::<NonZeroU64>()?)),
+            Err(VarError::NotPresent) => None,
+            Err(e) => return Err(e.into()),
+        };
+        let decisions = DecisionConfig::from_env()?.map(|c| {
+            Decisions::new(
+                c,
+                src_client.clone(),
+                discord_client,
+                database_url.to_string(),
+            )
+        });
+        // claiming is harmless enough to leave open to everyone, but this isn't
+        if decisions.is_some() && verifier_role_id.is_none() {
+            return Err(BotError::InvalidConfig(
+                "PUSH_DECISIONS_TO_SRC requires VERIFIER_ROLE_ID to be set".to_string(),
+            ));
+        }
+        Ok(Self::new(src_client, verifier_role_id, decisions))
+    }
+
+    /// returns `None` for interactions we don't handle, which discord will show to the user as
+    /// having failed
+    pub async fn handle(
+        &self,
+        interaction: &Interaction,
+        conn: &mut SqliteConnection,
+    ) -> Result<Option<InteractionResponse>, BotError> {
+        if interaction.kind == InteractionType::Ping {
+            return Ok(Some(InteractionResponse {
+                kind: InteractionResponseType::Pong,
+                data: None,
+            }));
+        }
+        match &interaction.data {
+            Some(InteractionData::ApplicationCommand(data)) => match data.name.as_str() {
+                VERIFIERS_COMMAND => verifiers_response(data, conn).map(Some),
+                SUBSCRIBE_COMMAND => subscribe_response(interaction, data, conn),
+                UNSUBSCRIBE_COMMAND => unsubscribe_response(interaction, data, conn),
+                SUBSCRIPTIONS_COMMAND => subscriptions_response(interaction, conn),
+                LINK_COMMAND => self.link_response(interaction, data, conn).await,
+                UNLINK_COMMAND => self.unlink_response(interaction, data, conn),
+                _ => Ok(None),
+            },
+            Some(InteractionData::MessageComponent(data)) => {
+                if let Some((action, run_id)) = parse_custom_id(&data.custom_id) {
+                    self.claim_response(interaction, action, run_id, conn)
+                } else if let Some((action, run_id)) = parse_decision_custom_id(&data.custom_id) {
+                    self.decision_response(interaction, action, run_id)
+                } else {
+                    Ok(None)
+                }
+            }
+            Some(InteractionData::ModalSubmit(data)) => {
+                match parse_reason_modal_id(&data.custom_id) {
+                    Some(run_id) => match modal_reason(data) {
+                        Some(reason) => {
+                            let status = NewStatus::Rejected {
+                                reason: reason.to_string(),
+                            };
+                            self.push_decision(interaction, run_id, status)
+                        }
+                        None => Ok(Some(ephemeral_response("You need to give a reason"))),
+                    },
+                    None => Ok(None),
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
+    async fn link_response(
+        &self,
+        interaction: &Interaction,
+        data: &CommandData,
+        conn: &mut SqliteConnection,
+    ) -> Result<Option<InteractionResponse>, BotError> {
+        let (user, _) = match interaction_user(interaction) {
+            Some(u) => u,
+            None => return Ok(None),
+        };
+        let src_name = match string_option(data, "src") {
+            Some(n) => n,
+            None => return Ok(Some(ephemeral_response("Which SRC account is yours?"))),
+        };
+        let message = link_account(&self.src_client, user.id, src_name, conn).await?;
+        Ok(Some(ephemeral_response(&message)))
+    }
+
+    fn unlink_response(
+        &self,
+        interaction: &Interaction,
+        data: &CommandData,
+        conn: &mut SqliteConnection,
+    ) -> Result<Option<InteractionResponse>, BotError> {
+        let (user, _) = match interaction_user(interaction) {
+            Some(u) => u,
+            None => return Ok(None),
+        };
+        let target = match user_option(data, "user") {
+            Some(other) if other != user.id => other,
+            _ => {
+                let message = match unlink_account(user.id, conn)? {
+                    Some(name) => format!("You're no longer linked to {} on SRC", name),
+                    None => "You hadn't linked an SRC account".to_string(),
+                };
+                return Ok(Some(ephemeral_response(&message)));
+            }
+        };
+        // without a verifier role everyone counts as a verifier, which is fine for claiming runs
+        // but not for this
+        if self.verifier_role_id.is_none() || !self.is_verifier(interaction) {
+            return Ok(Some(ephemeral_response(
+                "Only verifiers can unlink other people",
+            )));
+        }
+        let message = match unlink_account(target, conn)? {
+            Some(name) => {
+                info!("{} unlinked {} from SRC user {}", user.id, target, name);
+                format!("They're no longer linked to {} on SRC", name)
+            }
+            None => "They hadn't linked an SRC account".to_string(),
+        };
+        Ok(Some(ephemeral_response(&message)))
+    }
+
+    fn is_verifier(&self, interaction: &Interaction) -> bool {
+        match self.verifier_role_id {
+            Some(role) => interaction
+                .member
+                .as_ref()
+                .map(|m| m.roles.contains(&role))
+                .unwrap_or(false),
+            None => true,
+        }
+    }
+
+    fn claim_response(
+        &self,
+        interaction: &Interaction,
+        action: ClaimAction,
+        run_id: &str,
+        conn: &mut SqliteConnection,
+    ) -> Result<Option<InteractionResponse>, BotError> {
+        if !self.is_verifier(interaction) {
+            return Ok(Some(ephemeral_response("Only verifiers can claim runs")));
+        }
+        let (user, nick) = match interaction_user(interaction) {
+            Some(u) => u,
+            None => return Ok(None),
+        };
+        let name = nick.unwrap_or(&user.name);
+        match apply_claim(action, run_id, user.id, name, conn)? {
+            ClaimOutcome::Refused(why) => Ok(Some(ephemeral_response(&why))),
+            ClaimOutcome::Updated(claimant) => {
+                let embeds = interaction
+                    .message
+                    .as_ref()
+                    .map(|m| {
+                        m.embeds
+                            .iter()
+                            .cloned()
+                            .map(|e| with_claimant(e, claimant))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                Ok(Some(InteractionResponse {
+                    kind: InteractionResponseType::UpdateMessage,
+                    data: Some(InteractionResponseData {
+                        embeds: Some(embeds),
+                        ..Default::default()
+                    }),
+                }))
+            }
+        }
+    }
+
+    fn decision_response(
+        &self,
+        interaction: &Interaction,
+        action: DecisionAction,
+        run_id: &str,
+    ) -> Result<Option<InteractionResponse>, BotError> {
+        if self.decisions.is_none() {
+            // an old announcement from before decisions were turned off
+            return Ok(Some(ephemeral_response(
+                "Approving and rejecting runs from discord is turned off",
+            )));
+        }
+        if !self.is_verifier(interaction) {
+            return Ok(Some(ephemeral_response(
+                "Only verifiers can approve or reject runs",
+            )));
+        }
+        match action {
+            DecisionAction::Approve => self.push_decision(interaction, run_id, NewStatus::Verified),
+            // we can't reject without a reason, so ask for one; the rest happens when the modal
+            // is submitted
+            DecisionAction::Reject => Ok(Some(reason_modal(run_id))),
+        }
+    }
+
+    fn push_decision(
+        &self,
+        interaction: &Interaction,
+        run_id: &str,
+        status: NewStatus,
+    ) -> Result<Option<InteractionResponse>, BotError> {
+        let decisions = match &self.decisions {
+            Some(d) => d,
+            None => return Ok(None),
+        };
+        // checked when the button was pressed, but the modal is a separate interaction
+        if !self.is_verifier(interaction) {
+            return Ok(Some(ephemeral_response(
+                "Only verifiers can approve or reject runs",
+            )));
+        }
+        let (user, nick) = match interaction_user(interaction) {
+            Some(u) => u,
+            None => return Ok(None),
+        };
+        let name = nick.unwrap_or(&user.name).to_string();
+        let embeds = interaction
+            .message
+            .as_ref()
+            .map(|m| m.embeds.clone())
+            .unwrap_or_default();
+        // SRC can take longer to answer than discord will wait for us, so answer now and edit
+        // the message (or follow up) once we know how it went
+        let decisions = decisions.clone();
+        let token = interaction.token.clone();
+        let run_id = run_id.to_string();
+        let user_id = user.id;
+        IN_FLIGHT.spawn(async move {
+            decisions
+                .push_and_follow_up(&token, &run_id, &status, user_id, &name, embeds)
+                .await
+        });
+        Ok(Some(InteractionResponse {
+            kind: InteractionResponseType::DeferredUpdateMessage,
+            data: None,
+        }))
+    }
+}
+
+/// the user who triggered the interaction, and their server nickname if they have one
+fn interaction_user(interaction: &Interaction) -> Option<(&User, Option<&str>)> {
+    if let Some(member) = &interaction.member {
+        if let Some(user) = &member.user {
+            return Some((user, member.nick.as_deref()));
+        }
+    }
+    interaction.user.as_ref().map(|u| (u, None))
+}
+
+fn integer_option(data: &CommandData, name: &str) -> Option<i64> {
+    data.options
+        .iter()
+        .find(|o| o.name == name)
+        .and_then(|o| match o.value {
+            CommandOptionValue::Integer(i) => Some(i),
+            _ => None,
+        })
+}
+
+fn string_option<'a>(data: &'a CommandData, name: &str) -> Option<&'a str> {
+    data.options
+        .iter()
+        .find(|o| o.name == name)
+        .and_then(|o| match &o.value {
+            CommandOptionValue::String(s) => Some(s.as_str()),
+            _ => None,
+        })
+}
+
+fn boolean_option(data: &CommandData, name: &str) -> Option<bool> {
+    data.options
+        .iter()
+        .find(|o| o.name == name)
+        .and_then(|o| match o.value {
+            CommandOptionValue::Boolean(b) => Some(b),
+            _ => None,
+        })
+}
+
+fn user_option(data: &CommandData, name: &str) -> Option<Id<UserMarker>> {
+    data.options
+        .iter()
+        .find(|o| o.name == name)
+        .and_then(|o| match o.value {
+            CommandOptionValue::User(u) => Some(u),
+            _ => None,
+        })
+}
+
+/// what a (un)subscribe command is about. you have to pick exactly one of a runner or a category
+fn subscription_target(data: &CommandData) -> Option<(SubscriptionKind, &str)> {
+    match (
+        string_option(data, "runner"),
+        string_option(data, "category"),
+    ) {
+        (Some(r), None) => Some((SubscriptionKind::Runner, r)),
+        (None, Some(c)) => Some((SubscriptionKind::Category, c)),
+        _ => None,
+    }
+}
+
+fn subscribe_response(
+    interaction: &Interaction,
+    data: &CommandData,
+    conn: &mut SqliteConnection,
+) -> Result<Option<InteractionResponse>, BotError> {
+    let (user, _) = match interaction_user(interaction) {
+        Some(u) => u,
+        None => return Ok(None),
+    };
+    let (kind, target) = match subscription_target(data) {
+        Some(t) => t,
+        None => {
+            return Ok(Some(ephemeral_response(
+                "Pick either a runner or a category (one at a time)",
+            )))
+        }
+    };
+    let verified = boolean_option(data, "verified").unwrap_or(false);
+    subscribe(user.id, kind, target, verified, conn)?;
+    let when = if verified {
+        "submits a run, and when it's verified"
+    } else {
+        "submits a run"
+    };
+    let who = match kind {
+        SubscriptionKind::Runner => target.to_string(),
+        SubscriptionKind::Category => format!("someone in {}", target),
+    };
+    Ok(Some(ephemeral_response(&format!(
+        "I'll DM you when {} {}. Make sure you allow DMs from this server!",
+        who, when
+    ))))
+}
+
+fn unsubscribe_response(
+    interaction: &Interaction,
+    data: &CommandData,
+    conn: &mut SqliteConnection,
+) -> Result<Option<InteractionResponse>, BotError> {
+    let (user, _) = match interaction_user(interaction) {
+        Some(u) => u,
+        None => return Ok(None),
+    };
+    let (kind, target) = match subscription_target(data) {
+        Some(t) => t,
+        None => {
+            return Ok(Some(ephemeral_response(
+                "Pick either a runner or a category (one at a time)",
+            )))
+        }
+    };
+    let message = if unsubscribe(user.id, kind, target, conn)? {
+        format!("You won't get any more DMs about {}", target)
+    } else {
+        format!("You weren't subscribed to {}", target)
+    };
+    Ok(Some(ephemeral_response(&message)))
+}
+
+fn subscriptions_response(
+    interaction: &Interaction,
+    conn: &mut SqliteConnection,
+) -> Result<Option<InteractionResponse>, BotError> {
+    let (user, _) = match interaction_user(interaction) {
+        Some(u) => u,
+        None => return Ok(None),
+    };
+    let subs = subscriptions_for(user.id, conn)?;
+    if subs.is_empty() {
+        return Ok(Some(ephemeral_response(
+            "You aren't subscribed to anything. Use /subscribe to change that",
+        )));
+    }
+    let lines = subs
+        .iter()
+        .map(|s| {
+            let verified = if s.notify_verified {
+                " (and verifications)"
+            } else {
+                ""
+            };
+            format!("{} {}{}", s.kind, s.target, verified)
+        })
+        .collect::<Vec<_>>();
+    Ok(Some(ephemeral_response(&format!(
+        "You get DMs about:\n{}",
+        lines.join("\n")
+    ))))
+}
+
+fn verifiers_response(
+    data: &CommandData,
+    conn: &mut SqliteConnection,
+) -> Result<InteractionResponse, BotError> {
+    let days = integer_option(data, "days");
+    let since = match days {
+        Some(d) => {
+            // discord should have held it to the option's limits, but don't count on that
+            let since = (1..=MAX_VERIFIERS_DAYS)
+                .contains(&d)
+                .then(|| Utc::now().checked_sub_signed(Duration::days(d)))
+                .flatten();
+            match since {
+                Some(s) => Some(format_timestamp(s)),
+                None => {
+                    return Ok(ephemeral_response(&format!(
+                        "days has to be between 1 and {}",
+                        MAX_VERIFIERS_DAYS
+                    )))
+                }
+            }
+        }
+        None => None,
+    };
+    let stats = verifier_stats(since.as_deref(), conn)?;
+    Ok(message_response(InteractionResponseData {
+        embeds: Some(vec![verifier_stats_embed(&stats, days)]),
+        ..Default::default()
+    }))
+}
+
+/// a message only the user who triggered the interaction can see
+fn ephemeral_response(content: &str) -> InteractionResponse {
+    message_response(InteractionResponseData {
+        content: Some(content.to_string()),
+        flags: Some(MessageFlags::EPHEMERAL),
+        ..Default::default()
+    })
+}
+
+fn message_response(data: InteractionResponseData) -> InteractionResponse {
+    InteractionResponse {
+        kind: InteractionResponseType::ChannelMessageWithSource,
+        data: Some(data),
+    }
+}
@@ -1,16 +1,73 @@
 extern crate diesel;
 
-use diesel::{Connection, ConnectionResult, SqliteConnection};
+use diesel::connection::SimpleConnection;
+use diesel::{Connection, ConnectionError, ConnectionResult, SqliteConnection};
 
+pub mod admin;
+pub mod alerts;
+pub mod announce;
+pub mod backfill;
+pub mod celebrations;
+pub mod claims;
+pub mod decisions;
+pub mod digest;
 pub mod discord_client;
+pub mod dry_run;
+pub mod embeds;
 pub mod error;
+pub mod events;
+#[cfg(any(test, feature = "test-util"))]
+pub mod fake_discord;
+pub mod gateway;
+pub mod interactions;
+pub mod lifecycle;
+pub mod links;
+pub mod metrics;
+#[cfg(any(test, feature = "test-util"))]
+pub mod mock_server;
 pub mod models;
+pub mod notifier;
+pub mod reactions;
 pub mod schema;
+pub mod shutdown;
 pub mod src;
+pub mod staleness;
+pub mod subscriptions;
 pub mod utils;
+pub mod verifier_stats;
+pub mod web;
 
 pub const ALTTP_GAME_ID: &str = "9d3rr0dl";
 
 pub fn get_conn(database_url: &str) -> ConnectionResult<SqliteConnection>{
-    SqliteConnection::establish(&database_url)
-}
\ No newline at end of file
+    let mut conn = SqliteConnection::establish(&database_url)?;
+    // other tasks (e.g. the gateway) have their own connections, so wait on locks rather than
+    // failing immediately
+    conn.batch_execute("PRAGMA busy_timeout = 5000;")
+        .map_err(ConnectionError::CouldntSetupConfiguration)?;
+    Ok(conn)
+}
+
+/// a fresh in-memory database with all the migrations run, for tests
+#[cfg(test)]
+pub(crate) fn test_conn() -> SqliteConnection {
+    use diesel_migrations::{FileBasedMigrations, MigrationHarness};
+    let mut conn = get_conn(":memory:").unwrap();
+    conn.run_pending_migrations(FileBasedMigrations::find_migrations_directory().unwrap())
+        .unwrap();
+    conn
+}
+
+/// somewhere in the temp dir for a test to put files, that no other test (or run of the tests)
+/// will be using at the same time
+#[cfg(test)]
+pub(crate) fn test_path(name: &str) -> std::path::PathBuf {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!(
+        "alttp-queue-bot-test-{}-{}-{}",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed),
+        name
+    ))
+}
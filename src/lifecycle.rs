@@ -0,0 +1,150 @@
+// a run leaves the queue when it's verified, rejected, or deleted. we notice that by it being
+// absent from a complete listing of the queue, and then ask SRC what became of it.
+
+use crate::error::{BotError, ErrorClass};
+use crate::models::runs::{Outcome, Run};
+use crate::schema;
+use crate::src::{get_run, get_user, RunsListing, SRCClient, SRCStatus};
+use crate::utils::{parse_timestamp, timestamp_now};
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+use log::{info, warn};
+
+/// how many departed runs we'll look up per poll, so that a big batch of verifications doesn't
+/// eat our whole SRC rate limit at once
+const MAX_RESOLUTIONS_PER_POLL: i64 = 20;
+
+/// records that any run we thought was in the queue, but which isn't in `listing`, has left it.
+/// `listing` must be complete, or we'd mark runs that we just didn't fetch
+pub fn mark_departed_runs(
+    listing: &RunsListing,
+    conn: &mut SqliteConnection,
+) -> Result<(), BotError> {
+    use schema::runs::dsl::*;
+    if !listing.is_complete() {
+        return Err(BotError::InvalidState(
+            "Can't tell which runs left the queue from a partial listing".to_string(),
+        ));
+    }
+    let queued_ids = listing
+        .runs()
+        .iter()
+        .map(|r| r.id.to_string())
+        .collect::<Vec<_>>();
+    let departed = diesel::update(
+        runs.filter(left_queue_at.is_null())
+            .filter(run_id.ne_all(queued_ids)),
+    )
+    .set(left_queue_at.eq(timestamp_now()))
+    .execute(conn)?;
+    if departed > 0 {
+        info!("{} runs have left the src queue", departed);
+    }
+    Ok(())
+}
+
+/// looks up the outcome of runs that have left the queue, but whose outcome we don't know yet.
+/// returns the runs we found out about, along with what became of them
+pub async fn resolve_departed_runs(
+    src_client: &SRCClient,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<(Run, Outcome)>, BotError> {
+    use schema::runs::dsl::*;
+    let unresolved = runs
+        .filter(left_queue_at.is_not_null())
+        .filter(outcome.is_null())
+        // oldest first, so that a few runs we can't resolve don't keep everything else waiting
+        .order((left_queue_at.asc(), id.asc()))
+        .limit(MAX_RESOLUTIONS_PER_POLL)
+        .load::<Run>(conn)?;
+    let mut resolved = vec![];
+    for run in unresolved {
+        let (new_outcome, decided, examiner) = match get_run(src_client, run.run_id.as_str()).await
+        {
+            Ok(status) => match status.status {
+                SRCStatus::New => {
+                    // it's back in the queue (or our listing was lying to us). either way,
+                    // the next poll will notice it and clear `left_queue_at`. until then, send
+                    // it to the back of the line
+                    diesel::update(&run)
+                        .set(left_queue_at.eq(timestamp_now()))
+                        .execute(conn)?;
+                    continue;
+                }
+                SRCStatus::Verified {
+                    examiner,
+                    verify_date,
+                } => (Outcome::Verified, verify_date, examiner),
+                SRCStatus::Rejected { examiner, .. } => (Outcome::Rejected, None, examiner),
+            },
+            Err(e) => match e.class() {
+                // e.g. a 404 because the run's been deleted. asking again won't help
+                ErrorClass::Permanent => {
+                    warn!(
+                        "Unable to get status of departed run {}: {:?}",
+                        run.run_id, e
+                    );
+                    (Outcome::Unknown, None, None)
+                }
+                _ => {
+                    warn!(
+                        "Unable to get status of departed run {}, will try again next poll: {:?}",
+                        run.run_id, e
+                    );
+                    continue;
+                }
+            },
+        };
+        let decided = decided.unwrap_or_else(timestamp_now);
+        let waited = match (
+            run.submitted.as_deref().and_then(parse_timestamp),
+            parse_timestamp(&decided),
+        ) {
+            (Some(s), Some(d)) => Some((d - s).num_seconds() as i32),
+            _ => None,
+        };
+        let name = match &examiner {
+            Some(e) => Some(examiner_name_for(e, src_client, conn).await?),
+            None => None,
+        };
+        diesel::update(&run)
+            .set((
+                outcome.eq(new_outcome.as_str()),
+                decided_at.eq(decided),
+                examiner_src_id.eq(examiner),
+                examiner_name.eq(name),
+                wait_secs.eq(waited),
+            ))
+            .execute(conn)?;
+        resolved.push((run, new_outcome));
+    }
+    Ok(resolved)
+}
+
+/// finds the display name of an SRC user, preferring one we've already recorded so that we
+/// don't look up the same handful of mods over and over. falls back to the id if SRC won't tell
+/// us
+async fn examiner_name_for(
+    examiner_id: &str,
+    src_client: &SRCClient,
+    conn: &mut SqliteConnection,
+) -> Result<String, BotError> {
+    use schema::runs::dsl::*;
+    let known = runs
+        .filter(examiner_src_id.eq(examiner_id))
+        .filter(examiner_name.is_not_null())
+        .select(examiner_name)
+        .first::<Option<String>>(conn)
+        .optional()?
+        .flatten();
+    if let Some(n) = known {
+        return Ok(n);
+    }
+    match get_user(src_client, examiner_id).await {
+        Ok(user) => Ok(user.names.international),
+        Err(e) => {
+            warn!("Unable to look up SRC user {}: {}", examiner_id, e);
+            Ok(examiner_id.to_string())
+        }
+    }
+}
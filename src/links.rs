@@ -0,0 +1,171 @@
+use crate::error::BotError;
+use crate::models::links::{LinkedAccount, NewLinkedAccount};
+use crate::schema;
+use crate::src::{get_user, SRCClient};
+use crate::utils::timestamp_now;
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+use log::{info, warn};
+use std::num::NonZeroU64;
+use std::time::Duration;
+use twilight_model::channel::message::AllowedMentions;
+use twilight_model::id::marker::UserMarker;
+use twilight_model::id::Id;
+
+/// `/link` has to answer within discord's 3 second interaction deadline
+const SRC_LOOKUP_TIMEOUT: Duration = Duration::from_millis(2500);
+
+/// looks up `src_name` on SRC and links it to the discord user, replacing any account they'd
+/// linked before. returns a message for the user either way
+pub async fn link_account(
+    src_client: &SRCClient,
+    discord_user: Id<UserMarker>,
+    src_name: &str,
+    conn: &mut SqliteConnection,
+) -> Result<String, BotError> {
+    let src_name = src_name.trim();
+    // SRC accepts usernames anywhere it accepts user ids
+    let user = match tokio::time::timeout(SRC_LOOKUP_TIMEOUT, get_user(src_client, src_name)).await
+    {
+        Ok(Ok(u)) => u,
+        Ok(Err(e)) => {
+            warn!("Unable to look up SRC user {}: {}", src_name, e);
+            return Ok(format!("I couldn't find {} on SRC", src_name));
+        }
+        Err(_) => return Ok("SRC didn't answer in time; try again in a bit".to_string()),
+    };
+    let src_id = user.id.to_string();
+    if !save_link(discord_user, &src_id, &user.names.international, conn)? {
+        return Ok(format!(
+            "{} is already linked to someone else. If it's yours, ask a verifier to unlink it",
+            user.names.international
+        ));
+    }
+    info!(
+        "Linked {} to SRC user {} ({})",
+        discord_user, user.names.international, src_id
+    );
+    Ok(format!(
+        "Linked you to {} on SRC. I'll mention you when your runs show up",
+        user.names.international
+    ))
+}
+
+/// links the SRC account to the discord user, unless someone else already has. returns whether
+/// it did
+fn save_link(
+    discord_user: Id<UserMarker>,
+    src_id: &str,
+    src_name: &str,
+    conn: &mut SqliteConnection,
+) -> Result<bool, BotError> {
+    use schema::linked_accounts::dsl;
+    let discord_user = discord_user.to_string();
+    let owner = dsl::linked_accounts
+        .filter(dsl::src_user_id.eq(src_id))
+        .select(dsl::discord_user_id)
+        .first::<String>(conn)
+        .optional()?;
+    if owner.map_or(false, |o| o != discord_user) {
+        return Ok(false);
+    }
+    diesel::insert_into(dsl::linked_accounts)
+        .values(NewLinkedAccount {
+            discord_user_id: discord_user,
+            src_user_id: src_id,
+            src_name,
+            linked_at: timestamp_now(),
+        })
+        .on_conflict(dsl::discord_user_id)
+        .do_update()
+        .set((
+            dsl::src_user_id.eq(src_id),
+            dsl::src_name.eq(src_name),
+            dsl::linked_at.eq(timestamp_now()),
+        ))
+        .execute(conn)?;
+    Ok(true)
+}
+
+/// returns the SRC name they'd linked, if any
+pub fn unlink_account(
+    discord_user: Id<UserMarker>,
+    conn: &mut SqliteConnection,
+) -> Result<Option<String>, BotError> {
+    use schema::linked_accounts::dsl::*;
+    let existing = linked_accounts
+        .filter(discord_user_id.eq(discord_user.to_string()))
+        .first::<LinkedAccount>(conn)
+        .optional()?;
+    if let Some(account) = &existing {
+        diesel::delete(linked_accounts.filter(id.eq(account.id))).execute(conn)?;
+    }
+    Ok(existing.map(|a| a.src_name))
+}
+
+/// the discord user who's linked to this SRC account
+pub fn linked_discord_user(
+    src_id: &str,
+    conn: &mut SqliteConnection,
+) -> Result<Option<Id<UserMarker>>, BotError> {
+    use schema::linked_accounts::dsl::*;
+    let user = linked_accounts
+        .filter(src_user_id.eq(src_id))
+        .select(discord_user_id)
+        .first::<String>(conn)
+        .optional()?;
+    Ok(user
+        .and_then(|u| u.parse::<NonZeroU64>().ok())
+        .map(Id::<UserMarker>::from))
+}
+
+/// message content that pings `user`, and only `user`
+pub fn runner_mention(user: Id<UserMarker>) -> (String, AllowedMentions) {
+    (
+        format!("<@{}>", user),
+        AllowedMentions {
+            users: vec![user],
+            ..Default::default()
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::links::{linked_discord_user, runner_mention, save_link, unlink_account};
+    use crate::test_conn;
+    use twilight_model::id::Id;
+
+    #[test]
+    fn test_runner_mention() {
+        let (content, allowed) = runner_mention(Id::new(1234));
+        assert_eq!("<@1234>", content);
+        assert_eq!(vec![Id::new(1234)], allowed.users);
+        assert!(allowed.parse.is_empty());
+        assert!(allowed.roles.is_empty());
+        assert!(!allowed.replied_user);
+    }
+
+    #[test]
+    fn test_first_link_wins() {
+        let mut conn = test_conn();
+        assert!(save_link(Id::new(1), "8e9mj5n8", "runner", &mut conn).unwrap());
+        // linking it again is fine, but nobody else gets to
+        assert!(save_link(Id::new(1), "8e9mj5n8", "runner", &mut conn).unwrap());
+        assert!(!save_link(Id::new(2), "8e9mj5n8", "runner", &mut conn).unwrap());
+        assert_eq!(
+            Some(Id::new(1)),
+            linked_discord_user("8e9mj5n8", &mut conn).unwrap()
+        );
+
+        assert_eq!(
+            Some("runner".to_string()),
+            unlink_account(Id::new(1), &mut conn).unwrap()
+        );
+        assert!(save_link(Id::new(2), "8e9mj5n8", "runner", &mut conn).unwrap());
+        assert_eq!(
+            Some(Id::new(2)),
+            linked_discord_user("8e9mj5n8", &mut conn).unwrap()
+        );
+    }
+}
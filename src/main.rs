@@ -3,249 +3,667 @@ extern crate log4rs;
 extern crate serde_json;
 extern crate speedrun_api;
 
+use chrono::Utc;
 use diesel::prelude::*;
 use diesel::SqliteConnection;
 use diesel_migrations::MigrationHarness;
-use rand::rng;
-use rand::seq::IndexedRandom;
+use futures_util::future::join_all;
 use std::collections::HashMap;
 use std::path::Path;
-use std::time::Duration;
-use twilight_model::channel::embed::Embed;
-use twilight_model::channel::embed::EmbedField;
-
-use alttp_queue_bot::discord_client::{BotDiscordClient, DiscordError};
-use alttp_queue_bot::models::runs::{NewRun, Run};
-use alttp_queue_bot::src::{get_runs, CategoriesRepository, SRCRun};
-use alttp_queue_bot::utils::{env_var, format_hms, secs_to_millis};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+use alttp_queue_bot::alerts::{AlertConfig, Alerter};
+use alttp_queue_bot::announce::create_run_message;
+use alttp_queue_bot::backfill::{backfill, summary_from_env, summary_post};
+use alttp_queue_bot::celebrations::{celebrate_verified_runs, CelebrationConfig};
+use alttp_queue_bot::decisions::DecisionConfig;
+use alttp_queue_bot::digest::{post_due_digests, DigestConfig};
+use alttp_queue_bot::discord_client::BotDiscordClient;
+use alttp_queue_bot::dry_run::{scratch_database, DryRunConfig, DryRunNotifier};
+use alttp_queue_bot::events::{EventConfig, EventEmitter, EventKind, QueueEvent};
+use alttp_queue_bot::gateway::run_gateway;
+use alttp_queue_bot::interactions::{commands, InteractionHandler, InteractionTransport};
+use alttp_queue_bot::lifecycle::{mark_departed_runs, resolve_departed_runs};
+use alttp_queue_bot::metrics::METRICS;
+use alttp_queue_bot::models::runs::{NewRun, Outcome, Run};
+use alttp_queue_bot::notifier::{bot_client_from_env, notifier_from_env, Notifier};
+use alttp_queue_bot::reactions::ReactionConfig;
+use alttp_queue_bot::shutdown::{shutdown_signal, IN_FLIGHT};
+use alttp_queue_bot::src::{get_runs, CategoriesRepository, RunsListing, SRCClient, SRCRun};
+use alttp_queue_bot::staleness::{remind_stale_runs, StalenessConfig};
+use alttp_queue_bot::subscriptions::{notify_subscribers, SubscriptionEvent};
+use alttp_queue_bot::utils::env_var;
+use alttp_queue_bot::web::interactions::InteractionsEndpoint;
+use alttp_queue_bot::web::{listen_addr_from_env, max_poll_age_from_env, serve, WebState};
 use alttp_queue_bot::{error::*, get_conn, schema, ALTTP_GAME_ID};
 use log::{debug, info, warn};
 use speedrun_api::SpeedrunApiClientAsync;
-use twilight_http::api_error::{ApiError, RatelimitedApiError};
-use twilight_http::error::ErrorType;
 
-/// mutates `db_run` in place
-/// sleeps off discord time
-async fn create_run_message(
-    src_run: &SRCRun<'_>,
-    discord_client: &BotDiscordClient,
-    categories: &CategoriesRepository<'_>,
-) -> Result<(), BotError> {
-    let titles = vec![
-        "New PB arrived!",
-        "Anotha one",
-        "🚨 PB ALERT 🚨",
-        "Meowski get on this one",
-        "gaming?!",
-        "someone was eating their wheaties",
-        "get a load of this guy",
-        "of all the runs I've seen...",
-        "an ostentatious display of skill",
-        "absolutely cracked",
-        "this run is built different",
-        "speed incarnate",
-        "a true gamer moment",
-        "unreal gaming skills",
-        "this is peak performance",
-        "legendary run",
-        "a masterpiece of speedrunning",
-        "this run deserves a medal",
-        "phenomenal execution",
-        "a run for the ages",
-        "next-level gaming",
-        "a true display of mastery",
-        "this run is fire",
-        "insane gameplay",
-        "elite speedrunning",
-        "this run is art 🎨",
-        "unbelievable performance",
-        "a run to remember",
-        "probably spliced",
-        "always check helma/arrghus!",
-        "some people have all the luck!",
-        "how many capespins in this one?",
-        "doomtaDisdainfulDonny",
-        "swifARTISTE",
-        "will this one start a fight?",
-        "is this WR pace?",
-        "did someone say 'poggers'?",
-        "this run is bussin' fr fr",
-        "a certified hood classic",
-        "MY GOAT",
-        "absolutely no cap",
-        "this run is sus 🕵️",
-        "a true sigma grindset",
-        "chef's kiss 👨‍🍳💋",
-        "GoatEmotey",
-        "superm209Eyes",
-        "now THIS is a 24/7 Andy Watch Party",
-        "its lmos league",
-        "don't forget to show your keybinds!",
-        "someone's hogging all the PB paste",
-        "this needs to be retimed",
-    ];
-
-    let mut rng = rng();
-    let title = titles.choose(&mut rng).map(|s| s.to_string());
-
-    let embeds = vec![Embed {
-        author: None,
-        color: None,
-        description: None,
-        fields: vec![
-            EmbedField {
-                inline: true,
-                name: "Runner".to_string(),
-                value: src_run.player().unwrap_or("Unknown").to_string(),
-            },
-            EmbedField {
-                inline: true,
-                name: "Category".to_string(),
-                value: categories
-                    .category_name_from_run(src_run)
-                    .unwrap_or("Unknown".to_string()),
-            },
-            EmbedField {
-                inline: true,
-                name: "Time".to_string(),
-                value: format_hms(src_run.times.primary_t),
-            },
-        ],
-        footer: None,
-        image: None,
-        kind: "rich".to_string(),
-        provider: None,
-        thumbnail: None,
-        timestamp: None,
-        title,
-        url: Some(src_run.weblink.to_string()),
-        video: None,
-    }];
-
-    let rli = discord_client
-        .create_message(embeds)
-        .await
-        .map_err(BotError::from)?;
-    rli.sleep().await;
-    Ok(())
+/// how often we check for runs that have been in the queue too long. staleness is measured in
+/// days, so there's no point doing this every poll
+const STALENESS_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// how often we check whether a digest is due
+const DIGEST_CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// how long we'll wait for background work (e.g. event deliveries) once we've been asked to
+/// stop. systemd gives us 120 seconds in total, and the poll loop might have used most of that
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(20);
+/// how long to wait before trying the first backfill again, when SRC hasn't told us
+const BACKFILL_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// the optional things we do as runs come and go, on top of posting about them
+struct PollOptions<'a> {
+    /// add buttons for approving/rejecting runs on SRC to their posts
+    decision_buttons: bool,
+    /// congratulate runners on their newly verified runs, as the bot
+    celebrations: Option<(&'a CelebrationConfig, &'a BotDiscordClient)>,
+    events: Option<&'a EventEmitter>,
+    /// DM people who've subscribed to runners/categories, as the bot
+    subscriptions: Option<&'a BotDiscordClient>,
 }
 
 async fn handle_run(
     src_run: &SRCRun<'_>,
     runs_by_id: &mut HashMap<String, Run>,
     conn: &mut SqliteConnection,
-    discord_client: &BotDiscordClient,
+    notifier: &dyn Notifier,
     categories: &CategoriesRepository<'_>,
+    options: &PollOptions<'_>,
 ) -> Result<(), BotError> {
     let run_id = src_run.id.to_string();
 
-    if let Some(_r) = runs_by_id.get(&run_id) {
-        // this run is already in the db, so we don't need to do anything
+    if let Some(r) = runs_by_id.get(&run_id) {
+        // this run is already in the db, so we don't need to do anything (unless it had left
+        // the queue and come back, e.g. because a mod un-verified it). whatever we thought
+        // happened to it doesn't apply any more
+        if r.left_queue_at.is_some() {
+            diesel::update(r)
+                .set((
+                    schema::runs::left_queue_at.eq(None::<String>),
+                    schema::runs::outcome.eq(None::<String>),
+                    schema::runs::decided_at.eq(None::<String>),
+                ))
+                .execute(conn)?;
+        }
         return Ok(());
     }
 
-    create_run_message(&src_run, discord_client, categories).await?;
+    let message_id = create_run_message(
+        &src_run,
+        notifier,
+        categories,
+        options.decision_buttons,
+        conn,
+    )
+    .await?;
+    METRICS.record_run_posted();
     // only create the run after we've posted about it, now that all we are doing is making
     // one post about it
-    let new_run = NewRun {
-        submitted: src_run.submitted.as_ref().map(|s| s.as_str()),
-        run_id,
-    };
     diesel::insert_into(schema::runs::table)
-        .values(new_run)
+        .values(NewRun::from_src_run(src_run, Some(message_id)))
         .execute(conn)?;
+    if let Some(events) = options.events {
+        events.emit(
+            &QueueEvent::from_src_run(EventKind::RunSubmitted, src_run, categories),
+            conn,
+        );
+    }
+    // the run's been posted and recorded by now, so this failing isn't the run failing
+    if let Some(discord_client) = options.subscriptions {
+        if let Err(e) = notify_subscribers(
+            SubscriptionEvent::Submitted,
+            src_run.player(),
+            categories.category_name_from_run(src_run).as_deref(),
+            Some(&src_run.weblink),
+            discord_client,
+            conn,
+        )
+        .await
+        {
+            METRICS.record_error(&e);
+            warn!("Unable to notify subscribers about run {}: {}", run_id, e);
+        }
+    }
 
     Ok(())
 }
 
-/// scans SRC for new runs, creates records + threads for them
+/// records a run we couldn't post about, unless we'd already recorded it before failing
+fn record_unposted_run(src_run: &SRCRun<'_>, conn: &mut SqliteConnection) -> Result<(), BotError> {
+    let known = schema::runs::table
+        .filter(schema::runs::run_id.eq(src_run.id.to_string()))
+        .count()
+        .get_result::<i64>(conn)?;
+    if known == 0 {
+        diesel::insert_into(schema::runs::table)
+            .values(NewRun::from_src_run(src_run, None))
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+/// scans SRC for new runs, creates records + threads for them. returns the errors for any runs
+/// we had to give up on this time round
 async fn handle_new_runs(
-    src_client: &SpeedrunApiClientAsync,
-    discord_client: &BotDiscordClient,
+    src_client: &SRCClient,
+    notifier: &dyn Notifier,
     categories: &CategoriesRepository<'_>,
     conn: &mut SqliteConnection,
-) -> Result<(), BotError> {
+    options: &PollOptions<'_>,
+) -> Result<Vec<BotError>, BotError> {
     // TODO(#4) this doesn't need to be a full table scan (and pulling this out to the caller might
     //          allow us to do fewer queries total, too)
     let known_runs = schema::runs::table.load::<Run>(conn)?;
     let mut runs_by_id: HashMap<String, Run> =
         HashMap::from_iter(known_runs.into_iter().map(|r| (r.run_id.clone(), r)));
-    let runs = get_runs(&src_client).await?;
-    info!("Processing {} runs in the src queue", runs.len());
-    for run in runs {
-        if let Err(e) = handle_run(&run, &mut runs_by_id, conn, discord_client, categories).await {
-            if let BotError::DiscordError(DiscordError::HttpError(httpe)) = e {
-                if let Some(rle) = http_error_to_ratelimit(httpe) {
+    let listing = get_runs(&src_client).await?;
+    if let RunsListing::Partial { runs, error } = &listing {
+        // it's still fine to announce the runs we did get; we just can't draw any conclusions
+        // about runs that *aren't* in here
+        warn!(
+            "Only got a partial listing of the src queue ({} runs): {}",
+            runs.len(),
+            error
+        );
+    }
+    info!("Processing {} runs in the src queue", listing.runs().len());
+    let mut failures = vec![];
+    for run in listing.runs() {
+        if let Err(e) = handle_run(run, &mut runs_by_id, conn, notifier, categories, options).await
+        {
+            // anything we didn't manage to post gets another go on the next poll
+            match e.class() {
+                ErrorClass::Retryable {
+                    retry_after: Some(retry_after),
+                } => {
                     // this is happening despite my efforts to avoid rate limits above, for some
                     // reason. best to just handle it i guess
-                    let tts = secs_to_millis(rle.retry_after);
                     debug!(
-                        "Rate limited processing {:?}: sleeping for {} -  {:?}",
-                        run, tts, rle
+                        "Rate limited processing {}: sleeping for {:?} - {}",
+                        run.id, retry_after, e
                     );
-                    tokio::time::sleep(Duration::from_millis(tts)).await;
+                    METRICS.record_rate_limit_sleep(retry_after);
+                    tokio::time::sleep(retry_after).await;
+                }
+                ErrorClass::Retryable { retry_after: None } => {
+                    METRICS.record_error(&e);
+                    METRICS.record_run_failure();
+                    warn!("Error handling run {}: {}", run.id, e);
+                    failures.push(e);
                 }
-            } else {
-                warn!("Error handling run {:?}: {:?}", run, e);
+                // trying again would only fail the same way on every poll, so remember the run
+                // without a post
+                ErrorClass::Permanent => {
+                    METRICS.record_error(&e);
+                    METRICS.record_run_failure();
+                    warn!("Giving up on posting run {}: {}", run.id, e);
+                    if let Err(e) = record_unposted_run(run, conn) {
+                        warn!("Unable to record run {}: {}", run.id, e);
+                    }
+                    failures.push(e);
+                }
+                // every other run is going to fail the same way, so don't bother with them
+                ErrorClass::Configuration => return Err(e),
             }
         }
     }
-    Ok(())
+    if listing.is_complete() {
+        METRICS.set_queue_size(listing.runs().len());
+        mark_departed_runs(&listing, conn)?;
+    }
+    Ok(failures)
 }
 
+/// returns the errors for any runs we couldn't post about; see `handle_new_runs`
 async fn run_once(
-    src_client: &SpeedrunApiClientAsync,
-    discord_client: &BotDiscordClient,
+    src_client: &SRCClient,
+    notifier: &dyn Notifier,
     categories: &CategoriesRepository<'_>,
     conn: &mut SqliteConnection,
-) -> Result<(), BotError> {
-    handle_new_runs(src_client, discord_client, categories, conn).await
+    options: &PollOptions<'_>,
+) -> Result<Vec<BotError>, BotError> {
+    let failures = handle_new_runs(src_client, notifier, categories, conn, options).await?;
+    let resolved = resolve_departed_runs(src_client, conn).await?;
+    if let Some(events) = options.events {
+        for (run, outcome) in &resolved {
+            let kind = match outcome {
+                Outcome::Verified => EventKind::RunVerified,
+                Outcome::Rejected => EventKind::RunRejected,
+                Outcome::Unknown => continue,
+            };
+            events.emit(&QueueEvent::from_db_run(kind, run, categories), conn);
+        }
+    }
+    let verified = resolved
+        .into_iter()
+        .filter(|(_, outcome)| *outcome == Outcome::Verified)
+        .map(|(run, _)| run)
+        .collect::<Vec<_>>();
+    if let Some(discord_client) = options.subscriptions {
+        for run in &verified {
+            // these runs have already been marked as verified, so they won't come round again;
+            // don't let one of them stop the rest from being announced
+            if let Err(e) = notify_subscribers(
+                SubscriptionEvent::Verified,
+                run.runner_name.as_deref(),
+                categories.category_name_from_db_run(run).as_deref(),
+                run.weblink.as_deref(),
+                discord_client,
+                conn,
+            )
+            .await
+            {
+                METRICS.record_error(&e);
+                warn!(
+                    "Unable to notify subscribers about run {}: {}",
+                    run.run_id, e
+                );
+            }
+        }
+    }
+    if let Some((config, discord_client)) = options.celebrations {
+        celebrate_verified_runs(
+            config,
+            &verified,
+            src_client,
+            discord_client,
+            categories,
+            conn,
+        )
+        .await;
+    }
+    Ok(failures)
 }
 
-fn http_error_to_ratelimit(httpe: twilight_http::Error) -> Option<RatelimitedApiError> {
-    let (kind, _) = httpe.into_parts();
-    match kind {
-        ErrorType::Response { error, .. } => match error {
-            ApiError::Ratelimited(rl) => Some(rl),
-            _ => None,
-        },
-        _ => None,
+/// backfills alttp's queue, trying again for as long as SRC is having trouble. going ahead
+/// without it would announce everything we didn't manage to backfill
+async fn backfill_until_done<'a>(
+    src_client: &'a SRCClient,
+    conn: &mut SqliteConnection,
+) -> Vec<SRCRun<'a>> {
+    loop {
+        match backfill(ALTTP_GAME_ID, src_client, conn).await {
+            Ok(backfilled) => return backfilled,
+            Err(e) => match e.class() {
+                ErrorClass::Retryable { retry_after } => {
+                    let delay = retry_after.unwrap_or(BACKFILL_RETRY_DELAY);
+                    warn!(
+                        "Unable to backfill the queue, trying again in {:?}: {}",
+                        delay, e
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                _ => panic!("Unable to backfill the queue: {}", e),
+            },
+        }
     }
 }
 
+/// for when we're asked to stop before we've started anything that needs winding down
+fn stop_during_startup() {
+    info!("Shut down during startup");
+    log::logger().flush();
+}
+
 #[tokio::main]
 async fn main() {
     println!("Starting up");
+    // installed first thing, so that a restart during startup doesn't kill us mid-migration
+    let shutdown = shutdown_signal().expect("Unable to handle shutdown signals");
+    tokio::pin!(shutdown);
     dotenv::dotenv().unwrap();
     let log_config_path = env_var("LOG4RS_CONFIG_FILE");
     log4rs::init_file(Path::new(&log_config_path), Default::default())
         .expect("Couldn't initialize logging");
-    let src_client = SpeedrunApiClientAsync::new().unwrap();
-    let discord_client = BotDiscordClient::new_from_env().unwrap();
-    let database_url = env_var("DATABASE_URL");
+    // shared with the interaction handler, which might need to push decisions to SRC
+    let src_client = Arc::new(
+        SRCClient::new_from_env(SpeedrunApiClientAsync::new().unwrap())
+            .expect("Unable to record SRC responses"),
+    );
+    // `None` if we're posting through a webhook, in which case nothing else that needs to talk
+    // to discord can be turned on
+    let discord_client = bot_client_from_env().expect("Invalid notifier configuration");
+    let bot = |feature: &str| match &discord_client {
+        Some(c) => c.clone(),
+        None => panic!(
+            "{} needs the bot, so it can't be used with NOTIFIER=\"webhook\"",
+            feature
+        ),
+    };
+    // a dry run only ever writes posts out locally, so everything else that would talk to
+    // discord (or anyone else) is switched off below
+    let dry_run_config = DryRunConfig::from_env().expect("Invalid dry run configuration");
+    let dry_run = dry_run_config.is_some();
+    let notifier: Arc<dyn Notifier> = match &dry_run_config {
+        Some(config) => Arc::new(
+            DryRunNotifier::new(config.output.as_deref()).expect("Unable to set up dry run"),
+        ),
+        None => notifier_from_env(discord_client.clone()).expect("Invalid notifier configuration"),
+    };
+    let database_url = match &dry_run_config {
+        Some(config) if config.skip_db_writes => {
+            scratch_database(&env_var("DATABASE_URL")).expect("Unable to set up dry run")
+        }
+        _ => env_var("DATABASE_URL"),
+    };
+    if dry_run {
+        info!(
+            "Dry run: not posting anything, and using the database at {}",
+            database_url
+        );
+    }
     let mut diesel_conn = get_conn(&database_url).expect("Unable to connect to database");
 
     let migrations = diesel_migrations::FileBasedMigrations::find_migrations_directory().unwrap();
     diesel_conn.run_pending_migrations(migrations).unwrap();
 
-    let cr = CategoriesRepository::new_with_fetch(ALTTP_GAME_ID, &src_client, &mut diesel_conn)
-        .await
-        .unwrap();
+    // the signal handlers have replaced the default of dying on the spot, so anything during
+    // startup that can take a while (e.g. retrying through an SRC outage) has to give way to them.
+    // nothing's running in the background yet, so there's nothing to wind down.
+    // the categories are shared with the web server
+    let cr = tokio::select! {
+        cr = CategoriesRepository::new_with_fetch(ALTTP_GAME_ID, &src_client, &mut diesel_conn) => {
+            Arc::new(cr.unwrap())
+        }
+        _ = &mut shutdown => return stop_during_startup(),
+    };
 
-    let poll_interval = env_var("POLL_INTERVAL_SECS")
-        .parse::<u64>()
-        .expect("Unable to parse POLL_INTERVAL_SECS as an integer");
-    let mut interval = tokio::time::interval(Duration::from_secs(poll_interval));
+    // on a fresh database, everything in the queue would be "new" and get its own post
+    let known_runs = schema::runs::table
+        .count()
+        .get_result::<i64>(&mut diesel_conn)
+        .expect("Unable to count runs");
+    if known_runs == 0 {
+        let summary = summary_from_env().expect("Invalid BACKFILL_SUMMARY");
+        let backfilled = tokio::select! {
+            backfilled = backfill_until_done(&src_client, &mut diesel_conn) => backfilled,
+            _ = &mut shutdown => return stop_during_startup(),
+        };
+        if summary && !backfilled.is_empty() {
+            if let Err(e) = notifier.post(&summary_post(&backfilled, &cr)).await {
+                warn!("Unable to post backfill summary: {}", e);
+            }
+        }
+    }
+
+    let interactions = InteractionTransport::from_env()
+        .expect("Invalid interactions configuration")
+        .filter(|_| !dry_run);
+    if interactions.is_some() {
+        let discord_client = bot("INTERACTIONS");
+        tokio::select! {
+            registered = discord_client.set_commands(&commands()) => {
+                registered.expect("Unable to register slash commands");
+            }
+            _ = &mut shutdown => return stop_during_startup(),
+        }
+    }
+    let reaction_config = ReactionConfig::from_env()
+        .expect("Invalid reaction configuration")
+        .filter(|_| !dry_run);
+    let gateway_interactions = match interactions {
+        Some(InteractionTransport::Gateway) => Some(
+            InteractionHandler::new_from_env(
+                src_client.clone(),
+                bot("INTERACTIONS"),
+                &database_url,
+            )
+            .expect("Invalid interactions configuration"),
+        ),
+        _ => None,
+    };
+    // tells the gateway and web server to wind down when we're shutting down
+    let (stop, stop_receiver) = watch::channel(false);
+    let mut background_tasks = vec![];
+    if gateway_interactions.is_some() || reaction_config.is_some() {
+        let gateway_client = bot("REACTION_ROLE_ID");
+        let gateway_db_url = database_url.clone();
+        let gateway_stop = stop_receiver.clone();
+        background_tasks.push(tokio::spawn(async move {
+            if let Err(e) = run_gateway(
+                gateway_client,
+                gateway_db_url,
+                gateway_interactions,
+                reaction_config,
+                gateway_stop,
+            )
+            .await
+            {
+                warn!("Gateway failed: {}", e);
+            }
+        }));
+    }
+    let poll_interval = Duration::from_secs(
+        env_var("POLL_INTERVAL_SECS")
+            .parse::<u64>()
+            .expect("Unable to parse POLL_INTERVAL_SECS as an integer"),
+    );
+    let listen_addr = listen_addr_from_env().expect("Invalid HTTP_LISTEN_ADDR");
+    if listen_addr.is_none() && matches!(interactions, Some(InteractionTransport::Http)) {
+        panic!("INTERACTIONS=\"http\" needs HTTP_LISTEN_ADDR to be set");
+    }
+    if let Some(addr) = listen_addr {
+        let http_interactions = match interactions {
+            Some(InteractionTransport::Http) => Some(
+                InteractionsEndpoint::new_from_env(
+                    InteractionHandler::new_from_env(
+                        src_client.clone(),
+                        bot("INTERACTIONS"),
+                        &database_url,
+                    )
+                    .expect("Invalid interactions configuration"),
+                )
+                .expect("Invalid HTTP interactions configuration"),
+            ),
+            _ => None,
+        };
+        let state = Arc::new(WebState {
+            database_url: database_url.clone(),
+            categories: cr.clone(),
+            interactions: http_interactions,
+            max_poll_age: max_poll_age_from_env(poll_interval)
+                .expect("Invalid HEALTHZ_MAX_POLL_AGE_SECS"),
+        });
+        let web_stop = stop_receiver.clone();
+        background_tasks.push(tokio::spawn(async move {
+            if let Err(e) = serve(addr, state, web_stop).await {
+                warn!("HTTP server failed: {}", e);
+            }
+        }));
+    }
+
+    // without interactions, nobody could press the buttons (and posts made through a webhook
+    // can't have buttons at all)
+    let decision_buttons = discord_client.is_some()
+        && interactions.is_some()
+        && DecisionConfig::from_env()
+            .expect("Invalid decision configuration")
+            .is_some();
+    let staleness_config = StalenessConfig::from_env()
+        .expect("Invalid staleness reminder configuration")
+        .filter(|_| !dry_run)
+        .map(|c| (c, bot("STALE_RUN_DAYS")));
+    let digest_config = DigestConfig::from_env()
+        .expect("Invalid digest configuration")
+        .filter(|_| !dry_run)
+        .map(|c| (c, bot("DIGEST_CHANNEL_ID")));
+    let celebration_config = CelebrationConfig::from_env()
+        .expect("Invalid celebration configuration")
+        .filter(|_| !dry_run)
+        .map(|c| (c, bot("CELEBRATION_CHANNEL_ID")));
+    let event_emitter = EventConfig::from_env()
+        .expect("Invalid event configuration")
+        .filter(|_| !dry_run)
+        .map(|c| EventEmitter::new(c, &database_url));
+    if let Some(events) = &event_emitter {
+        // whatever was still being sent when we last stopped
+        events
+            .resend_pending(&mut diesel_conn)
+            .expect("Unable to resend pending events");
+    }
+    let mut alerter = AlertConfig::from_env()
+        .expect("Invalid admin alert configuration")
+        .filter(|_| !dry_run)
+        .map(|c| {
+            (
+                Alerter::new(c),
+                bot("ADMIN_ALERT_CHANNEL_ID/ADMIN_ALERT_USER_ID"),
+            )
+        });
+    let poll_options = PollOptions {
+        decision_buttons,
+        celebrations: celebration_config
+            .as_ref()
+            .map(|(config, client)| (config, client.as_ref())),
+        events: event_emitter.as_ref(),
+        subscriptions: discord_client.as_deref().filter(|_| !dry_run),
+    };
+    let mut interval = tokio::time::interval(poll_interval);
+    // don't check for stale runs until we've had a chance to poll, so that we don't complain
+    // about runs that left the queue while we weren't running
+    let mut staleness_interval = tokio::time::interval_at(
+        tokio::time::Instant::now() + STALENESS_CHECK_INTERVAL,
+        STALENESS_CHECK_INTERVAL,
+    );
+    let mut digest_interval = tokio::time::interval(DIGEST_CHECK_INTERVAL);
     loop {
-        interval.tick().await;
-        if let Err(e) = run_once(&src_client, &discord_client, &cr, &mut diesel_conn).await {
-            warn!("Error: {:?}", e);
+        tokio::select! {
+            // checked first so that once we've been asked to stop, we don't start anything new.
+            // whatever's already running (e.g. a run that's been posted but not yet recorded)
+            // gets to finish, since select only interrupts the futures it's waiting on
+            biased;
+            _ = &mut shutdown => {
+                info!("Shutting down");
+                break;
+            }
+            _ = interval.tick() => {
+                let started = Instant::now();
+                let result = run_once(&src_client, notifier.as_ref(), &cr, &mut diesel_conn, &poll_options).await;
+                let clean = matches!(&result, Ok(run_failures) if run_failures.is_empty());
+                METRICS.record_poll(started.elapsed(), clean, Utc::now());
+                if let Some((alerter, client)) = alerter.as_mut() {
+                    alerter.record(&result, client).await;
+                }
+                if let Err(e) = result {
+                    METRICS.record_error(&e);
+                    warn!("Error: {}", e);
+                }
+            }
+            _ = staleness_interval.tick(), if staleness_config.is_some() => {
+                // unwrap is safe because of the select guard
+                let (config, client) = staleness_config.as_ref().unwrap();
+                if let Err(e) = remind_stale_runs(config, client, &mut diesel_conn).await {
+                    METRICS.record_error(&e);
+                    warn!("Error reminding about stale runs: {}", e);
+                }
+            }
+            _ = digest_interval.tick(), if digest_config.is_some() => {
+                let (config, client) = digest_config.as_ref().unwrap();
+                if let Err(e) = post_due_digests(config, client, &cr, &mut diesel_conn).await {
+                    METRICS.record_error(&e);
+                    warn!("Error posting digests: {}", e);
+                }
+            }
         }
     }
 
+    // let the gateway and web server finish what they're doing, and then anything they (or the
+    // poll loop) left running in the background, but don't hang around forever
+    let _ = stop.send(true);
+    let finished = tokio::time::timeout(SHUTDOWN_GRACE_PERIOD, async {
+        join_all(background_tasks).await;
+        IN_FLIGHT.wait().await;
+    })
+    .await;
+    if finished.is_err() {
+        warn!(
+            "Gave up waiting for background tasks after {:?}",
+            SHUTDOWN_GRACE_PERIOD
+        );
+    }
+    drop(diesel_conn);
+    info!("Shut down cleanly");
+    log::logger().flush();
+
     /*
     what could happen in the future:
-        * automatic moderation based on discord actions
         * alttpce coverage
      */
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{handle_new_runs, PollOptions};
+    use alttp_queue_bot::fake_discord::{FakeDiscord, FakeResponse};
+    use alttp_queue_bot::src::{CategoriesRepository, SRCClient};
+    use alttp_queue_bot::{get_conn, schema};
+    use diesel::prelude::*;
+    use diesel_migrations::{FileBasedMigrations, MigrationHarness};
+    use speedrun_api::SpeedrunApiClientAsync;
+    use twilight_model::id::Id;
+
+    /// 30 runs, over two pages
+    fn replaying_queue() -> SRCClient {
+        SRCClient::replaying(
+            SpeedrunApiClientAsync::new().unwrap(),
+            ["runs_first_page", "runs_second_page"]
+                .map(|f| format!("{}/api_responses/replay/{}", env!("CARGO_MANIFEST_DIR"), f)),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_run_is_retried() {
+        let discord = FakeDiscord::start().await;
+        let discord_client = discord.client(Id::new(10));
+        let mut conn = get_conn(":memory:").unwrap();
+        conn.run_pending_migrations(FileBasedMigrations::find_migrations_directory().unwrap())
+            .unwrap();
+        let categories = CategoriesRepository::new(vec![], vec![]);
+        let options = PollOptions {
+            decision_buttons: false,
+            celebrations: None,
+            events: None,
+            subscriptions: None,
+        };
+
+        discord.script(FakeResponse::RateLimited {
+            retry_after: 0.01,
+            global: false,
+        });
+        handle_new_runs(
+            &replaying_queue(),
+            &discord_client,
+            &categories,
+            &mut conn,
+            &options,
+        )
+        .await
+        .unwrap();
+        // the run we got rate limited on wasn't recorded...
+        let recorded = schema::runs::table
+            .count()
+            .get_result::<i64>(&mut conn)
+            .unwrap();
+        assert_eq!(29, recorded);
+        assert_eq!(30, discord.requests().len());
+
+        // ...so it's posted the next time round, and nothing else is
+        handle_new_runs(
+            &replaying_queue(),
+            &discord_client,
+            &categories,
+            &mut conn,
+            &options,
+        )
+        .await
+        .unwrap();
+        let recorded = schema::runs::table
+            .count()
+            .get_result::<i64>(&mut conn)
+            .unwrap();
+        assert_eq!(30, recorded);
+        assert_eq!(31, discord.requests().len());
+    }
+}
@@ -0,0 +1,275 @@
+use crate::discord_client::DiscordError;
+use crate::error::BotError;
+use crate::src::SRCError;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// everything we expose at `/metrics`. this is a global so that anywhere that notices
+/// something can record it without having to have it passed in
+pub static METRICS: Metrics = Metrics::new();
+
+const PREFIX: &str = "alttp_queue_bot";
+
+pub struct Metrics {
+    polls_succeeded: AtomicU64,
+    polls_failed: AtomicU64,
+    last_poll_duration_millis: AtomicU64,
+    /// unix timestamp, or 0 if we haven't had one yet
+    last_successful_poll: AtomicI64,
+    queue_size: AtomicU64,
+    runs_posted: AtomicU64,
+    run_failures: AtomicU64,
+    rate_limit_sleeps: AtomicU64,
+    rate_limit_sleep_millis: AtomicU64,
+    /// (source, kind) -> count
+    errors: Mutex<BTreeMap<(&'static str, &'static str), u64>>,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            polls_succeeded: AtomicU64::new(0),
+            polls_failed: AtomicU64::new(0),
+            last_poll_duration_millis: AtomicU64::new(0),
+            last_successful_poll: AtomicI64::new(0),
+            queue_size: AtomicU64::new(0),
+            runs_posted: AtomicU64::new(0),
+            run_failures: AtomicU64::new(0),
+            rate_limit_sleeps: AtomicU64::new(0),
+            rate_limit_sleep_millis: AtomicU64::new(0),
+            errors: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// a poll only `succeeded` if every new run in it was posted, too
+    pub fn record_poll(&self, duration: Duration, succeeded: bool, now: DateTime<Utc>) {
+        self.last_poll_duration_millis
+            .store(duration.as_millis() as u64, Ordering::Relaxed);
+        if succeeded {
+            self.polls_succeeded.fetch_add(1, Ordering::Relaxed);
+            self.last_successful_poll
+                .store(now.timestamp(), Ordering::Relaxed);
+        } else {
+            self.polls_failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn set_queue_size(&self, size: usize) {
+        self.queue_size.store(size as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_run_posted(&self) {
+        self.runs_posted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// a run we couldn't post about
+    pub fn record_run_failure(&self) {
+        self.run_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_rate_limit_sleep(&self, duration: Duration) {
+        self.rate_limit_sleeps.fetch_add(1, Ordering::Relaxed);
+        self.rate_limit_sleep_millis
+            .fetch_add(duration.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_error(&self, error: &BotError) {
+        let labels = error_labels(error);
+        if let Ok(mut errors) = self.errors.lock() {
+            *errors.entry(labels).or_default() += 1;
+        }
+    }
+
+    /// healthy means we've successfully polled SRC (and posted every new run) in the last
+    /// `max_age`
+    pub fn is_healthy(&self, now: DateTime<Utc>, max_age: Duration) -> bool {
+        let last = self.last_successful_poll.load(Ordering::Relaxed);
+        last != 0 && now.timestamp() - last <= max_age.as_secs() as i64
+    }
+
+    /// the prometheus text format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: Vec<(String, String)>| {
+            // writing to a String can't fail
+            let _ = writeln!(out, "# HELP {}_{} {}", PREFIX, name, help);
+            let _ = writeln!(out, "# TYPE {}_{} {}", PREFIX, name, kind);
+            for (labels, value) in samples {
+                let _ = writeln!(out, "{}_{}{} {}", PREFIX, name, labels, value);
+            }
+        };
+        let load = |a: &AtomicU64| a.load(Ordering::Relaxed).to_string();
+        metric(
+            "polls_total",
+            "counter",
+            "Polls of the SRC queue, by whether they succeeded",
+            vec![
+                ("{result=\"ok\"}".to_string(), load(&self.polls_succeeded)),
+                ("{result=\"error\"}".to_string(), load(&self.polls_failed)),
+            ],
+        );
+        metric(
+            "poll_duration_seconds",
+            "gauge",
+            "How long the most recent poll took",
+            vec![(
+                String::new(),
+                (self.last_poll_duration_millis.load(Ordering::Relaxed) as f64 / 1000.0)
+                    .to_string(),
+            )],
+        );
+        metric(
+            "last_successful_poll_timestamp_seconds",
+            "gauge",
+            "When we last polled SRC successfully (0 if we haven't yet)",
+            vec![(
+                String::new(),
+                self.last_successful_poll
+                    .load(Ordering::Relaxed)
+                    .to_string(),
+            )],
+        );
+        metric(
+            "queue_size",
+            "gauge",
+            "Runs in the SRC queue as of the last poll",
+            vec![(String::new(), load(&self.queue_size))],
+        );
+        metric(
+            "runs_posted_total",
+            "counter",
+            "Runs we've posted about",
+            vec![(String::new(), load(&self.runs_posted))],
+        );
+        metric(
+            "run_failures_total",
+            "counter",
+            "Runs we couldn't post about (most are retried on the next poll)",
+            vec![(String::new(), load(&self.run_failures))],
+        );
+        metric(
+            "rate_limit_sleeps_total",
+            "counter",
+            "Times we've slept off a discord rate limit",
+            vec![(String::new(), load(&self.rate_limit_sleeps))],
+        );
+        metric(
+            "rate_limit_sleep_seconds_total",
+            "counter",
+            "Time spent sleeping off discord rate limits",
+            vec![(
+                String::new(),
+                (self.rate_limit_sleep_millis.load(Ordering::Relaxed) as f64 / 1000.0).to_string(),
+            )],
+        );
+        let errors = self
+            .errors
+            .lock()
+            .map(|e| {
+                e.iter()
+                    .map(|((source, kind), count)| {
+                        (
+                            format!("{{source=\"{}\",kind=\"{}\"}}", source, kind),
+                            count.to_string(),
+                        )
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        metric(
+            "errors_total",
+            "counter",
+            "Errors, by where they came from and what went wrong",
+            errors,
+        );
+        out
+    }
+}
+
+/// (source, kind) labels for an error
+fn error_labels(error: &BotError) -> (&'static str, &'static str) {
+    match error {
+        BotError::SRCError(e) => (
+            "src",
+            match e {
+                SRCError::ApiError { .. } => "api",
+                SRCError::QueryBuildError(_) => "query_build",
+                SRCError::PageFetchError { .. } => "page_fetch",
+                SRCError::UnexpectedResponse { .. } => "unexpected_response",
+                SRCError::StatusUpdateRefused { .. } => "status_update_refused",
+            },
+        ),
+        BotError::DiscordError(e) => (
+            "discord",
+            match e {
+                DiscordError::HttpError(_) => "http",
+                DiscordError::ValidationError(_) => "validation",
+                DiscordError::DeserializeBodyError(_) => "deserialize_body",
+                DiscordError::InvalidInput(_) => "invalid_input",
+                DiscordError::WebhookError(_) => "webhook",
+                DiscordError::DryRunError(_) => "dry_run",
+                DiscordError::PostedWithoutId(_) => "posted_without_id",
+            },
+        ),
+        BotError::DatabaseError(_) | BotError::DatabaseConnectionError(_) => ("database", "db"),
+        BotError::VariableMissing(_) | BotError::VariableParseError(_) => ("config", "env"),
+        BotError::InvalidConfig(_) => ("config", "invalid"),
+        BotError::InvalidState(_) => ("bot", "invalid_state"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::discord_client::DiscordError;
+    use crate::error::BotError;
+    use crate::metrics::Metrics;
+    use crate::utils::parse_timestamp;
+    use std::time::Duration;
+
+    #[test]
+    fn test_health() {
+        let metrics = Metrics::new();
+        let now = parse_timestamp("2022-09-01T12:00:00Z").unwrap();
+        let max_age = Duration::from_secs(300);
+        // no polls yet
+        assert!(!metrics.is_healthy(now, max_age));
+        metrics.record_poll(Duration::from_secs(2), true, now);
+        assert!(metrics.is_healthy(now + chrono::Duration::seconds(300), max_age));
+        // failures don't count
+        metrics.record_poll(
+            Duration::from_secs(2),
+            false,
+            now + chrono::Duration::seconds(60),
+        );
+        assert!(!metrics.is_healthy(now + chrono::Duration::seconds(301), max_age));
+    }
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        metrics.set_queue_size(12);
+        metrics.record_run_posted();
+        metrics.record_run_failure();
+        metrics.record_rate_limit_sleep(Duration::from_millis(1500));
+        let error = BotError::DiscordError(DiscordError::ValidationError("too long".to_string()));
+        metrics.record_error(&error);
+        metrics.record_error(&error);
+        metrics.record_error(&BotError::InvalidState("oops".to_string()));
+        let rendered = metrics.render();
+        let lines = rendered.lines().collect::<Vec<_>>();
+        assert!(lines.contains(&"# TYPE alttp_queue_bot_queue_size gauge"));
+        assert!(lines.contains(&"alttp_queue_bot_queue_size 12"));
+        assert!(lines.contains(&"alttp_queue_bot_runs_posted_total 1"));
+        assert!(lines.contains(&"alttp_queue_bot_run_failures_total 1"));
+        assert!(lines.contains(&"alttp_queue_bot_rate_limit_sleep_seconds_total 1.5"));
+        assert!(lines
+            .contains(&"alttp_queue_bot_errors_total{source=\"discord\",kind=\"validation\"} 2"));
+        assert!(lines
+            .contains(&"alttp_queue_bot_errors_total{source=\"bot\",kind=\"invalid_state\"} 1"));
+        assert!(lines.contains(&"alttp_queue_bot_polls_total{result=\"ok\"} 0"));
+    }
+}
@@ -0,0 +1,92 @@
+// a local http server for tests to point things at instead of discord, SRC or an event endpoint.
+// it remembers every request it's sent, and answers them however the test tells it to
+
+use hyper::header::HeaderMap;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use url::Url;
+
+/// a request the server was sent
+#[derive(Debug, Clone)]
+pub struct SeenRequest {
+    pub method: Method,
+    pub path: String,
+    pub query: Option<String>,
+    pub headers: HeaderMap,
+    /// `None` if it was empty or wasn't json
+    pub body: Option<serde_json::Value>,
+}
+
+impl SeenRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|v| v.to_str().ok())
+    }
+}
+
+pub struct MockServer {
+    addr: SocketAddr,
+    seen: Arc<Mutex<Vec<SeenRequest>>>,
+}
+
+impl MockServer {
+    /// starts listening on a random local port. `respond` gets each request along with how many
+    /// came before it. has to be called from within a tokio runtime
+    pub fn start<F>(respond: F) -> Self
+    where
+        F: Fn(&SeenRequest, usize) -> Response<Body> + Send + Sync + 'static,
+    {
+        let seen = Arc::new(Mutex::new(vec![]));
+        let seen_by_server = seen.clone();
+        let respond = Arc::new(respond);
+        let make_svc = make_service_fn(move |_conn| {
+            let seen = seen_by_server.clone();
+            let respond = respond.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let seen = seen.clone();
+                    let respond = respond.clone();
+                    async move {
+                        let (parts, body) = req.into_parts();
+                        let bytes = hyper::body::to_bytes(body).await.unwrap_or_default();
+                        let req = SeenRequest {
+                            method: parts.method,
+                            path: parts.uri.path().to_string(),
+                            query: parts.uri.query().map(ToString::to_string),
+                            headers: parts.headers,
+                            body: serde_json::from_slice(&bytes).ok(),
+                        };
+                        let mut seen = seen.lock().unwrap();
+                        let resp = respond(&req, seen.len());
+                        seen.push(req);
+                        Ok::<_, Infallible>(resp)
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        Self { addr, seen }
+    }
+
+    /// `path` on this server, e.g. `url("/events")`
+    pub fn url(&self, path: &str) -> Url {
+        // a socket address is always a valid host
+        Url::parse(&format!("http://{}{}", self.addr, path)).expect("Invalid mock server url")
+    }
+
+    /// everything we've been sent so far, oldest first
+    pub fn seen(&self) -> MutexGuard<'_, Vec<SeenRequest>> {
+        self.seen.lock().unwrap()
+    }
+}
+
+/// for `MockServer::start`'s `respond`
+pub fn response(status: StatusCode, body: impl Into<Body>) -> Response<Body> {
+    let mut resp = Response::new(body.into());
+    *resp.status_mut() = status;
+    resp
+}
@@ -1,4 +1,4 @@
-use crate::schema::category_aliases::dsl::*;
+use crate::schema::category_aliases;
 use diesel::helper_types::{Eq, Filter};
 use diesel::prelude::*;
 
@@ -10,8 +10,18 @@ pub struct CategoryAlias {
     pub alias: String,
 }
 
+#[derive(Insertable)]
+#[table_name = "category_aliases"]
+pub struct NewCategoryAlias<'a> {
+    pub game_src_id: &'a str,
+    pub category_src_id: &'a str,
+    pub alias: &'a str,
+}
+
 impl CategoryAlias {
-    pub fn by_game_id(game_id: &str) -> Filter<category_aliases, Eq<game_src_id, &str>> {
-        category_aliases.filter(game_src_id.eq(game_id))
+    pub fn by_game_id(
+        game_id: &str,
+    ) -> Filter<category_aliases::table, Eq<category_aliases::game_src_id, &str>> {
+        category_aliases::table.filter(category_aliases::game_src_id.eq(game_id))
     }
 }
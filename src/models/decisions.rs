@@ -0,0 +1,31 @@
+use crate::schema::src_decisions;
+use diesel::prelude::*;
+
+/// an attempt to verify or reject a run on SRC from discord
+#[derive(Queryable, Debug)]
+pub struct SrcDecision {
+    pub id: i32,
+    /// the run's SRC id
+    pub run_id: String,
+    pub discord_user_id: String,
+    pub discord_user_name: String,
+    /// "verified" or "rejected"
+    pub decision: String,
+    pub reason: Option<String>,
+    /// a datetime string
+    pub attempted_at: String,
+    /// why it didn't work, if it didn't
+    pub error: Option<String>,
+}
+
+#[derive(Insertable)]
+#[table_name = "src_decisions"]
+pub struct NewSrcDecision<'a> {
+    pub run_id: &'a str,
+    pub discord_user_id: String,
+    pub discord_user_name: &'a str,
+    pub decision: &'a str,
+    pub reason: Option<&'a str>,
+    pub attempted_at: String,
+    pub error: Option<String>,
+}
@@ -0,0 +1,18 @@
+use crate::schema::digests;
+use diesel::prelude::*;
+
+#[derive(Queryable, Debug)]
+pub struct Digest {
+    pub id: i32,
+    /// "daily" or "weekly"
+    pub period: String,
+    /// a datetime string representing when the digest was posted
+    pub sent_at: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "digests"]
+pub struct NewDigest<'a> {
+    pub period: &'a str,
+    pub sent_at: String,
+}
@@ -0,0 +1,23 @@
+use crate::schema::linked_accounts;
+use diesel::prelude::*;
+
+/// a discord user's SRC account, as they told us with `/link`
+#[derive(Queryable, Debug)]
+pub struct LinkedAccount {
+    pub id: i32,
+    pub discord_user_id: String,
+    pub src_user_id: String,
+    /// the SRC name they linked with, for display
+    pub src_name: String,
+    /// a datetime string
+    pub linked_at: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "linked_accounts"]
+pub struct NewLinkedAccount<'a> {
+    pub discord_user_id: String,
+    pub src_user_id: &'a str,
+    pub src_name: &'a str,
+    pub linked_at: String,
+}
@@ -1,2 +1,8 @@
 pub mod aliases;
+pub mod decisions;
+pub mod digests;
+pub mod links;
+pub mod pending_events;
+pub mod reactions;
 pub mod runs;
+pub mod subscriptions;
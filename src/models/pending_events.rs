@@ -0,0 +1,20 @@
+use crate::schema::pending_events;
+use diesel::prelude::*;
+
+/// an event we were still trying to send to `endpoint`; see `EventEmitter`
+#[derive(Queryable, Debug)]
+pub struct PendingEvent {
+    pub id: i32,
+    pub endpoint: String,
+    pub body: String,
+    /// a datetime string
+    pub created_at: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "pending_events"]
+pub struct NewPendingEvent<'a> {
+    pub endpoint: &'a str,
+    pub body: &'a str,
+    pub created_at: String,
+}
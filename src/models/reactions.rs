@@ -0,0 +1,23 @@
+use crate::schema::run_reactions;
+use diesel::prelude::*;
+
+#[derive(Queryable, Debug)]
+pub struct RunReaction {
+    pub id: i32,
+    /// the id of the run in our db (not SRC's id)
+    pub run_id: i32,
+    pub discord_user_id: String,
+    /// see `ModReaction`
+    pub reaction: String,
+    /// a datetime string representing when the reaction was added
+    pub reacted_at: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "run_reactions"]
+pub struct NewRunReaction<'a> {
+    pub run_id: i32,
+    pub discord_user_id: String,
+    pub reaction: &'a str,
+    pub reacted_at: String,
+}
@@ -1,5 +1,8 @@
 use crate::schema::runs;
+use crate::src::SRCRun;
 use diesel::prelude::*;
+use twilight_model::id::marker::MessageMarker;
+use twilight_model::id::Id;
 
 #[derive(Queryable, Identifiable, Debug)]
 pub struct Run {
@@ -8,6 +11,86 @@ pub struct Run {
     pub submitted: Option<String>,
     /// the run's SRC id
     pub run_id: String,
+    /// a datetime string representing when we noticed the run was no longer in the queue
+    pub left_queue_at: Option<String>,
+    /// a datetime string representing when we pinged the mods about this run being stale
+    pub stale_reminder_sent_at: Option<String>,
+    pub weblink: Option<String>,
+    pub category_src_id: Option<String>,
+    /// JSON object of the run's variable ids -> value ids (see `SRCRun::values`)
+    pub category_values: Option<String>,
+    /// see `Outcome`
+    pub outcome: Option<String>,
+    /// a datetime string representing when the run was verified/rejected. SRC only tells us this
+    /// for verified runs; otherwise it's when we noticed.
+    pub decided_at: Option<String>,
+    /// the SRC user id of the mod who verified/rejected the run
+    pub examiner_src_id: Option<String>,
+    pub examiner_name: Option<String>,
+    /// how long the run waited between being submitted and being verified/rejected
+    pub wait_secs: Option<i32>,
+    /// the discord user id of whoever has claimed this run to verify it
+    pub claimed_by_discord_id: Option<String>,
+    pub claimed_by_name: Option<String>,
+    pub claimed_at: Option<String>,
+    /// the discord id of the message we posted about this run
+    pub message_id: Option<String>,
+    /// a datetime string; we won't remind the mods about this run being stale until after this
+    pub snoozed_until: Option<String>,
+    /// the runner's name, as we announced it
+    pub runner_name: Option<String>,
+    /// the runner's SRC user id (`None` for guests)
+    pub runner_src_id: Option<String>,
+    /// the run's time, in seconds
+    pub primary_t: Option<f64>,
+}
+
+/// a run with nothing but its id filled in. tests fill in the columns they care about with
+/// `Run { .., ..test_run("abc123") }`, so that adding a column doesn't mean editing them all
+#[cfg(test)]
+pub(crate) fn test_run(run_id: &str) -> Run {
+    Run {
+        id: 0,
+        submitted: None,
+        run_id: run_id.to_string(),
+        left_queue_at: None,
+        stale_reminder_sent_at: None,
+        weblink: None,
+        category_src_id: None,
+        category_values: None,
+        outcome: None,
+        decided_at: None,
+        examiner_src_id: None,
+        examiner_name: None,
+        wait_secs: None,
+        claimed_by_discord_id: None,
+        claimed_by_name: None,
+        claimed_at: None,
+        message_id: None,
+        snoozed_until: None,
+        runner_name: None,
+        runner_src_id: None,
+        primary_t: None,
+    }
+}
+
+/// what happened to a run once it left the queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Verified,
+    Rejected,
+    /// we couldn't find out (e.g. the run was deleted)
+    Unknown,
+}
+
+impl Outcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Verified => "verified",
+            Outcome::Rejected => "rejected",
+            Outcome::Unknown => "unknown",
+        }
+    }
 }
 
 #[derive(Identifiable, AsChangeset)]
@@ -37,4 +120,28 @@ pub struct NewRun<'a> {
     pub submitted: Option<&'a str>,
     #[diesel(serialize_as=String)]
     pub run_id: String,
+    pub weblink: Option<&'a str>,
+    pub category_src_id: Option<String>,
+    pub category_values: Option<String>,
+    pub message_id: Option<String>,
+    pub runner_name: Option<&'a str>,
+    pub runner_src_id: Option<&'a str>,
+    pub primary_t: Option<f64>,
+}
+
+impl<'a> NewRun<'a> {
+    /// a run we've just posted about as `message_id` (or decided not to post about at all)
+    pub fn from_src_run(src_run: &'a SRCRun<'_>, message_id: Option<Id<MessageMarker>>) -> Self {
+        Self {
+            submitted: src_run.submitted.as_deref(),
+            run_id: src_run.id.to_string(),
+            weblink: Some(&src_run.weblink),
+            category_src_id: Some(src_run.category.to_string()),
+            category_values: Some(src_run.values_json()),
+            message_id: message_id.map(|m| m.to_string()),
+            runner_name: src_run.player(),
+            runner_src_id: src_run.player_id(),
+            primary_t: Some(src_run.times.primary_t),
+        }
+    }
 }
@@ -0,0 +1,26 @@
+use crate::schema::subscriptions;
+use diesel::prelude::*;
+
+#[derive(Queryable, Identifiable, Debug, Clone)]
+pub struct Subscription {
+    pub id: i32,
+    pub discord_user_id: String,
+    /// "runner" or "category"
+    pub kind: String,
+    /// the runner or category name, lowercased
+    pub target: String,
+    /// whether to DM when a matching run is verified, as well as when it's submitted
+    pub notify_verified: bool,
+    /// a datetime string
+    pub created_at: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "subscriptions"]
+pub struct NewSubscription<'a> {
+    pub discord_user_id: String,
+    pub kind: &'a str,
+    pub target: String,
+    pub notify_verified: bool,
+    pub created_at: String,
+}
@@ -0,0 +1,300 @@
+use crate::discord_client::{BotDiscordClient, DiscordError};
+use crate::error::BotError;
+use crate::metrics::METRICS;
+use async_trait::async_trait;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::env::VarError;
+use std::sync::Arc;
+use std::time::Duration;
+use twilight_model::application::component::Component;
+use twilight_model::channel::embed::Embed;
+use twilight_model::channel::message::AllowedMentions;
+use twilight_model::id::marker::MessageMarker;
+use twilight_model::id::Id;
+use url::Url;
+
+/// everything that goes into a post about a run
+#[derive(Debug, Clone)]
+pub struct RunPost {
+    pub content: Option<String>,
+    pub embeds: Vec<Embed>,
+    /// buttons only work if discord can send us the interactions, so backends that can't do
+    /// that drop these
+    pub components: Vec<Component>,
+    pub allowed_mentions: AllowedMentions,
+}
+
+/// somewhere we can post about runs. both methods sleep off any rate limit they run into, so
+/// callers can fire them off back to back
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    /// returns the id of the message we posted, so that we can edit it later
+    async fn post(&self, post: &RunPost) -> Result<Id<MessageMarker>, DiscordError>;
+
+    /// replaces the contents of a message we posted earlier
+    async fn edit(&self, message_id: Id<MessageMarker>, post: &RunPost)
+        -> Result<(), DiscordError>;
+}
+
+#[async_trait]
+impl Notifier for BotDiscordClient {
+    async fn post(&self, post: &RunPost) -> Result<Id<MessageMarker>, DiscordError> {
+        let rli = self
+            .create_message(
+                post.content.as_deref(),
+                post.embeds.clone(),
+                post.components.clone(),
+                &post.allowed_mentions,
+            )
+            .await?;
+        rli.sleep().await;
+        Ok(rli.item)
+    }
+
+    async fn edit(
+        &self,
+        message_id: Id<MessageMarker>,
+        post: &RunPost,
+    ) -> Result<(), DiscordError> {
+        self.update_message(
+            message_id,
+            post.content.as_deref(),
+            &post.embeds,
+            &post.components,
+            &post.allowed_mentions,
+        )
+        .await?
+        .sleep()
+        .await;
+        Ok(())
+    }
+}
+
+/// posts to a discord webhook, for servers that don't want the bot in them
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+    /// `https://discord.com/api/webhooks/{id}/{token}`
+    url: Url,
+    username: Option<String>,
+    avatar_url: Option<String>,
+}
+
+#[derive(Serialize)]
+struct WebhookBody<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<&'a str>,
+    embeds: &'a [Embed],
+    allowed_mentions: &'a AllowedMentions,
+    // these can only be set when executing, not when editing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    username: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    avatar_url: Option<&'a str>,
+}
+
+/// the only part of the message discord sends back that we care about
+#[derive(Deserialize)]
+struct WebhookMessage {
+    id: Id<MessageMarker>,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: Url, username: Option<String>, avatar_url: Option<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url,
+            username,
+            avatar_url,
+        }
+    }
+
+    /// reads `WEBHOOK_URL`, and optionally `WEBHOOK_USERNAME` and `WEBHOOK_AVATAR_URL`
+    pub fn new_from_env() -> Result<Self, BotError> {
+        let url = Url::parse(&env::var("WEBHOOK_URL")?)
+            .map_err(|e| BotError::InvalidConfig(format!("Invalid WEBHOOK_URL: {}", e)))?;
+        Ok(Self::new(
+            url,
+            env::var("WEBHOOK_USERNAME").ok(),
+            env::var("WEBHOOK_AVATAR_URL").ok(),
+        ))
+    }
+
+    fn body<'a>(&'a self, post: &'a RunPost, executing: bool) -> WebhookBody<'a> {
+        if !post.components.is_empty() {
+            // webhooks we don't own can't have components at all
+            warn!(
+                "Dropping {} components from webhook post",
+                post.components.len()
+            );
+        }
+        WebhookBody {
+            content: post.content.as_deref(),
+            embeds: &post.embeds,
+            allowed_mentions: &post.allowed_mentions,
+            username: self.username.as_deref().filter(|_| executing),
+            avatar_url: self.avatar_url.as_deref().filter(|_| executing),
+        }
+    }
+
+    async fn send(
+        &self,
+        req: reqwest::RequestBuilder,
+        body: &WebhookBody<'_>,
+    ) -> Result<reqwest::Response, DiscordError> {
+        let body =
+            serde_json::to_vec(body).map_err(|e| DiscordError::WebhookError(e.to_string()))?;
+        let resp = req
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| DiscordError::WebhookError(e.to_string()))?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let text = resp.text().await.unwrap_or_default();
+            return Err(DiscordError::WebhookError(format!("{}: {}", status, text)));
+        }
+        if let Some(sleep_time) = webhook_sleep_time(resp.headers()) {
+            METRICS.record_rate_limit_sleep(sleep_time);
+            tokio::time::sleep(sleep_time).await;
+        }
+        Ok(resp)
+    }
+}
+
+/// how long discord wants us to wait before using the webhook again, if we've used it up
+fn webhook_sleep_time(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name)?.to_str().ok()?.parse::<f64>().ok();
+    if header("x-ratelimit-remaining")? > 0.0 {
+        return None;
+    }
+    Some(Duration::from_secs_f64(header("x-ratelimit-reset-after")?))
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    async fn post(&self, post: &RunPost) -> Result<Id<MessageMarker>, DiscordError> {
+        let mut url = self.url.clone();
+        // without this discord doesn't tell us the message id
+        url.query_pairs_mut().append_pair("wait", "true");
+        let resp = self
+            .send(self.client.post(url), &self.body(post, true))
+            .await?;
+        let bytes = resp
+            .bytes()
+            .await
+            .map_err(|e| DiscordError::WebhookError(e.to_string()))?;
+        let message = serde_json::from_slice::<WebhookMessage>(&bytes)
+            .map_err(|e| DiscordError::WebhookError(e.to_string()))?;
+        Ok(message.id)
+    }
+
+    async fn edit(
+        &self,
+        message_id: Id<MessageMarker>,
+        post: &RunPost,
+    ) -> Result<(), DiscordError> {
+        let url = format!(
+            "{}/messages/{}",
+            self.url.as_str().trim_end_matches('/'),
+            message_id
+        );
+        self.send(self.client.patch(url), &self.body(post, false))
+            .await?;
+        Ok(())
+    }
+}
+
+/// the bot, unless `NOTIFIER` is "webhook". posting through a webhook is for servers that don't
+/// want the bot in them, so there's no bot to log in as, and nothing that needs one can be used
+pub fn bot_client_from_env() -> Result<Option<Arc<BotDiscordClient>>, BotError> {
+    match env::var("NOTIFIER") {
+        Ok(n) if n == "webhook" => Ok(None),
+        Ok(n) if n == "bot" => Ok(Some(Arc::new(BotDiscordClient::new_from_env()?))),
+        Ok(n) => Err(BotError::InvalidConfig(format!("Unknown NOTIFIER: {}", n))),
+        Err(VarError::NotPresent) => Ok(Some(Arc::new(BotDiscordClient::new_from_env()?))),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// posts as the bot if we have one (see `bot_client_from_env`), otherwise to `WEBHOOK_URL`
+pub fn notifier_from_env(
+    discord_client: Option<Arc<BotDiscordClient>>,
+) -> Result<Arc<dyn Notifier>, BotError> {
+    let notifier: Arc<dyn Notifier> = match discord_client {
+        Some(c) => c,
+        None => Arc::new(WebhookNotifier::new_from_env()?),
+    };
+    Ok(notifier)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::embeds::EmbedBuilder;
+    use crate::mock_server::{response, MockServer};
+    use crate::notifier::{Notifier, RunPost, WebhookNotifier};
+    use hyper::{Method, StatusCode};
+    use twilight_model::channel::message::AllowedMentions;
+    use twilight_model::id::Id;
+
+    /// stands in for discord's webhook endpoints, answering everything with a message with id 42
+    fn mock_discord() -> MockServer {
+        MockServer::start(|_, _| response(StatusCode::OK, r#"{"id":"42"}"#))
+    }
+
+    fn post() -> RunPost {
+        RunPost {
+            content: Some("<@1>".to_string()),
+            embeds: vec![EmbedBuilder::new().title("New PB arrived!").build()],
+            components: vec![],
+            allowed_mentions: AllowedMentions {
+                users: vec![Id::new(1)],
+                ..Default::default()
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_webhook_post() {
+        let discord = mock_discord();
+        let notifier = WebhookNotifier::new(
+            discord.url("/api/webhooks/1234/secret-token"),
+            Some("Queue Bot".to_string()),
+            Some("https://example.com/avatar.png".to_string()),
+        );
+        assert_eq!(Id::new(42), notifier.post(&post()).await.unwrap());
+        let seen = discord.seen();
+        assert_eq!(1, seen.len());
+        assert_eq!(Method::POST, seen[0].method);
+        assert_eq!("/api/webhooks/1234/secret-token", seen[0].path);
+        assert_eq!(Some("wait=true"), seen[0].query.as_deref());
+        let body = seen[0].body.as_ref().unwrap();
+        assert_eq!("Queue Bot", body["username"]);
+        assert_eq!("https://example.com/avatar.png", body["avatar_url"]);
+        assert_eq!("<@1>", body["content"]);
+        assert_eq!("New PB arrived!", body["embeds"][0]["title"]);
+        assert_eq!(serde_json::json!(["1"]), body["allowed_mentions"]["users"]);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_edit() {
+        let discord = mock_discord();
+        let notifier = WebhookNotifier::new(
+            discord.url("/api/webhooks/1234/secret-token"),
+            Some("Queue Bot".to_string()),
+            None,
+        );
+        notifier.edit(Id::new(42), &post()).await.unwrap();
+        let seen = discord.seen();
+        assert_eq!(1, seen.len());
+        assert_eq!(Method::PATCH, seen[0].method);
+        assert_eq!("/api/webhooks/1234/secret-token/messages/42", seen[0].path);
+        let body = seen[0].body.as_ref().unwrap();
+        // discord won't let us change these on an existing message
+        assert!(body.get("username").is_none());
+        assert!(body.get("avatar_url").is_none());
+        assert_eq!("New PB arrived!", body["embeds"][0]["title"]);
+    }
+}
@@ -0,0 +1,213 @@
+use crate::error::BotError;
+use crate::models::reactions::NewRunReaction;
+use crate::models::runs::Run;
+use crate::schema;
+use crate::utils::{format_timestamp, timestamp_now};
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+use log::info;
+use std::env;
+use std::env::VarError;
+use std::num::NonZeroU64;
+use twilight_model::channel::{Reaction, ReactionType};
+use twilight_model::id::marker::RoleMarker;
+use twilight_model::id::Id;
+
+/// reactions on run posts that mean something to us
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModReaction {
+    /// ✅
+    Verify,
+    /// ❌
+    Reject,
+    /// ⏰: don't remind anyone about this run for a while
+    Snooze,
+}
+
+impl ModReaction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ModReaction::Verify => "verify",
+            ModReaction::Reject => "reject",
+            ModReaction::Snooze => "snooze",
+        }
+    }
+
+    fn from_emoji(emoji: &ReactionType) -> Option<Self> {
+        let name = match emoji {
+            ReactionType::Unicode { name } => name,
+            ReactionType::Custom { .. } => return None,
+        };
+        // some clients send emoji with a trailing variation selector
+        match name.trim_end_matches('\u{fe0f}') {
+            "\u{2705}" => Some(ModReaction::Verify),
+            "\u{274c}" => Some(ModReaction::Reject),
+            "\u{23f0}" => Some(ModReaction::Snooze),
+            _ => None,
+        }
+    }
+}
+
+/// settings for moderating runs by reacting to their posts
+pub struct ReactionConfig {
+    /// reactions from anyone without this role are ignored
+    pub role_id: Id<RoleMarker>,
+    /// how long ⏰ puts off staleness reminders for
+    pub snooze: Duration,
+}
+
+impl ReactionConfig {
+    /// returns `None` if reaction moderation isn't configured (i.e. `REACTION_ROLE_ID` is unset)
+    pub fn from_env() -> Result<Option<Self>, BotError> {
+        let role_id = match env::var("REACTION_ROLE_ID") {
+            Ok(r) => Id::<RoleMarker>::from(r.parse::<NonZeroU64>()?),
+            Err(VarError::NotPresent) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let snooze_days = match env::var("SNOOZE_DAYS") {
+            Ok(d) => d.parse::<u32>()?,
+            Err(_) => 3,
+        };
+        Ok(Some(Self {
+            role_id,
+            snooze: Duration::days(snooze_days as i64),
+        }))
+    }
+}
+
+fn run_for_message(
+    reaction: &Reaction,
+    conn: &mut SqliteConnection,
+) -> Result<Option<Run>, BotError> {
+    use schema::runs::dsl::*;
+    Ok(runs
+        .filter(message_id.eq(reaction.message_id.to_string()))
+        .first::<Run>(conn)
+        .optional()?)
+}
+
+/// records a reaction against the run whose post it was added to. ignores reactions from people
+/// without the configured role, and reactions on anything other than run posts
+pub fn record_reaction(
+    config: &ReactionConfig,
+    reaction: &Reaction,
+    conn: &mut SqliteConnection,
+) -> Result<(), BotError> {
+    let mod_reaction = match ModReaction::from_emoji(&reaction.emoji) {
+        Some(r) => r,
+        None => return Ok(()),
+    };
+    let has_role = reaction
+        .member
+        .as_ref()
+        .map(|m| m.roles.contains(&config.role_id))
+        .unwrap_or(false);
+    if !has_role {
+        return Ok(());
+    }
+    let run = match run_for_message(reaction, conn)? {
+        Some(r) => r,
+        None => return Ok(()),
+    };
+    info!(
+        "{} reacted {} on run {}",
+        reaction.user_id,
+        mod_reaction.as_str(),
+        run.run_id
+    );
+    diesel::insert_or_ignore_into(schema::run_reactions::table)
+        .values(NewRunReaction {
+            run_id: run.id,
+            discord_user_id: reaction.user_id.to_string(),
+            reaction: mod_reaction.as_str(),
+            reacted_at: timestamp_now(),
+        })
+        .execute(conn)?;
+    if mod_reaction == ModReaction::Snooze {
+        use schema::runs::dsl::*;
+        // clearing the reminder means we'll remind again once the snooze is over, if the run is
+        // still waiting
+        diesel::update(&run)
+            .set((
+                snoozed_until.eq(format_timestamp(Utc::now() + config.snooze)),
+                stale_reminder_sent_at.eq(None::<String>),
+            ))
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+/// forgets a reaction that's been taken back. we don't get told the reactor's roles here, but
+/// we only ever recorded reactions from people with the right role anyway
+pub fn remove_reaction(reaction: &Reaction, conn: &mut SqliteConnection) -> Result<(), BotError> {
+    use schema::run_reactions::dsl as rr;
+    let mod_reaction = match ModReaction::from_emoji(&reaction.emoji) {
+        Some(r) => r,
+        None => return Ok(()),
+    };
+    let run = match run_for_message(reaction, conn)? {
+        Some(r) => r,
+        None => return Ok(()),
+    };
+    diesel::delete(
+        rr::run_reactions
+            .filter(rr::run_id.eq(run.id))
+            .filter(rr::discord_user_id.eq(reaction.user_id.to_string()))
+            .filter(rr::reaction.eq(mod_reaction.as_str())),
+    )
+    .execute(conn)?;
+    if mod_reaction == ModReaction::Snooze {
+        let snoozes_left = rr::run_reactions
+            .filter(rr::run_id.eq(run.id))
+            .filter(rr::reaction.eq(ModReaction::Snooze.as_str()))
+            .count()
+            .get_result::<i64>(conn)?;
+        if snoozes_left == 0 {
+            diesel::update(&run)
+                .set(schema::runs::snoozed_until.eq(None::<String>))
+                .execute(conn)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::reactions::ModReaction;
+    use twilight_model::channel::ReactionType;
+    use twilight_model::id::Id;
+
+    fn unicode(name: &str) -> ReactionType {
+        ReactionType::Unicode {
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_from_emoji() {
+        assert_eq!(
+            Some(ModReaction::Verify),
+            ModReaction::from_emoji(&unicode("✅"))
+        );
+        assert_eq!(
+            Some(ModReaction::Reject),
+            ModReaction::from_emoji(&unicode("❌"))
+        );
+        assert_eq!(
+            Some(ModReaction::Snooze),
+            ModReaction::from_emoji(&unicode("⏰"))
+        );
+        assert_eq!(
+            Some(ModReaction::Snooze),
+            ModReaction::from_emoji(&unicode("⏰\u{fe0f}"))
+        );
+        assert_eq!(None, ModReaction::from_emoji(&unicode("🎉")));
+        let custom = ReactionType::Custom {
+            animated: false,
+            id: Id::new(1234),
+            name: Some("\u{2705}".to_string()),
+        };
+        assert_eq!(None, ModReaction::from_emoji(&custom));
+    }
+}
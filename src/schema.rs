@@ -7,12 +7,102 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    digests (id) {
+        id -> Integer,
+        period -> Text,
+        sent_at -> Text,
+    }
+}
+
 diesel::table! {
     runs (id) {
         id -> Integer,
         submitted -> Nullable<Text>,
         run_id -> Text,
+        left_queue_at -> Nullable<Text>,
+        stale_reminder_sent_at -> Nullable<Text>,
+        weblink -> Nullable<Text>,
+        category_src_id -> Nullable<Text>,
+        category_values -> Nullable<Text>,
+        outcome -> Nullable<Text>,
+        decided_at -> Nullable<Text>,
+        examiner_src_id -> Nullable<Text>,
+        examiner_name -> Nullable<Text>,
+        wait_secs -> Nullable<Integer>,
+        claimed_by_discord_id -> Nullable<Text>,
+        claimed_by_name -> Nullable<Text>,
+        claimed_at -> Nullable<Text>,
+        message_id -> Nullable<Text>,
+        snoozed_until -> Nullable<Text>,
+        runner_name -> Nullable<Text>,
+        runner_src_id -> Nullable<Text>,
+        primary_t -> Nullable<Double>,
+    }
+}
+
+diesel::table! {
+    linked_accounts (id) {
+        id -> Integer,
+        discord_user_id -> Text,
+        src_user_id -> Text,
+        src_name -> Text,
+        linked_at -> Text,
+    }
+}
+
+diesel::table! {
+    run_reactions (id) {
+        id -> Integer,
+        run_id -> Integer,
+        discord_user_id -> Text,
+        reaction -> Text,
+        reacted_at -> Text,
+    }
+}
+
+diesel::table! {
+    src_decisions (id) {
+        id -> Integer,
+        run_id -> Text,
+        discord_user_id -> Text,
+        discord_user_name -> Text,
+        decision -> Text,
+        reason -> Nullable<Text>,
+        attempted_at -> Text,
+        error -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    subscriptions (id) {
+        id -> Integer,
+        discord_user_id -> Text,
+        kind -> Text,
+        target -> Text,
+        notify_verified -> Bool,
+        created_at -> Text,
+    }
+}
+
+diesel::table! {
+    pending_events (id) {
+        id -> Integer,
+        endpoint -> Text,
+        body -> Text,
+        created_at -> Text,
     }
 }
 
-diesel::allow_tables_to_appear_in_same_query!(category_aliases, runs,);
+diesel::joinable!(run_reactions -> runs (run_id));
+
+diesel::allow_tables_to_appear_in_same_query!(
+    category_aliases,
+    digests,
+    linked_accounts,
+    pending_events,
+    run_reactions,
+    runs,
+    src_decisions,
+    subscriptions,
+);
@@ -0,0 +1,113 @@
+use crate::error::BotError;
+use futures_util::future::join_all;
+use log::info;
+use std::future::Future;
+use std::sync::Mutex;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// background work that shouldn't be cut off part way through by a shutdown (event deliveries,
+/// decisions being pushed to SRC). this is a global for the same reason `METRICS` is
+pub static IN_FLIGHT: InFlight = InFlight::new();
+
+/// resolves once we've been asked to stop, with SIGTERM (systemd) or SIGINT (ctrl-c). the
+/// handlers are installed when this is called rather than when it's first polled, so a signal
+/// that arrives in between isn't lost and doesn't kill us outright
+pub fn shutdown_signal() -> Result<impl Future<Output = ()>, BotError> {
+    let install = |kind: SignalKind| {
+        signal(kind)
+            .map_err(|e| BotError::InvalidState(format!("Unable to install signal handler: {}", e)))
+    };
+    let mut sigterm = install(SignalKind::terminate())?;
+    let mut sigint = install(SignalKind::interrupt())?;
+    Ok(async move {
+        tokio::select! {
+            _ = sigterm.recv() => info!("Got SIGTERM"),
+            _ = sigint.recv() => info!("Got SIGINT"),
+        }
+    })
+}
+
+/// resolves once `true` has been sent down `stop` (or the sender's gone), for telling the
+/// gateway and web server that we're shutting down
+pub async fn stopped(mut stop: watch::Receiver<bool>) {
+    while !*stop.borrow() {
+        if stop.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+pub struct InFlight {
+    tasks: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl InFlight {
+    const fn new() -> Self {
+        Self {
+            tasks: Mutex::new(vec![]),
+        }
+    }
+
+    /// like `tokio::spawn`, but `wait` will wait for it
+    pub fn spawn(&self, task: impl Future<Output = ()> + Send + 'static) {
+        let handle = tokio::spawn(task);
+        if let Ok(mut tasks) = self.tasks.lock() {
+            tasks.retain(|t| !t.is_finished());
+            tasks.push(handle);
+        }
+    }
+
+    /// waits for everything that's been spawned (including anything spawned while we wait) to
+    /// finish
+    pub async fn wait(&self) {
+        loop {
+            let tasks = match self.tasks.lock() {
+                Ok(mut tasks) => std::mem::take(&mut *tasks),
+                Err(_) => return,
+            };
+            if tasks.is_empty() {
+                return;
+            }
+            join_all(tasks).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shutdown::{stopped, InFlight};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::watch;
+
+    #[tokio::test]
+    async fn test_wait_for_in_flight() {
+        let in_flight = InFlight::new();
+        let finished = Arc::new(AtomicBool::new(false));
+        let finished_by_task = finished.clone();
+        in_flight.spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            finished_by_task.store(true, Ordering::SeqCst);
+        });
+        in_flight.wait().await;
+        assert!(finished.load(Ordering::SeqCst));
+        // nothing left to wait for
+        in_flight.wait().await;
+    }
+
+    #[tokio::test]
+    async fn test_stopped() {
+        let (stop, receiver) = watch::channel(false);
+        let waiting = tokio::spawn(stopped(receiver));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!waiting.is_finished());
+        stop.send(true).unwrap();
+        tokio::time::timeout(Duration::from_secs(1), waiting)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
@@ -1,12 +1,12 @@
 use crate::error::BotError;
 use crate::models::aliases::CategoryAlias;
-use crate::src::{get_categories, Category, SRCRun, Value};
+use crate::models::runs::Run;
+use crate::src::{get_categories, Category, SRCClient, SRCRun, Value};
 use diesel::prelude::*;
 use diesel::SqliteConnection;
 use speedrun_api::api::categories::CategoryId;
 use speedrun_api::api::games::GameId;
 use speedrun_api::api::variables::{ValueId, VariableId};
-use speedrun_api::SpeedrunApiClientAsync;
 use std::collections::HashMap;
 
 // i think this is kind of a bastardization of the ~*~Design Pattern~*~ Repository
@@ -31,12 +31,14 @@ impl<'a> CategoriesRepository<'a> {
         }
     }
 
-    /// fetches data from the SRC API & local DB and creates a CategoriesRepository
-    pub async fn new_with_fetch<'b, GID: Into<GameId<'a>>>(
+    /// fetches data from the SRC API & local DB and creates a CategoriesRepository. nothing in
+    /// here borrows from `src_client`, so with a `'static` game id this can be shared with
+    /// other tasks
+    pub async fn new_with_fetch<GID: Into<GameId<'a>>>(
         game_id: GID,
-        src_client: &'b SpeedrunApiClientAsync,
+        src_client: &SRCClient,
         conn: &mut SqliteConnection,
-    ) -> Result<CategoriesRepository<'b>, BotError> {
+    ) -> Result<CategoriesRepository<'a>, BotError> {
         let gid = game_id.into();
         let categories = get_categories(gid.clone(), src_client).await?;
         let aliases = CategoryAlias::by_game_id(&gid.to_string()).load(conn)?;
@@ -96,7 +98,45 @@ impl<'a> CategoriesRepository<'a> {
         self.category_name(&run.category, &run.values)
     }
 
+    /// like `category_name_from_run`, but for a run we've stored in the db
+    pub fn category_name_from_db_run(&self, run: &Run) -> Option<String> {
+        let category_id = CategoryId::from(run.category_src_id.clone()?);
+        self.category_name(&category_id, &Self::db_run_values(run))
+    }
 
+    /// the (variable id, value id) of a db run's subcategory, if its category has one. these are
+    /// what pick out the run's leaderboard within its category
+    pub fn subcategory_values_from_db_run(&self, run: &Run) -> Vec<(String, String)> {
+        let cat = match run
+            .category_src_id
+            .clone()
+            .and_then(|c| self.categories.get(&CategoryId::from(c)))
+        {
+            Some(c) => c,
+            None => return vec![],
+        };
+        let values = Self::db_run_values(run);
+        cat.variables
+            .data
+            .iter()
+            .filter(|var| var.is_subcategory)
+            .filter_map(|var| {
+                values
+                    .get(&var.id)
+                    .map(|val| (var.id.to_string(), val.to_string()))
+            })
+            .collect()
+    }
+
+    fn db_run_values(run: &Run) -> HashMap<VariableId<'a>, ValueId<'a>> {
+        run.category_values
+            .as_ref()
+            .and_then(|v| serde_json::from_str::<HashMap<String, String>>(v).ok())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(var, val)| (VariableId::from(var), ValueId::from(val)))
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,292 @@
+use crate::src::replay::{Recorder, Replayer};
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::request::Builder;
+use http::{Request, Response, StatusCode};
+use log::{debug, warn};
+use speedrun_api::api::{ApiError, AsyncClient, RestClient};
+use speedrun_api::error::RestError;
+use speedrun_api::SpeedrunApiClientAsync;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use url::Url;
+
+/// SRC documents a limit of 100 requests per minute; we stay a little under that so that
+/// clock skew between us and them doesn't get us a 420
+const REQUESTS_PER_MINUTE: u32 = 90;
+/// how many times to re-send a request that got a 420/429/5xx before giving up on it
+const MAX_RETRIES: u32 = 4;
+/// backoff for the first retry; doubles on each subsequent one
+const INITIAL_BACKOFF: Duration = Duration::from_secs(2);
+
+/// A wrapper around the speedrun-api client that throttles every request we make with a token
+/// bucket and retries the ones SRC tells us to back off on.
+///
+/// This sits at the `AsyncClient` level (rather than around our `get_*` functions) so that
+/// every page of a paginated query is throttled individually.
+pub struct SRCClient {
+    inner: SpeedrunApiClientAsync,
+    bucket: TokenBucket,
+    fixtures: Option<Fixtures>,
+}
+
+enum Fixtures {
+    Record(Recorder),
+    /// nothing goes over the network at all
+    Replay(Replayer),
+}
+
+impl SRCClient {
+    pub fn new(inner: SpeedrunApiClientAsync) -> Self {
+        Self {
+            inner,
+            bucket: TokenBucket::new(REQUESTS_PER_MINUTE, Duration::from_secs(60)),
+            fixtures: None,
+        }
+    }
+
+    /// like `new`, but saves every response SRC gives us to `dir`
+    pub fn recording(inner: SpeedrunApiClientAsync, dir: impl Into<PathBuf>) -> io::Result<Self> {
+        Ok(Self {
+            fixtures: Some(Fixtures::Record(Recorder::new(dir)?)),
+            ..Self::new(inner)
+        })
+    }
+
+    /// answers every request with a response saved by `recording` to one of `dirs` (see
+    /// `Replayer::new`), instead of asking SRC. `inner` is only used to build urls
+    pub fn replaying(
+        inner: SpeedrunApiClientAsync,
+        dirs: impl IntoIterator<Item = impl AsRef<Path>>,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            fixtures: Some(Fixtures::Replay(Replayer::new(dirs)?)),
+            ..Self::new(inner)
+        })
+    }
+
+    /// reads `SRC_RECORD_DIR`, which turns on recording
+    pub fn new_from_env(inner: SpeedrunApiClientAsync) -> io::Result<Self> {
+        match std::env::var("SRC_RECORD_DIR") {
+            Ok(dir) => Self::recording(inner, dir),
+            Err(_) => Ok(Self::new(inner)),
+        }
+    }
+
+    /// this client without the throttling or retries, for requests that someone is waiting on
+    /// and that we make rarely enough not to need them
+    pub fn single_attempt(&self) -> SingleAttempt<'_> {
+        SingleAttempt(self)
+    }
+}
+
+/// see `SRCClient::single_attempt`. still replays/records like the client it came from
+pub struct SingleAttempt<'a>(&'a SRCClient);
+
+impl RestClient for SRCClient {
+    type Error = RestError;
+
+    fn rest_endpoint(&self, endpoint: &str) -> Result<Url, ApiError<Self::Error>> {
+        self.inner.rest_endpoint(endpoint)
+    }
+}
+
+#[async_trait]
+impl AsyncClient for SRCClient {
+    async fn rest_async(
+        &self,
+        request: Builder,
+        body: Vec<u8>,
+    ) -> Result<Response<Bytes>, ApiError<Self::Error>> {
+        // recorded responses are the ones we ended up with, so replaying them skips the
+        // throttling and retries too
+        if let Some(Fixtures::Replay(replayer)) = &self.fixtures {
+            return Ok(replayer.next(&request));
+        }
+        let mut attempt = 0;
+        loop {
+            self.bucket.acquire().await;
+            let resp = self
+                .inner
+                .rest_async(copy_builder(&request), body.clone())
+                .await?;
+            let status = resp.status();
+            if !should_retry(status) || attempt >= MAX_RETRIES {
+                if let Some(Fixtures::Record(recorder)) = &self.fixtures {
+                    recorder.record(&request, &resp);
+                }
+                return Ok(resp);
+            }
+            let backoff = retry_after(&resp).unwrap_or(INITIAL_BACKOFF * 2u32.pow(attempt));
+            warn!(
+                "SRC responded {} to {:?}; retrying in {:?} (attempt {} of {})",
+                status,
+                request.uri_ref(),
+                backoff,
+                attempt + 1,
+                MAX_RETRIES
+            );
+            tokio::time::sleep(backoff).await;
+            attempt += 1;
+        }
+    }
+}
+
+impl RestClient for SingleAttempt<'_> {
+    type Error = RestError;
+
+    fn rest_endpoint(&self, endpoint: &str) -> Result<Url, ApiError<Self::Error>> {
+        self.0.rest_endpoint(endpoint)
+    }
+}
+
+#[async_trait]
+impl AsyncClient for SingleAttempt<'_> {
+    async fn rest_async(
+        &self,
+        request: Builder,
+        body: Vec<u8>,
+    ) -> Result<Response<Bytes>, ApiError<Self::Error>> {
+        if let Some(Fixtures::Replay(replayer)) = &self.0.fixtures {
+            return Ok(replayer.next(&request));
+        }
+        let resp = self
+            .0
+            .inner
+            .rest_async(copy_builder(&request), body)
+            .await?;
+        if let Some(Fixtures::Record(recorder)) = &self.0.fixtures {
+            recorder.record(&request, &resp);
+        }
+        Ok(resp)
+    }
+}
+
+/// `Builder` isn't `Clone`, so we rebuild it from its parts for each attempt
+fn copy_builder(request: &Builder) -> Builder {
+    let mut b = Request::builder();
+    if let Some(method) = request.method_ref() {
+        b = b.method(method.clone());
+    }
+    if let Some(uri) = request.uri_ref() {
+        b = b.uri(uri.clone());
+    }
+    if let Some(headers) = request.headers_ref() {
+        for (name, value) in headers {
+            b = b.header(name, value);
+        }
+    }
+    b
+}
+
+/// SRC uses 420 (!) for rate limiting, but we'll also respect a normal 429
+fn should_retry(status: StatusCode) -> bool {
+    status.as_u16() == 420 || status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+fn retry_after<T>(resp: &Response<T>) -> Option<Duration> {
+    let secs = resp
+        .headers()
+        .get(http::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .parse::<u64>()
+        .ok()?;
+    Some(Duration::from_secs(secs))
+}
+
+/// A simple token bucket: holds up to `capacity` tokens and refills continuously such that
+/// `capacity` tokens are restored over `per`.
+pub struct TokenBucket {
+    capacity: f64,
+    refill_per_sec: f64,
+    state: Mutex<BucketState>,
+}
+
+struct BucketState {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl BucketState {
+    /// takes a token if one is available, otherwise returns how long until one will be
+    fn take(&mut self, now: Instant, capacity: f64, refill_per_sec: f64) -> Option<Duration> {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * refill_per_sec).min(capacity);
+        self.last_refill = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64(
+                (1.0 - self.tokens) / refill_per_sec,
+            ))
+        }
+    }
+}
+
+impl TokenBucket {
+    pub fn new(capacity: u32, per: Duration) -> Self {
+        let capacity = capacity as f64;
+        Self {
+            capacity,
+            refill_per_sec: capacity / per.as_secs_f64(),
+            state: Mutex::new(BucketState {
+                tokens: capacity,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// waits until a token is available, then takes it
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().await;
+                match state.take(Instant::now(), self.capacity, self.refill_per_sec) {
+                    Some(w) => w,
+                    None => return,
+                }
+            };
+            debug!("Out of SRC request tokens, sleeping for {:?}", wait);
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::src::client::{should_retry, BucketState};
+    use http::StatusCode;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_bucket_drains_and_refills() {
+        let start = Instant::now();
+        let mut state = BucketState {
+            tokens: 2.0,
+            last_refill: start,
+        };
+        assert_eq!(None, state.take(start, 2.0, 1.0));
+        assert_eq!(None, state.take(start, 2.0, 1.0));
+        assert_eq!(Some(Duration::from_secs(1)), state.take(start, 2.0, 1.0));
+        // refilling never exceeds capacity
+        let later = start + Duration::from_secs(10);
+        assert_eq!(None, state.take(later, 2.0, 1.0));
+        assert_eq!(None, state.take(later, 2.0, 1.0));
+        assert!(state.take(later, 2.0, 1.0).is_some());
+    }
+
+    #[test]
+    fn test_should_retry() {
+        assert!(should_retry(StatusCode::from_u16(420).unwrap()));
+        assert!(should_retry(StatusCode::TOO_MANY_REQUESTS));
+        assert!(should_retry(StatusCode::BAD_GATEWAY));
+        assert!(!should_retry(StatusCode::NOT_FOUND));
+        assert!(!should_retry(StatusCode::OK));
+    }
+}
@@ -0,0 +1,118 @@
+use crate::src::{SRCClient, SRCError, Times};
+use crate::ALTTP_GAME_ID;
+use http::{Method, Request};
+use serde::Deserialize;
+use speedrun_api::api::{AsyncClient, RestClient, Root};
+
+#[derive(Deserialize, Debug)]
+struct Leaderboard {
+    runs: Vec<PlacedRun>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PlacedRun {
+    place: u32,
+    run: LeaderboardRun,
+}
+
+#[derive(Deserialize, Debug)]
+struct LeaderboardRun {
+    id: String,
+    times: Times,
+}
+
+/// where a run sits on its leaderboard
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placement {
+    /// 1 is a world record
+    pub place: u32,
+    /// in seconds
+    pub primary_t: f64,
+}
+
+fn find_placement(leaderboard: Leaderboard, run_id: &str) -> Option<Placement> {
+    leaderboard
+        .runs
+        .into_iter()
+        .find(|r| r.run.id == run_id)
+        .map(|r| Placement {
+            place: r.place,
+            primary_t: r.run.times.primary_t,
+        })
+}
+
+/// finds `run_id` on the leaderboard for `category_id`, narrowed down by `subcategory`
+/// (variable id, value id) pairs. returns `None` if it isn't there, which for a verified run
+/// means it's been beaten by the same runner's PB
+pub async fn get_leaderboard_placement(
+    src_client: &SRCClient,
+    category_id: &str,
+    subcategory: &[(String, String)],
+    run_id: &str,
+) -> Result<Option<Placement>, SRCError> {
+    let endpoint = format!("leaderboards/{}/category/{}", ALTTP_GAME_ID, category_id);
+    let mut url = src_client
+        .rest_endpoint(&endpoint)
+        .map_err(SRCError::api(endpoint.as_str()))?;
+    for (var, val) in subcategory {
+        url.query_pairs_mut()
+            .append_pair(&format!("var-{}", var), val);
+    }
+    let request = Request::builder().method(Method::GET).uri(url.as_str());
+    let resp = src_client
+        .rest_async(request, vec![])
+        .await
+        .map_err(SRCError::api(endpoint.as_str()))?;
+    if !resp.status().is_success() {
+        return Err(SRCError::UnexpectedResponse {
+            endpoint,
+            status: resp.status().as_u16(),
+            body: String::from_utf8_lossy(resp.body()).into_owned(),
+        });
+    }
+    let leaderboard = serde_json::from_slice::<Root<Leaderboard>>(resp.body()).map_err(|e| {
+        SRCError::UnexpectedResponse {
+            endpoint,
+            status: resp.status().as_u16(),
+            body: e.to_string(),
+        }
+    })?;
+    Ok(find_placement(leaderboard.data, run_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::src::leaderboards::{find_placement, Leaderboard, Placement};
+    use speedrun_api::api::Root;
+
+    // trimmed down from a real response
+    const LEADERBOARD: &str = r#"{"data": {
+        "weblink": "https://www.speedrun.com/alttp#No_Major_Glitches",
+        "game": "9d3rr0dl",
+        "category": "9d8jgv7k",
+        "runs": [
+            {"place": 1, "run": {"id": "aaaaaaaa", "times": {"primary": "PT1H24M", "primary_t": 5040}}},
+            {"place": 2, "run": {"id": "bbbbbbbb", "times": {"primary": "PT1H25M", "primary_t": 5100}}},
+            {"place": 2, "run": {"id": "cccccccc", "times": {"primary": "PT1H25M", "primary_t": 5100}}}
+        ]
+    }}"#;
+
+    #[test]
+    fn test_find_placement() {
+        let lb = || {
+            serde_json::from_str::<Root<Leaderboard>>(LEADERBOARD)
+                .unwrap()
+                .data
+        };
+        assert_eq!(
+            Some(Placement {
+                place: 1,
+                primary_t: 5040.0
+            }),
+            find_placement(lb(), "aaaaaaaa")
+        );
+        // ties share a place
+        assert_eq!(Some(2), find_placement(lb(), "cccccccc").map(|p| p.place));
+        assert_eq!(None, find_placement(lb(), "dddddddd"));
+    }
+}
@@ -1,53 +1,128 @@
 mod category_repository;
+mod client;
+mod leaderboards;
+mod replay;
+mod status;
 
+use crate::error::ErrorClass;
 use crate::ALTTP_GAME_ID;
 
 use futures_util::StreamExt;
+use http::{Method, Request};
 use serde::Deserialize;
 use speedrun_api::api;
 use speedrun_api::api::categories::{CategoryEmbeds, CategoryId};
 use speedrun_api::api::games::{GameCategories, GameCategoriesBuilderError, GameId};
 use speedrun_api::api::runs::{Run, RunBuilderError, RunEmbeds, RunId, Runs, RunsBuilderError};
+use speedrun_api::api::users::{User, UserBuilderError, UserId};
 use speedrun_api::api::variables::{ValueId, VariableId};
-use speedrun_api::api::{ApiError, AsyncQuery, PagedEndpointExt, Root};
+use speedrun_api::api::{ApiError, AsyncClient, AsyncQuery, PagedEndpointExt, RestClient, Root};
 use speedrun_api::error::RestError;
 use speedrun_api::types::{Names, Status};
-use speedrun_api::SpeedrunApiClientAsync;
 use std::collections::HashMap;
 
-// pub type SRCError = ApiError<RestError>;
-
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 pub enum SRCError {
     /// error getting response from SRC API
-    ApiError(ApiError<RestError>),
+    #[error("SRC API error ({endpoint}): {source}")]
+    ApiError {
+        /// what we were asking for, e.g. `runs/y8dwozoj`
+        endpoint: String,
+        source: ApiError<RestError>,
+    },
     /// error constructing the query
-    /// (stringified because I don't care that much about every variant)
-    QueryBuildError(String),
+    #[error("Unable to build SRC query: {0}")]
+    QueryBuildError(#[from] QueryBuildError),
+    /// a page of a paginated query failed, even after retrying
+    #[error("Fetching {endpoint} failed after {fetched} items: {source}")]
+    PageFetchError {
+        endpoint: String,
+        /// how many items we'd successfully fetched before the failure
+        fetched: usize,
+        source: ApiError<RestError>,
+    },
+    /// SRC answered, but not with what we expected
+    #[error("Unexpected response from {endpoint} ({status}): {body}")]
+    UnexpectedResponse {
+        endpoint: String,
+        status: u16,
+        body: String,
+    },
+    /// SRC wouldn't let us change a run's status
+    #[error("SRC refused to update run {run_id} ({status}): {message}")]
+    StatusUpdateRefused {
+        run_id: String,
+        status: u16,
+        /// SRC's explanation, if it gave one
+        message: String,
+    },
 }
 
-impl From<ApiError<RestError>> for SRCError {
-    fn from(apie: ApiError<RestError>) -> Self {
-        Self::ApiError(apie)
+#[derive(Debug, thiserror::Error)]
+pub enum QueryBuildError {
+    #[error(transparent)]
+    Run(#[from] RunBuilderError),
+    #[error(transparent)]
+    Runs(#[from] RunsBuilderError),
+    #[error(transparent)]
+    User(#[from] UserBuilderError),
+    #[error(transparent)]
+    GameCategories(#[from] GameCategoriesBuilderError),
+    #[error("Invalid url: {0}")]
+    Url(#[from] url::ParseError),
+    #[error("Unable to serialize request body: {0}")]
+    Body(#[from] serde_json::Error),
+}
+
+impl SRCError {
+    /// for `map_err`: an `ApiError` from asking SRC for `endpoint`
+    pub fn api(endpoint: impl Into<String>) -> impl FnOnce(ApiError<RestError>) -> Self {
+        let endpoint = endpoint.into();
+        move |source| Self::ApiError { endpoint, source }
+    }
+
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            // the client has already retried these, but SRC falls over often enough that it's
+            // worth trying again on the next poll
+            SRCError::ApiError { .. } | SRCError::PageFetchError { .. } => {
+                ErrorClass::Retryable { retry_after: None }
+            }
+            SRCError::UnexpectedResponse { status, .. } if *status == 429 || *status >= 500 => {
+                ErrorClass::Retryable { retry_after: None }
+            }
+            // our API key doesn't belong to someone who can verify runs
+            SRCError::StatusUpdateRefused { status, .. } if *status == 401 || *status == 403 => {
+                ErrorClass::Configuration
+            }
+            SRCError::QueryBuildError(_)
+            | SRCError::UnexpectedResponse { .. }
+            | SRCError::StatusUpdateRefused { .. } => ErrorClass::Permanent,
+        }
     }
 }
 
 impl From<RunBuilderError> for SRCError {
     fn from(rbe: RunBuilderError) -> Self {
-        Self::QueryBuildError(rbe.to_string())
+        Self::QueryBuildError(rbe.into())
     }
 }
 
-
 impl From<RunsBuilderError> for SRCError {
     fn from(rbe: RunsBuilderError) -> Self {
-        Self::QueryBuildError(rbe.to_string())
+        Self::QueryBuildError(rbe.into())
+    }
+}
+
+impl From<UserBuilderError> for SRCError {
+    fn from(ube: UserBuilderError) -> Self {
+        Self::QueryBuildError(ube.into())
     }
 }
 
 impl From<GameCategoriesBuilderError> for SRCError {
     fn from(gcbe: GameCategoriesBuilderError) -> Self {
-        Self::QueryBuildError(gcbe.to_string())
+        Self::QueryBuildError(gcbe.into())
     }
 }
 
@@ -104,75 +179,340 @@ pub struct SRCRun<'a> {
 #[derive(Deserialize, Debug)]
 pub struct SRCRunStatus<'a> {
     pub id: RunId<'a>,
-    pub status: Status<'a>,
+    pub status: SRCStatus,
+}
+
+/// a run's status, as SRC reports it on a single run
+// N.B. this is our own type rather than `speedrun_api::types::Status` so that we control
+// exactly which fields we pick up
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "status")]
+#[serde(rename_all = "lowercase")]
+pub enum SRCStatus {
+    New,
+    Verified {
+        /// the SRC user id of the mod who verified it
+        examiner: Option<String>,
+        #[serde(rename(deserialize = "verify-date"))]
+        verify_date: Option<String>,
+    },
+    Rejected {
+        examiner: Option<String>,
+        reason: Option<String>,
+    },
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SRCUser<'a> {
+    pub id: UserId<'a>,
+    pub names: Names,
 }
 
 impl<'a> SRCRun<'a> {
     pub fn player(&self) -> Option<&str> {
         self.players.data.first().map(|p| p.name())
     }
+
+    /// every runner, for co-op and race runs
+    pub fn player_names(&self) -> Vec<&str> {
+        self.players.data.iter().map(|p| p.name()).collect()
+    }
+
+    /// the SRC user id of the (first) runner, unless they're a guest
+    pub fn player_id(&self) -> Option<&str> {
+        self.players.data.first().and_then(|p| p.user_id())
+    }
+
+    /// the run's variable values as a JSON object, for storing in the db
+    pub fn values_json(&self) -> String {
+        let values = self
+            .values
+            .iter()
+            .map(|(var, val)| (var.to_string(), val.to_string()))
+            .collect::<HashMap<_, _>>();
+        // a map of strings to strings can't fail to serialize
+        serde_json::to_string(&values).unwrap_or_default()
+    }
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(tag = "rel")]
 #[serde(rename_all = "lowercase")]
 pub enum PlayerEmbed {
-    User { names: Names },
+    User { id: String, names: Names },
     Guest { name: String },
 }
 
 impl PlayerEmbed {
     pub fn name(&self) -> &str {
         match self {
-            Self::User { names } => &names.international,
+            Self::User { names, .. } => &names.international,
             Self::Guest { name } => &name,
         }
     }
+
+    pub fn user_id(&self) -> Option<&str> {
+        match self {
+            Self::User { id, .. } => Some(id),
+            Self::Guest { .. } => None,
+        }
+    }
+}
+
+/// The SRC queue as returned by `get_runs`.
+///
+/// A partial listing still has every run we managed to fetch, but a run being absent from it
+/// does *not* mean the run has left the queue; callers must not make that inference.
+#[derive(Debug)]
+pub enum RunsListing<'a> {
+    Complete(Vec<SRCRun<'a>>),
+    Partial {
+        runs: Vec<SRCRun<'a>>,
+        /// why we stopped fetching
+        error: SRCError,
+    },
+}
+
+impl<'a> RunsListing<'a> {
+    pub fn is_complete(&self) -> bool {
+        matches!(self, Self::Complete(_))
+    }
+
+    pub fn runs(&self) -> &[SRCRun<'a>] {
+        match self {
+            Self::Complete(runs) => runs,
+            Self::Partial { runs, .. } => runs,
+        }
+    }
+
+    pub fn into_runs(self) -> Vec<SRCRun<'a>> {
+        match self {
+            Self::Complete(runs) => runs,
+            Self::Partial { runs, .. } => runs,
+        }
+    }
 }
 
-pub async fn get_runs(src_client: &SpeedrunApiClientAsync) -> Result<Vec<SRCRun<'_>>, SRCError> {
+/// fetches every run in the queue. if a page fails partway through, returns what we got as a
+/// `RunsListing::Partial`; if we didn't get anything at all, that's an error.
+pub async fn get_runs(src_client: &SRCClient) -> Result<RunsListing<'_>, SRCError> {
+    get_game_runs(src_client, ALTTP_GAME_ID).await
+}
+
+/// like `get_runs`, but for any game's queue
+pub async fn get_game_runs<'a>(
+    src_client: &'a SRCClient,
+    game_id: &'a str,
+) -> Result<RunsListing<'a>, SRCError> {
     let runs: Runs = Runs::builder()
         .status(api::runs::RunStatus::New)
-        .game(ALTTP_GAME_ID)
+        .game(game_id)
         .orderby(api::runs::RunsSorting::Submitted)
         .direction(api::Direction::Asc)
         .embed(RunEmbeds::Players)
         .build()?;
 
-    let mut runs_stream = runs.stream::<SRCRun, SpeedrunApiClientAsync>(&src_client);
+    let mut runs_stream = runs.stream::<SRCRun, SRCClient>(&src_client);
 
     let mut runs = vec![];
     while let Some(t) = runs_stream.next().await {
         match t {
             Ok(r) => runs.push(r),
-            Err(e) => {
-                println!("Error fetching run: {:?}", e);
-                continue;
+            Err(source) => {
+                let error = SRCError::PageFetchError {
+                    endpoint: format!("runs?game={}", game_id),
+                    fetched: runs.len(),
+                    source,
+                };
+                if runs.is_empty() {
+                    return Err(error);
+                }
+                return Ok(RunsListing::Partial { runs, error });
             }
         };
     }
-    Ok(runs)
+    Ok(RunsListing::Complete(runs))
 }
 
-pub async fn get_run<'a, T: Into<RunId<'a>>>(src_client: &SpeedrunApiClientAsync, id: T) -> Result<SRCRunStatus<'a>, SRCError> {
+/// the only part of `runs/{id}` that `get_run` looks at
+#[derive(Deserialize, Debug)]
+struct RunStatusOnly {
+    status: SRCStatus,
+}
+
+/// a deleted run comes back as `SRCError::UnexpectedResponse` with a 404, rather than as an
+/// `ApiError` like SRC being down would
+pub async fn get_run<'a, T: Into<RunId<'a>>>(src_client: &SRCClient, id: T) -> Result<SRCRunStatus<'a>, SRCError> {
     let run_id = id.into();
-    let gr = Run::builder().id(run_id).build()?;
-    let o = gr.query_async(src_client).await;
-    o.map_err(Into::into)
+    let endpoint = format!("runs/{}", run_id);
+    // not `Run::query_async`: its errors don't say what the status code was
+    let url = src_client
+        .rest_endpoint(&endpoint)
+        .map_err(SRCError::api(endpoint.as_str()))?;
+    let request = Request::builder().method(Method::GET).uri(url.as_str());
+    let resp = src_client
+        .rest_async(request, vec![])
+        .await
+        .map_err(SRCError::api(endpoint.as_str()))?;
+    if !resp.status().is_success() {
+        return Err(SRCError::UnexpectedResponse {
+            endpoint,
+            status: resp.status().as_u16(),
+            body: String::from_utf8_lossy(resp.body()).into_owned(),
+        });
+    }
+    let run = serde_json::from_slice::<Root<RunStatusOnly>>(resp.body()).map_err(|e| {
+        SRCError::UnexpectedResponse {
+            endpoint,
+            status: resp.status().as_u16(),
+            body: e.to_string(),
+        }
+    })?;
+    Ok(SRCRunStatus {
+        id: run_id,
+        status: run.data.status,
+    })
+}
+
+/// like `get_run`, but with everything we need to post about it
+pub async fn get_full_run<'a, T: Into<RunId<'a>>>(
+    src_client: &SRCClient,
+    id: T,
+) -> Result<SRCRun<'a>, SRCError> {
+    let id = id.into();
+    let endpoint = format!("runs/{}", id);
+    let run = Run::builder().id(id).embed(RunEmbeds::Players).build()?;
+    run.query_async(src_client)
+        .await
+        .map_err(SRCError::api(endpoint))
+}
+
+pub async fn get_user<'a, T: Into<UserId<'a>>>(
+    src_client: &SRCClient,
+    id: T,
+) -> Result<SRCUser<'a>, SRCError> {
+    let id = id.into();
+    let endpoint = format!("users/{}", id);
+    let user = User::builder().id(id).build()?;
+    user.query_async(src_client)
+        .await
+        .map_err(SRCError::api(endpoint))
 }
 
 pub async fn get_categories<'a, GID: Into<GameId<'a>>>(
     game_id: GID,
-    src_client: &SpeedrunApiClientAsync,
-) -> Result<Vec<Category<'_>>, SRCError> {
+    src_client: &SRCClient,
+) -> Result<Vec<Category<'a>>, SRCError> {
     // we're gonna just get category-relevant variables in here because i don't care about
     // blue balls
+    let game_id = game_id.into();
+    let endpoint = format!("games/{}/categories", game_id);
     let categories_q: GameCategories = GameCategories::builder()
         .id(game_id)
         .miscellaneous(false)
         .embed(CategoryEmbeds::Variables)
         .build()?;
-    categories_q.query_async(src_client).await.map_err(Into::into)
+    categories_q
+        .query_async(src_client)
+        .await
+        .map_err(SRCError::api(endpoint))
 }
 
 pub use category_repository::CategoriesRepository;
+pub use client::{SRCClient, SingleAttempt};
+pub use leaderboards::{get_leaderboard_placement, Placement};
+pub use replay::{Exchange, Recorder, Replayer};
+pub use status::{NewStatus, StatusUpdater, DEFAULT_BASE_URL};
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ErrorClass;
+    use crate::src::{
+        get_categories, get_run, get_runs, CategoriesRepository, RunsListing, SRCClient, SRCError,
+    };
+    use crate::ALTTP_GAME_ID;
+    use speedrun_api::api::categories::CategoryId;
+    use speedrun_api::api::variables::{ValueId, VariableId};
+    use speedrun_api::SpeedrunApiClientAsync;
+    use std::collections::HashMap;
+
+    /// a client that answers from `api_responses/replay/{fixtures}`, one directory after another
+    fn replaying(fixtures: &[&str]) -> SRCClient {
+        let dirs = fixtures
+            .iter()
+            .map(|f| format!("{}/api_responses/replay/{}", env!("CARGO_MANIFEST_DIR"), f));
+        SRCClient::replaying(SpeedrunApiClientAsync::new().unwrap(), dirs).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_get_runs_pagination() {
+        let client = replaying(&["runs_first_page", "runs_second_page"]);
+        let listing = get_runs(&client).await.unwrap();
+        assert!(listing.is_complete());
+        let runs = listing.runs();
+        assert_eq!(30, runs.len());
+        assert_eq!("m3xg0vwm", runs[0].id.to_string());
+        assert_eq!(Some("robjbeasley"), runs[0].player());
+        // the first run on the second page
+        assert_eq!("mwv0gx3m", runs[20].id.to_string());
+    }
+
+    #[tokio::test]
+    async fn test_get_runs_partial() {
+        let client = replaying(&["runs_first_page", "runs_second_page_fails"]);
+        match get_runs(&client).await.unwrap() {
+            RunsListing::Partial {
+                runs,
+                error: SRCError::PageFetchError { fetched, .. },
+            } => {
+                assert_eq!(20, runs.len());
+                assert_eq!(20, fetched);
+            }
+            other => panic!("expected a partial listing, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_deleted_run() {
+        // nothing recorded, so the replayer 404s like SRC does for a deleted run
+        let client = replaying(&[]);
+        match get_run(&client, "zzzzzzzz").await {
+            Err(e @ SRCError::UnexpectedResponse { status: 404, .. }) => {
+                assert_eq!(ErrorClass::Permanent, e.class());
+            }
+            other => panic!("expected a 404, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_categories() {
+        let client = replaying(&["game_categories"]);
+        let categories = get_categories(ALTTP_GAME_ID, &client).await.unwrap();
+        assert_eq!(
+            vec![
+                "No Major Glitches",
+                "Restricted Major Glitches",
+                "Major Glitches",
+                "Misc"
+            ],
+            categories.iter().map(|c| c.name.as_str()).collect::<Vec<_>>()
+        );
+        let subcategory = categories[0]
+            .variables
+            .data
+            .iter()
+            .find(|v| v.is_subcategory)
+            .unwrap();
+        assert_eq!("2lg2368p", subcategory.id.to_string());
+
+        let repo = CategoriesRepository::new(categories, vec![]);
+        let values = HashMap::from([(
+            VariableId::from("2lg2368p".to_string()),
+            ValueId::from("013xwzr1".to_string()),
+        )]);
+        assert_eq!(
+            Some("Any% No Major Glitches".to_string()),
+            repo.category_name(&CategoryId::from("wk6jz5rd".to_string()), &values)
+        );
+    }
+}
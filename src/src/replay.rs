@@ -0,0 +1,235 @@
+// recording SRC's responses to disk, and serving them back again, so that a session against the
+// real API can be replayed (e.g. in tests) without touching the network
+
+use http::request::Builder;
+use http::{Response, StatusCode};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use url::Url;
+
+/// one request and what SRC said to it. each is stored as its own numbered JSON file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Exchange {
+    pub method: String,
+    pub url: String,
+    /// `url`'s query parameters, pulled out to make fixtures easier to read. for paginated
+    /// queries this includes which page it was (`offset`)
+    pub query: Vec<(String, String)>,
+    pub status: u16,
+    /// SRC always answers with JSON, pagination and all
+    pub body: serde_json::Value,
+}
+
+impl Exchange {
+    fn new(request: &Builder, response: &Response<bytes::Bytes>) -> Self {
+        let url = request
+            .uri_ref()
+            .map(ToString::to_string)
+            .unwrap_or_default();
+        Self {
+            method: request
+                .method_ref()
+                .map(ToString::to_string)
+                .unwrap_or_else(|| "GET".to_string()),
+            query: query_pairs(&url),
+            url,
+            status: response.status().as_u16(),
+            body: serde_json::from_slice(response.body()).unwrap_or_else(|_| {
+                serde_json::Value::String(String::from_utf8_lossy(response.body()).into_owned())
+            }),
+        }
+    }
+}
+
+fn query_pairs(url: &str) -> Vec<(String, String)> {
+    Url::parse(url)
+        .map(|u| u.query_pairs().into_owned().collect())
+        .unwrap_or_default()
+}
+
+/// what has to match for a recorded response to be used for a request: the path, and the
+/// query in any order. the host doesn't matter, so that fixtures work against any base url
+fn match_key(url: &str) -> (String, Vec<(String, String)>) {
+    let mut query = query_pairs(url);
+    query.sort();
+    let path = Url::parse(url)
+        .map(|u| u.path().to_string())
+        .unwrap_or_else(|_| url.to_string());
+    (path, query)
+}
+
+fn fixture_path(dir: &Path, n: usize) -> PathBuf {
+    dir.join(format!("{:04}.json", n))
+}
+
+/// writes every response we get from SRC to a directory
+pub struct Recorder {
+    dir: PathBuf,
+    /// how many responses we've written, including any that were already in `dir`
+    recorded: AtomicUsize,
+}
+
+impl Recorder {
+    /// if `dir` already has fixtures in it, we carry on numbering from the last one
+    pub fn new(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        let existing = std::fs::read_dir(&dir)?
+            .filter_map(Result::ok)
+            .filter(|e| e.path().extension().map_or(false, |ext| ext == "json"))
+            .count();
+        Ok(Self {
+            dir,
+            recorded: AtomicUsize::new(existing),
+        })
+    }
+
+    /// best effort: failing to record shouldn't stop the bot from working
+    pub(crate) fn record(&self, request: &Builder, response: &Response<bytes::Bytes>) {
+        let n = self.recorded.fetch_add(1, Ordering::Relaxed) + 1;
+        let path = fixture_path(&self.dir, n);
+        let result = serde_json::to_vec_pretty(&Exchange::new(request, response))
+            .map_err(io::Error::from)
+            .and_then(|json| std::fs::write(&path, json));
+        if let Err(e) = result {
+            warn!("Unable to record SRC response to {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// serves up responses recorded by a `Recorder`
+pub struct Replayer {
+    remaining: Mutex<VecDeque<Exchange>>,
+}
+
+impl Replayer {
+    /// reads every fixture in each of `dirs`, in order, as if they'd all been recorded in one
+    /// go. that way sessions that start the same (e.g. with the first page of the queue) can
+    /// share that part
+    pub fn new(dirs: impl IntoIterator<Item = impl AsRef<Path>>) -> io::Result<Self> {
+        let mut remaining = VecDeque::new();
+        for dir in dirs {
+            let mut paths = std::fs::read_dir(dir.as_ref())?
+                .filter_map(Result::ok)
+                .map(|e| e.path())
+                .filter(|p| p.extension().map_or(false, |ext| ext == "json"))
+                .collect::<Vec<_>>();
+            paths.sort();
+            for path in paths {
+                let exchange = serde_json::from_slice::<Exchange>(&std::fs::read(&path)?)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                remaining.push_back(exchange);
+            }
+        }
+        Ok(Self {
+            remaining: Mutex::new(remaining),
+        })
+    }
+
+    /// the earliest unused response to the same request (see `match_key`). requests can run
+    /// concurrently (e.g. the poll loop and the interaction handler), so this doesn't insist that
+    /// they come in exactly the order they were recorded in. if there isn't one, this is a 404,
+    /// same as SRC would give for something it doesn't have
+    pub(crate) fn next(&self, request: &Builder) -> Response<bytes::Bytes> {
+        let url = request
+            .uri_ref()
+            .map(ToString::to_string)
+            .unwrap_or_default();
+        let method = request
+            .method_ref()
+            .map(ToString::to_string)
+            .unwrap_or_else(|| "GET".to_string());
+        let key = match_key(&url);
+        let found = self.remaining.lock().ok().and_then(|mut remaining| {
+            let i = remaining
+                .iter()
+                .position(|e| e.method == method && match_key(&e.url) == key)?;
+            remaining.remove(i)
+        });
+        let (status, body) = match found {
+            Some(exchange) => {
+                let body = match exchange.body {
+                    serde_json::Value::String(s) => s.into_bytes(),
+                    other => serde_json::to_vec(&other).unwrap_or_default(),
+                };
+                (
+                    StatusCode::from_u16(exchange.status).unwrap_or(StatusCode::OK),
+                    body,
+                )
+            }
+            None => {
+                warn!("No recorded response left for {} {}", method, url);
+                let message = format!("no recorded response left for {} {}", method, url);
+                (
+                    StatusCode::NOT_FOUND,
+                    serde_json::to_vec(&serde_json::json!({"status": 404, "message": message}))
+                        .unwrap_or_default(),
+                )
+            }
+        };
+        let mut response = Response::new(bytes::Bytes::from(body));
+        *response.status_mut() = status;
+        response
+    }
+
+    /// how many recorded responses haven't been asked for yet
+    pub fn remaining(&self) -> usize {
+        self.remaining.lock().map(|r| r.len()).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::src::replay::{Recorder, Replayer};
+    use crate::test_path;
+    use http::{Request, Response, StatusCode};
+
+    fn request(url: &str) -> http::request::Builder {
+        Request::builder().method("GET").uri(url)
+    }
+
+    #[test]
+    fn test_record_then_replay() {
+        let dir = test_path("replay");
+        let recorder = Recorder::new(&dir).unwrap();
+        let page = |n: u32| {
+            Response::new(bytes::Bytes::from(format!(
+                r#"{{"data":[],"pagination":{{"offset":{}}}}}"#,
+                n
+            )))
+        };
+        recorder.record(&request("https://src.test/api/v1/runs?offset=0"), &page(0));
+        recorder.record(
+            &request("https://src.test/api/v1/runs?offset=20"),
+            &page(20),
+        );
+        recorder.record(&request("https://src.test/api/v1/users/abc"), &page(0));
+
+        let replayer = Replayer::new([&dir]).unwrap();
+        assert_eq!(3, replayer.remaining());
+        // a different endpoint doesn't use up the runs pages
+        let user = replayer.next(&request("https://src.test/api/v1/users/abc"));
+        assert_eq!(StatusCode::OK, user.status());
+        // pages are matched by their query, not by which came first
+        let second = replayer.next(&request("https://src.test/api/v1/runs?offset=20"));
+        let first = replayer.next(&request("https://src.test/api/v1/runs?offset=0"));
+        let body = |r: &Response<bytes::Bytes>| {
+            serde_json::from_slice::<serde_json::Value>(r.body()).unwrap()["pagination"]["offset"]
+                .clone()
+        };
+        assert_eq!(0, body(&first));
+        assert_eq!(20, body(&second));
+        assert_eq!(
+            StatusCode::NOT_FOUND,
+            replayer
+                .next(&request("https://src.test/api/v1/runs?offset=40"))
+                .status()
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,162 @@
+use crate::src::{QueryBuildError, SRCError};
+use http::header::CONTENT_TYPE;
+use http::{Method, Request};
+use serde::{Deserialize, Serialize};
+use speedrun_api::api::AsyncClient;
+use speedrun_api::error::RestError;
+use url::Url;
+
+pub const DEFAULT_BASE_URL: &str = "https://www.speedrun.com/api/v1/";
+const API_KEY_HEADER: &str = "X-API-Key";
+
+/// a status we can set a run to
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "status")]
+#[serde(rename_all = "lowercase")]
+pub enum NewStatus {
+    Verified,
+    Rejected { reason: String },
+}
+
+impl NewStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NewStatus::Verified => "verified",
+            NewStatus::Rejected { .. } => "rejected",
+        }
+    }
+
+    pub fn reason(&self) -> Option<&str> {
+        match self {
+            NewStatus::Verified => None,
+            NewStatus::Rejected { reason } => Some(reason),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct StatusBody<'a> {
+    status: &'a NewStatus,
+}
+
+/// SRC's error responses look like `{"status": 403, "message": "..."}`
+#[derive(Deserialize)]
+struct ErrorBody {
+    message: String,
+}
+
+/// sets run statuses on SRC. this is the only thing we do that needs an API key, and it acts as
+/// whoever the key belongs to.
+pub struct StatusUpdater {
+    api_key: String,
+    base_url: Url,
+}
+
+impl StatusUpdater {
+    pub fn new(api_key: String, base_url: Url) -> Self {
+        Self { api_key, base_url }
+    }
+
+    /// sends `PUT /runs/{id}/status` through `client`, which decides whether it's throttled or
+    /// retried. the decision buttons use `SRCClient::single_attempt`, so for them it's neither.
+    /// this doesn't record anything; `Decisions::push` does that
+    pub async fn set_status<C: AsyncClient<Error = RestError> + Sync>(
+        &self,
+        client: &C,
+        run_id: &str,
+        status: &NewStatus,
+    ) -> Result<(), SRCError> {
+        let endpoint = format!("runs/{}/status", run_id);
+        let url = self
+            .base_url
+            .join(&endpoint)
+            .map_err(QueryBuildError::from)?;
+        let body = serde_json::to_vec(&StatusBody { status }).map_err(QueryBuildError::from)?;
+        let request = Request::builder()
+            .method(Method::PUT)
+            .uri(url.as_str())
+            .header(API_KEY_HEADER, &self.api_key)
+            .header(CONTENT_TYPE, "application/json");
+        let resp = client
+            .rest_async(request, body)
+            .await
+            .map_err(SRCError::api(endpoint))?;
+        if resp.status().is_success() {
+            return Ok(());
+        }
+        let message = serde_json::from_slice::<ErrorBody>(resp.body())
+            .map(|b| b.message)
+            .unwrap_or_else(|_| String::from_utf8_lossy(resp.body()).into_owned());
+        Err(SRCError::StatusUpdateRefused {
+            run_id: run_id.to_string(),
+            status: resp.status().as_u16(),
+            message,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mock_server::{response, MockServer};
+    use crate::src::status::{NewStatus, StatusUpdater};
+    use crate::src::{SRCClient, SRCError};
+    use hyper::{Method, StatusCode};
+    use speedrun_api::SpeedrunApiClientAsync;
+
+    /// stands in for SRC's `PUT /runs/{id}/status`, answering every request with `status` and
+    /// `body`
+    fn mock_src(status: StatusCode, body: &'static str) -> MockServer {
+        MockServer::start(move |_, _| response(status, body))
+    }
+
+    fn client() -> SRCClient {
+        SRCClient::new(SpeedrunApiClientAsync::new().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_verify() {
+        let src = mock_src(StatusCode::OK, r#"{"data":{}}"#);
+        let updater = StatusUpdater::new("secret".to_string(), src.url("/api/v1/"));
+        updater
+            .set_status(&client(), "abc123", &NewStatus::Verified)
+            .await
+            .unwrap();
+        let seen = src.seen();
+        assert_eq!(1, seen.len());
+        assert_eq!(Method::PUT, seen[0].method);
+        assert_eq!("/api/v1/runs/abc123/status", seen[0].path);
+        assert_eq!(Some("secret"), seen[0].header("x-api-key"));
+        assert_eq!(
+            Some(serde_json::json!({"status": {"status": "verified"}})),
+            seen[0].body
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reject_refused() {
+        let src = mock_src(
+            StatusCode::FORBIDDEN,
+            r#"{"status":403,"message":"You are not a moderator of this game."}"#,
+        );
+        let updater = StatusUpdater::new("secret".to_string(), src.url("/api/v1/"));
+        let status = NewStatus::Rejected {
+            reason: "no video".to_string(),
+        };
+        match updater.set_status(&client(), "abc123", &status).await {
+            Err(SRCError::StatusUpdateRefused {
+                status, message, ..
+            }) => {
+                assert_eq!(403, status);
+                assert_eq!("You are not a moderator of this game.", message);
+            }
+            other => panic!("Expected the update to be refused, got {:?}", other),
+        }
+        let seen = src.seen();
+        // a 403 isn't worth retrying
+        assert_eq!(1, seen.len());
+        assert_eq!(
+            Some(serde_json::json!({"status": {"status": "rejected", "reason": "no video"}})),
+            seen[0].body
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use crate::discord_client::BotDiscordClient;
+use crate::embeds::EmbedBuilder;
+use crate::error::BotError;
+use crate::models::runs::Run;
+use crate::schema;
+use crate::utils::{parse_timestamp, timestamp_now};
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+use log::info;
+use std::env;
+use std::env::VarError;
+use std::num::NonZeroU64;
+use twilight_model::channel::embed::Embed;
+use twilight_model::channel::message::AllowedMentions;
+use twilight_model::id::marker::{ChannelMarker, RoleMarker};
+use twilight_model::id::Id;
+
+/// settings for reminding the mods about runs that have been sitting in the queue too long
+pub struct StalenessConfig {
+    /// how long a run can be in the queue before we complain about it
+    pub max_age: Duration,
+    pub channel_id: Id<ChannelMarker>,
+    /// the role to ping about stale runs
+    pub role_id: Id<RoleMarker>,
+}
+
+impl StalenessConfig {
+    /// returns `None` if staleness reminders aren't configured (i.e. `STALE_RUN_DAYS` is unset)
+    pub fn from_env() -> Result<Option<Self>, BotError> {
+        let days = match env::var("STALE_RUN_DAYS") {
+            Ok(d) => d.parse::<u32>()?,
+            Err(VarError::NotPresent) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let channel_id =
+            Id::<ChannelMarker>::from(env::var("MOD_CHANNEL_ID")?.parse::<NonZeroU64>()?);
+        let role_id = Id::<RoleMarker>::from(env::var("MOD_ROLE_ID")?.parse::<NonZeroU64>()?);
+        Ok(Some(Self {
+            max_age: Duration::days(days as i64),
+            channel_id,
+            role_id,
+        }))
+    }
+}
+
+/// picks out the runs which have been waiting for longer than `max_age`, along with how long
+/// they've been waiting. runs we can't tell the age of are never considered stale.
+fn stale_runs(runs: Vec<Run>, now: DateTime<Utc>, max_age: Duration) -> Vec<(Run, Duration)> {
+    runs.into_iter()
+        .filter_map(|r| {
+            let submitted = parse_timestamp(r.submitted.as_ref()?)?;
+            let age = now - submitted;
+            if age > max_age {
+                Some((r, age))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn stale_run_embed(run: &Run, age: Duration) -> Embed {
+    let embed = EmbedBuilder::new()
+        .title(format!("Waiting in the queue for {} days", age.num_days()))
+        .field("Run", run.run_id.clone(), true)
+        .field(
+            "Submitted",
+            run.submitted.as_deref().unwrap_or("Unknown"),
+            true,
+        );
+    let embed = match &run.weblink {
+        Some(link) => embed.url(link),
+        None => embed,
+    };
+    embed.build()
+}
+
+/// pings the configured role once for each run that's been in the queue for too long
+pub async fn remind_stale_runs(
+    config: &StalenessConfig,
+    discord_client: &BotDiscordClient,
+    conn: &mut SqliteConnection,
+) -> Result<(), BotError> {
+    use schema::runs::dsl::*;
+    let candidates = runs
+        .filter(left_queue_at.is_null())
+        .filter(stale_reminder_sent_at.is_null())
+        .filter(
+            snoozed_until
+                .is_null()
+                .or(snoozed_until.lt(timestamp_now())),
+        )
+        .load::<Run>(conn)?;
+    let stale = stale_runs(candidates, Utc::now(), config.max_age);
+    if !stale.is_empty() {
+        info!("Reminding mods about {} stale runs", stale.len());
+    }
+    let allowed_mentions = AllowedMentions {
+        roles: vec![config.role_id],
+        ..Default::default()
+    };
+    for (run, age) in stale {
+        let rli = discord_client
+            .create_message_in(
+                config.channel_id,
+                Some(&format!(
+                    "<@&{}> this run needs some attention",
+                    config.role_id
+                )),
+                vec![stale_run_embed(&run, age)],
+                &allowed_mentions,
+            )
+            .await?;
+        diesel::update(&run)
+            .set(stale_reminder_sent_at.eq(timestamp_now()))
+            .execute(conn)?;
+        rli.sleep().await;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::runs::{test_run, Run};
+    use crate::staleness::stale_runs;
+    use crate::utils::parse_timestamp;
+    use chrono::Duration;
+
+    fn run(submitted: Option<&str>) -> Run {
+        Run {
+            submitted: submitted.map(ToString::to_string),
+            ..test_run("abc123")
+        }
+    }
+
+    #[test]
+    fn test_stale_runs() {
+        let now = parse_timestamp("2022-09-10T12:00:00Z").unwrap();
+        let runs = vec![
+            run(Some("2022-09-01T12:00:00Z")),
+            run(Some("2022-09-08T12:00:00Z")),
+            run(None),
+            run(Some("not a date")),
+        ];
+        let stale = stale_runs(runs, now, Duration::days(7));
+        assert_eq!(1, stale.len());
+        assert_eq!(
+            Some("2022-09-01T12:00:00Z"),
+            stale[0].0.submitted.as_deref()
+        );
+        assert_eq!(9, stale[0].1.num_days());
+    }
+}
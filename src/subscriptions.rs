@@ -0,0 +1,230 @@
+use crate::discord_client::BotDiscordClient;
+use crate::embeds::EmbedBuilder;
+use crate::error::BotError;
+use crate::models::subscriptions::{NewSubscription, Subscription};
+use crate::schema;
+use crate::utils::timestamp_now;
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+use log::{info, warn};
+use std::collections::HashSet;
+use std::num::NonZeroU64;
+use twilight_model::channel::embed::Embed;
+use twilight_model::channel::message::AllowedMentions;
+use twilight_model::id::marker::UserMarker;
+use twilight_model::id::Id;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriptionKind {
+    Runner,
+    Category,
+}
+
+impl SubscriptionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriptionKind::Runner => "runner",
+            SubscriptionKind::Category => "category",
+        }
+    }
+}
+
+/// something that happened to a run that subscribers might want to hear about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriptionEvent {
+    Submitted,
+    Verified,
+}
+
+/// subscribes the user to runs by a runner or in a category. subscribing to something you're
+/// already subscribed to just updates `notify_verified`
+pub fn subscribe(
+    user_id: Id<UserMarker>,
+    sub_kind: SubscriptionKind,
+    sub_target: &str,
+    verified: bool,
+    conn: &mut SqliteConnection,
+) -> Result<(), BotError> {
+    use schema::subscriptions::dsl::*;
+    diesel::insert_into(subscriptions)
+        .values(NewSubscription {
+            discord_user_id: user_id.to_string(),
+            kind: sub_kind.as_str(),
+            target: sub_target.trim().to_lowercase(),
+            notify_verified: verified,
+            created_at: timestamp_now(),
+        })
+        .on_conflict((discord_user_id, kind, target))
+        .do_update()
+        .set(notify_verified.eq(verified))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// returns whether there was anything to unsubscribe from
+pub fn unsubscribe(
+    user_id: Id<UserMarker>,
+    sub_kind: SubscriptionKind,
+    sub_target: &str,
+    conn: &mut SqliteConnection,
+) -> Result<bool, BotError> {
+    use schema::subscriptions::dsl::*;
+    let deleted = diesel::delete(
+        subscriptions
+            .filter(discord_user_id.eq(user_id.to_string()))
+            .filter(kind.eq(sub_kind.as_str()))
+            .filter(target.eq(sub_target.trim().to_lowercase())),
+    )
+    .execute(conn)?;
+    Ok(deleted > 0)
+}
+
+pub fn subscriptions_for(
+    user_id: Id<UserMarker>,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<Subscription>, BotError> {
+    use schema::subscriptions::dsl::*;
+    Ok(subscriptions
+        .filter(discord_user_id.eq(user_id.to_string()))
+        .order((kind.asc(), target.asc()))
+        .load::<Subscription>(conn)?)
+}
+
+/// everyone who should hear about `event` on a run by `runner` in `category`. each user is only
+/// listed once, even if several of their subscriptions match
+fn matching_subscribers(
+    subs: &[Subscription],
+    event: SubscriptionEvent,
+    runner: Option<&str>,
+    category: Option<&str>,
+) -> Vec<Id<UserMarker>> {
+    let runner = runner.map(str::to_lowercase);
+    let category = category.map(str::to_lowercase);
+    let mut seen = HashSet::new();
+    subs.iter()
+        .filter(|s| event == SubscriptionEvent::Submitted || s.notify_verified)
+        .filter(|s| {
+            let wanted = match s.kind.as_str() {
+                "runner" => runner.as_deref(),
+                "category" => category.as_deref(),
+                _ => None,
+            };
+            wanted == Some(s.target.as_str())
+        })
+        .filter_map(|s| s.discord_user_id.parse::<NonZeroU64>().ok())
+        .map(Id::<UserMarker>::from)
+        .filter(|id| seen.insert(*id))
+        .collect()
+}
+
+fn notification_embed(
+    event: SubscriptionEvent,
+    runner: Option<&str>,
+    category: Option<&str>,
+    weblink: Option<&str>,
+) -> Embed {
+    let title = match event {
+        SubscriptionEvent::Submitted => "A run you're subscribed to was submitted",
+        SubscriptionEvent::Verified => "A run you're subscribed to was verified",
+    };
+    let embed = EmbedBuilder::new()
+        .title(title)
+        .field("Runner", runner.unwrap_or("Unknown"), true)
+        .field("Category", category.unwrap_or("Unknown"), true)
+        .footer("Use /unsubscribe to stop these");
+    let embed = match weblink {
+        Some(link) => embed.url(link),
+        None => embed,
+    };
+    embed.build()
+}
+
+/// DMs everyone subscribed to the run's runner or category. someone having their DMs closed
+/// doesn't stop anyone else from being notified
+pub async fn notify_subscribers(
+    event: SubscriptionEvent,
+    runner: Option<&str>,
+    category: Option<&str>,
+    weblink: Option<&str>,
+    discord_client: &BotDiscordClient,
+    conn: &mut SqliteConnection,
+) -> Result<(), BotError> {
+    let subs = schema::subscriptions::table.load::<Subscription>(conn)?;
+    let subscribers = matching_subscribers(&subs, event, runner, category);
+    if subscribers.is_empty() {
+        return Ok(());
+    }
+    info!(
+        "Notifying {} subscribers about a {:?} run",
+        subscribers.len(),
+        event
+    );
+    for user_id in subscribers {
+        let channel_id = match discord_client.create_dm_channel(user_id).await {
+            Ok(c) => c,
+            Err(e) => {
+                warn!("Unable to open a DM with {}: {}", user_id, e);
+                continue;
+            }
+        };
+        match discord_client
+            .create_message_in(
+                channel_id,
+                None,
+                vec![notification_embed(event, runner, category, weblink)],
+                &AllowedMentions::default(),
+            )
+            .await
+        {
+            Ok(rli) => rli.sleep().await,
+            Err(e) => warn!("Unable to DM {}: {}", user_id, e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::subscriptions::Subscription;
+    use crate::subscriptions::{matching_subscribers, SubscriptionEvent};
+    use twilight_model::id::Id;
+
+    fn sub(user: &str, kind: &str, target: &str, notify_verified: bool) -> Subscription {
+        Subscription {
+            id: 0,
+            discord_user_id: user.to_string(),
+            kind: kind.to_string(),
+            target: target.to_string(),
+            notify_verified,
+            created_at: "2022-09-01T12:00:00Z".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_matching_subscribers() {
+        let subs = vec![
+            sub("1", "runner", "someone", false),
+            sub("2", "category", "no major glitches", true),
+            // subscribed to both; should only hear about it once
+            sub("3", "runner", "someone", true),
+            sub("3", "category", "no major glitches", true),
+            sub("4", "runner", "someone else", true),
+        ];
+        let submitted = matching_subscribers(
+            &subs,
+            SubscriptionEvent::Submitted,
+            Some("Someone"),
+            Some("No Major Glitches"),
+        );
+        assert_eq!(vec![Id::new(1), Id::new(2), Id::new(3)], submitted);
+        let verified = matching_subscribers(
+            &subs,
+            SubscriptionEvent::Verified,
+            Some("Someone"),
+            Some("No Major Glitches"),
+        );
+        assert_eq!(vec![Id::new(2), Id::new(3)], verified);
+        let unknown = matching_subscribers(&subs, SubscriptionEvent::Submitted, None, None);
+        assert!(unknown.is_empty());
+    }
+}
@@ -1,3 +1,5 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+
 // lifted from league bot
 // i really need a way to stop copy-pasting code across projects
 // this is extended to handle fractions though
@@ -35,6 +37,22 @@ pub fn env_var(key: &str) -> String {
     std::env::var(key).expect(&format!("Missing environment variable: `{}`", key))
 }
 
+/// formats a datetime the same way SRC does (e.g. `2022-08-11T23:54:52Z`), which lets us
+/// compare our timestamps and theirs as strings in sqlite
+pub fn format_timestamp(dt: DateTime<Utc>) -> String {
+    dt.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+pub fn timestamp_now() -> String {
+    format_timestamp(Utc::now())
+}
+
+pub fn parse_timestamp(s: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(s)
+        .ok()
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
 #[cfg(test)]
 mod tests {
     use crate::utils::format_hms;
@@ -0,0 +1,182 @@
+use crate::embeds::EmbedBuilder;
+use crate::error::BotError;
+use crate::models::runs::Run;
+use crate::schema;
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+use std::collections::HashMap;
+use twilight_model::channel::embed::Embed;
+
+/// how many verifiers we'll list before cutting it off
+const MAX_LISTED: usize = 10;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct VerifierStats {
+    pub name: String,
+    /// runs verified + rejected
+    pub judged: usize,
+    /// median time the runs they judged spent in the queue
+    pub median_wait_secs: Option<i64>,
+}
+
+/// per-verifier stats for runs judged since `since` (a datetime string), or ever if that's None
+pub fn verifier_stats(
+    since: Option<&str>,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<VerifierStats>, BotError> {
+    use schema::runs::dsl::*;
+    let mut q = runs.filter(examiner_src_id.is_not_null()).into_boxed();
+    if let Some(s) = since {
+        q = q.filter(decided_at.ge(s));
+    }
+    let judged = q.order(decided_at.asc()).load::<Run>(conn)?;
+    Ok(compute_stats(judged))
+}
+
+fn compute_stats(judged: Vec<Run>) -> Vec<VerifierStats> {
+    struct Tally {
+        name: String,
+        judged: usize,
+        waits: Vec<i64>,
+    }
+    let mut by_examiner: HashMap<String, Tally> = HashMap::new();
+    for run in judged {
+        let examiner = match run.examiner_src_id {
+            Some(e) => e,
+            None => continue,
+        };
+        let tally = by_examiner.entry(examiner.clone()).or_insert(Tally {
+            name: examiner,
+            judged: 0,
+            waits: vec![],
+        });
+        // runs are in decision order, so this leaves us with their most recent name
+        if let Some(n) = run.examiner_name {
+            tally.name = n;
+        }
+        tally.judged += 1;
+        // runs with no wait time still count as judged; we just can't include them in the median
+        if let Some(w) = run.wait_secs {
+            tally.waits.push(w as i64);
+        }
+    }
+    let mut stats = by_examiner
+        .into_values()
+        .map(|mut t| VerifierStats {
+            name: t.name,
+            judged: t.judged,
+            median_wait_secs: median(&mut t.waits),
+        })
+        .collect::<Vec<_>>();
+    stats.sort_by(|a, b| b.judged.cmp(&a.judged).then(a.name.cmp(&b.name)));
+    stats
+}
+
+pub(crate) fn median(values: &mut [i64]) -> Option<i64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_unstable();
+    let mid = values.len() / 2;
+    if values.len() % 2 == 0 {
+        Some((values[mid - 1] + values[mid]) / 2)
+    } else {
+        Some(values[mid])
+    }
+}
+
+/// e.g. "3d 4h", or "5h" for anything under a day
+pub fn format_wait(secs: i64) -> String {
+    let hours = secs / (60 * 60);
+    if hours >= 24 {
+        format!("{}d {}h", hours / 24, hours % 24)
+    } else {
+        format!("{}h", hours)
+    }
+}
+
+/// one line per verifier, busiest first
+pub fn format_verifier_stats(stats: &[VerifierStats]) -> String {
+    if stats.is_empty() {
+        return "Nobody's judged anything!".to_string();
+    }
+    let mut lines = stats
+        .iter()
+        .take(MAX_LISTED)
+        .map(|s| {
+            let wait = s
+                .median_wait_secs
+                .map(format_wait)
+                .unwrap_or_else(|| "?".to_string());
+            format!("{}: {} runs (median wait {})", s.name, s.judged, wait)
+        })
+        .collect::<Vec<_>>();
+    if stats.len() > MAX_LISTED {
+        lines.push(format!("...and {} more", stats.len() - MAX_LISTED));
+    }
+    lines.join("\n")
+}
+
+pub fn verifier_stats_embed(stats: &[VerifierStats], days: Option<i64>) -> Embed {
+    let title = match days {
+        Some(d) => format!("Verifiers: the last {} days", d),
+        None => "Verifiers: all time".to_string(),
+    };
+    EmbedBuilder::new()
+        .title(title)
+        .description(format_verifier_stats(stats))
+        .field(
+            "Runs judged",
+            stats.iter().map(|s| s.judged).sum::<usize>().to_string(),
+            true,
+        )
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::runs::{test_run, Run};
+    use crate::verifier_stats::{compute_stats, format_wait, VerifierStats};
+
+    fn judged(examiner: &str, name: Option<&str>, wait_secs: Option<i32>) -> Run {
+        Run {
+            outcome: Some("verified".to_string()),
+            examiner_src_id: Some(examiner.to_string()),
+            examiner_name: name.map(ToString::to_string),
+            wait_secs,
+            ..test_run("abc123")
+        }
+    }
+
+    #[test]
+    fn test_compute_stats() {
+        let stats = compute_stats(vec![
+            judged("a", Some("alice"), Some(10)),
+            judged("b", None, Some(100)),
+            judged("a", Some("alice"), Some(30)),
+            judged("a", Some("alice"), None),
+            judged("a", Some("alice"), Some(20)),
+        ]);
+        assert_eq!(
+            vec![
+                VerifierStats {
+                    name: "alice".to_string(),
+                    judged: 4,
+                    median_wait_secs: Some(20),
+                },
+                VerifierStats {
+                    name: "b".to_string(),
+                    judged: 1,
+                    median_wait_secs: Some(100),
+                },
+            ],
+            stats
+        );
+    }
+
+    #[test]
+    fn test_format_wait() {
+        assert_eq!("5h", format_wait(5 * 60 * 60 + 59));
+        assert_eq!("3d 4h", format_wait((3 * 24 + 4) * 60 * 60));
+    }
+}
@@ -0,0 +1,252 @@
+// a read-only view of the queue, for our website and anything else that wants it without
+// having to ask SRC
+
+use crate::error::BotError;
+use crate::get_conn;
+use crate::models::runs::{Outcome, Run};
+use crate::schema;
+use crate::src::CategoriesRepository;
+use crate::utils::{format_hms, format_timestamp, parse_timestamp};
+use crate::verifier_stats::median;
+use crate::web::{json_response, status_response, WebState};
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+use hyper::{Body, Response, StatusCode};
+use log::warn;
+use serde::Serialize;
+
+/// how far back `/api/stats` looks for verified/rejected runs
+const STATS_DAYS: i64 = 7;
+
+/// a run, as we tell the outside world about it
+#[derive(Debug, Serialize)]
+pub struct ApiRun {
+    pub run_id: String,
+    pub runner: Option<String>,
+    pub category: Option<String>,
+    /// formatted like our posts, e.g. 1:23:45
+    pub time: Option<String>,
+    pub time_secs: Option<f64>,
+    pub weblink: Option<String>,
+    pub submitted: Option<String>,
+    /// whether a verifier has claimed it. who claimed (and who examined) it are left out, since
+    /// they're discord identities and this is public
+    pub claimed: bool,
+    pub left_queue_at: Option<String>,
+    /// "verified", "rejected", or "unknown" once it's left the queue
+    pub outcome: Option<String>,
+    pub decided_at: Option<String>,
+    pub wait_secs: Option<i32>,
+}
+
+impl ApiRun {
+    pub fn new(run: Run, categories: &CategoriesRepository<'_>) -> Self {
+        Self {
+            category: categories.category_name_from_db_run(&run),
+            time: run.primary_t.map(format_hms),
+            time_secs: run.primary_t,
+            run_id: run.run_id,
+            runner: run.runner_name,
+            weblink: run.weblink,
+            submitted: run.submitted,
+            claimed: run.claimed_by_discord_id.is_some(),
+            left_queue_at: run.left_queue_at,
+            outcome: run.outcome,
+            decided_at: run.decided_at,
+            wait_secs: run.wait_secs,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueueResponse {
+    pub length: usize,
+    pub oldest_submitted: Option<String>,
+    /// oldest first
+    pub runs: Vec<ApiRun>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatsResponse {
+    pub queue_length: i64,
+    pub oldest_submitted: Option<String>,
+    /// how long the oldest run in the queue has been waiting
+    pub oldest_wait_secs: Option<i64>,
+    /// the rest of these cover the last `period_days` days
+    pub period_days: i64,
+    pub verified: i64,
+    pub rejected: i64,
+    pub median_wait_secs: Option<i64>,
+}
+
+/// runs currently in the queue, oldest first. runs we don't know the submission time of go last
+pub fn queued_runs(conn: &mut SqliteConnection) -> Result<Vec<Run>, BotError> {
+    use schema::runs::dsl::*;
+    let mut queued = runs
+        .filter(left_queue_at.is_null())
+        .order(submitted.asc())
+        .load::<Run>(conn)?;
+    // sqlite sorts nulls first
+    queued.sort_by_key(|r| r.submitted.is_none());
+    Ok(queued)
+}
+
+fn queue_response(queued: Vec<Run>, categories: &CategoriesRepository<'_>) -> QueueResponse {
+    QueueResponse {
+        length: queued.len(),
+        oldest_submitted: queued.first().and_then(|r| r.submitted.clone()),
+        runs: queued
+            .into_iter()
+            .map(|r| ApiRun::new(r, categories))
+            .collect(),
+    }
+}
+
+fn gather_stats(
+    now: DateTime<Utc>,
+    conn: &mut SqliteConnection,
+) -> Result<StatsResponse, BotError> {
+    use schema::runs::dsl::*;
+    let queue_length = runs
+        .filter(left_queue_at.is_null())
+        .count()
+        .get_result::<i64>(conn)?;
+    let oldest_submitted = runs
+        .filter(left_queue_at.is_null())
+        .filter(submitted.is_not_null())
+        .order(submitted.asc())
+        .select(submitted)
+        .first::<Option<String>>(conn)
+        .optional()?
+        .flatten();
+    let oldest_wait_secs = oldest_submitted
+        .as_deref()
+        .and_then(parse_timestamp)
+        .map(|s| (now - s).num_seconds());
+    let since = format_timestamp(now - Duration::days(STATS_DAYS));
+    let decided = runs
+        .filter(decided_at.ge(&since))
+        .select((outcome, wait_secs))
+        .load::<(Option<String>, Option<i32>)>(conn)?;
+    let count = |o: Outcome| {
+        decided
+            .iter()
+            .filter(|(out, _)| out.as_deref() == Some(o.as_str()))
+            .count() as i64
+    };
+    let mut waits = decided
+        .iter()
+        .filter_map(|(_, w)| w.map(i64::from))
+        .collect::<Vec<_>>();
+    Ok(StatsResponse {
+        queue_length,
+        oldest_submitted,
+        oldest_wait_secs,
+        period_days: STATS_DAYS,
+        verified: count(Outcome::Verified),
+        rejected: count(Outcome::Rejected),
+        median_wait_secs: median(&mut waits),
+    })
+}
+
+/// `GET /api/queue`
+pub(crate) fn queue(state: &WebState) -> Response<Body> {
+    let result = get_conn(&state.database_url)
+        .map_err(BotError::from)
+        .and_then(|mut conn| queued_runs(&mut conn));
+    match result {
+        Ok(queued) => json_response(&queue_response(queued, &state.categories)),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// `GET /api/runs/{id}`, where `id` is the SRC run id
+pub(crate) fn run(state: &WebState, src_id: &str) -> Response<Body> {
+    let result = get_conn(&state.database_url)
+        .map_err(BotError::from)
+        .and_then(|mut conn| {
+            use schema::runs::dsl::*;
+            Ok(runs
+                .filter(run_id.eq(src_id))
+                .first::<Run>(&mut conn)
+                .optional()?)
+        });
+    match result {
+        Ok(Some(r)) => json_response(&ApiRun::new(r, &state.categories)),
+        Ok(None) => status_response(StatusCode::NOT_FOUND),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// `GET /api/stats`
+pub(crate) fn stats(state: &WebState) -> Response<Body> {
+    let result = get_conn(&state.database_url)
+        .map_err(BotError::from)
+        .and_then(|mut conn| gather_stats(Utc::now(), &mut conn));
+    match result {
+        Ok(s) => json_response(&s),
+        Err(e) => internal_error(e),
+    }
+}
+
+pub(crate) fn internal_error(e: BotError) -> Response<Body> {
+    warn!("Error serving API request: {}", e);
+    status_response(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::runs::{test_run, Run};
+    use crate::src::CategoriesRepository;
+    use crate::web::api::{queue_response, ApiRun};
+
+    fn queued(id: &str, submitted: Option<&str>, primary_t: Option<f64>) -> Run {
+        Run {
+            submitted: submitted.map(ToString::to_string),
+            weblink: Some(format!("https://www.speedrun.com/alttp/run/{}", id)),
+            runner_name: Some("someone".to_string()),
+            primary_t,
+            ..test_run(id)
+        }
+    }
+
+    #[test]
+    fn test_queue_response() {
+        let categories = CategoriesRepository::new(vec![], vec![]);
+        let resp = queue_response(
+            vec![
+                queued("a", Some("2022-09-01T12:00:00Z"), Some(5040.0)),
+                queued("b", None, None),
+            ],
+            &categories,
+        );
+        assert_eq!(2, resp.length);
+        assert_eq!(
+            Some("2022-09-01T12:00:00Z"),
+            resp.oldest_submitted.as_deref()
+        );
+        assert_eq!(Some("1:24:00"), resp.runs[0].time.as_deref());
+        assert_eq!(None, resp.runs[0].category);
+        assert_eq!(None, resp.runs[1].time);
+
+        let empty = queue_response(vec![], &categories);
+        assert_eq!(0, empty.length);
+        assert_eq!(None, empty.oldest_submitted);
+    }
+
+    #[test]
+    fn test_no_discord_identities() {
+        let categories = CategoriesRepository::new(vec![], vec![]);
+        let run = Run {
+            claimed_by_discord_id: Some("1234".to_string()),
+            claimed_by_name: Some("a verifier".to_string()),
+            examiner_name: Some("another verifier".to_string()),
+            ..queued("a", None, None)
+        };
+        let json = serde_json::to_value(ApiRun::new(run, &categories)).unwrap();
+        assert_eq!(true, json["claimed"]);
+        assert!(!json.to_string().contains("verifier"));
+        assert!(!json.to_string().contains("1234"));
+    }
+}
@@ -0,0 +1,280 @@
+use crate::error::BotError;
+use crate::get_conn;
+use crate::interactions::InteractionHandler;
+use crate::web::{json_response, status_response};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use hyper::body::HttpBody;
+use hyper::header::CONTENT_LENGTH;
+use hyper::{Body, Request, Response, StatusCode};
+use log::{debug, warn};
+use std::env;
+use std::time::{SystemTime, UNIX_EPOCH};
+use twilight_model::application::interaction::Interaction;
+
+const SIGNATURE_HEADER: &str = "x-signature-ed25519";
+const TIMESTAMP_HEADER: &str = "x-signature-timestamp";
+/// requests signed longer ago than this are rejected, so that a captured request can't be
+/// replayed later
+const MAX_TIMESTAMP_SKEW_SECS: u64 = 5 * 60;
+/// anything bigger than this isn't an interaction, and isn't worth buffering to find out
+const MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// receives interactions over HTTP, as an alternative to holding a gateway connection open
+pub struct InteractionsEndpoint {
+    public_key: VerifyingKey,
+    handler: InteractionHandler,
+}
+
+impl InteractionsEndpoint {
+    pub fn new(public_key: VerifyingKey, handler: InteractionHandler) -> Self {
+        Self {
+            public_key,
+            handler,
+        }
+    }
+
+    /// reads the application's public key from `DISCORD_PUBLIC_KEY` (hex, as shown in the
+    /// developer portal)
+    pub fn new_from_env(handler: InteractionHandler) -> Result<Self, BotError> {
+        let public_key = parse_public_key(&env::var("DISCORD_PUBLIC_KEY")?)?;
+        Ok(Self::new(public_key, handler))
+    }
+
+    pub async fn handle(&self, req: Request<Body>, database_url: &str) -> Response<Body> {
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(ToString::to_string)
+        };
+        let (signature, timestamp) = match (header(SIGNATURE_HEADER), header(TIMESTAMP_HEADER)) {
+            (Some(s), Some(t)) => (s, t),
+            _ => return status_response(StatusCode::UNAUTHORIZED),
+        };
+        let too_long = header(CONTENT_LENGTH.as_str())
+            .and_then(|l| l.parse::<usize>().ok())
+            .map_or(false, |l| l > MAX_BODY_BYTES);
+        if too_long {
+            return status_response(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+        // the length header is optional, so keep count while reading too
+        let body = match read_limited(req.into_body(), MAX_BODY_BYTES).await {
+            Ok(Some(b)) => b,
+            Ok(None) => return status_response(StatusCode::PAYLOAD_TOO_LARGE),
+            Err(e) => {
+                warn!("Error reading interaction body: {}", e);
+                return status_response(StatusCode::BAD_REQUEST);
+            }
+        };
+        if !timestamp_is_fresh(&timestamp, SystemTime::now())
+            || !verify_signature(&self.public_key, &signature, &timestamp, &body)
+        {
+            debug!("Rejecting interaction with a bad signature");
+            return status_response(StatusCode::UNAUTHORIZED);
+        }
+        let interaction = match serde_json::from_slice::<Interaction>(&body) {
+            Ok(i) => i,
+            Err(e) => {
+                warn!("Unable to parse interaction: {}", e);
+                return status_response(StatusCode::BAD_REQUEST);
+            }
+        };
+        match self.respond(&interaction, database_url).await {
+            Ok(Some(response)) => json_response(&response),
+            Ok(None) => status_response(StatusCode::NOT_FOUND),
+            Err(e) => {
+                warn!("Error handling interaction {:?}: {}", interaction, e);
+                status_response(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        }
+    }
+
+    async fn respond(
+        &self,
+        interaction: &Interaction,
+        database_url: &str,
+    ) -> Result<Option<twilight_model::http::interaction::InteractionResponse>, BotError> {
+        let mut conn = get_conn(database_url)?;
+        self.handler.handle(interaction, &mut conn).await
+    }
+}
+
+pub fn parse_public_key(hex_key: &str) -> Result<VerifyingKey, BotError> {
+    let invalid = |why: String| BotError::InvalidConfig(format!("Invalid public key: {}", why));
+    let bytes: [u8; 32] = hex::decode(hex_key.trim())
+        .map_err(|e| invalid(e.to_string()))?
+        .try_into()
+        .map_err(|_| invalid("wrong length".to_string()))?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| invalid(e.to_string()))
+}
+
+/// `None` if `body` turns out to be longer than `limit`, in which case we stop reading it
+async fn read_limited(mut body: Body, limit: usize) -> Result<Option<Vec<u8>>, hyper::Error> {
+    let mut bytes = vec![];
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if bytes.len() + chunk.len() > limit {
+            return Ok(None);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(Some(bytes))
+}
+
+/// discord signs the timestamp header followed by the raw body
+fn verify_signature(
+    public_key: &VerifyingKey,
+    signature_hex: &str,
+    timestamp: &str,
+    body: &[u8],
+) -> bool {
+    let signature = match hex::decode(signature_hex)
+        .ok()
+        .and_then(|b| Signature::from_slice(&b).ok())
+    {
+        Some(s) => s,
+        None => return false,
+    };
+    let mut message = timestamp.as_bytes().to_vec();
+    message.extend_from_slice(body);
+    public_key.verify(&message, &signature).is_ok()
+}
+
+fn timestamp_is_fresh(timestamp: &str, now: SystemTime) -> bool {
+    let ts = match timestamp.parse::<u64>() {
+        Ok(t) => t,
+        Err(_) => return false,
+    };
+    let now = match now.duration_since(UNIX_EPOCH) {
+        Ok(n) => n.as_secs(),
+        Err(_) => return false,
+    };
+    now.abs_diff(ts) <= MAX_TIMESTAMP_SKEW_SECS
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interactions::InteractionHandler;
+    use crate::src::{CategoriesRepository, SRCClient};
+    use crate::web::interactions::{
+        InteractionsEndpoint, MAX_BODY_BYTES, SIGNATURE_HEADER, TIMESTAMP_HEADER,
+    };
+    use crate::web::{route, WebState};
+    use ed25519_dalek::{Signer, SigningKey};
+    use hyper::header::CONTENT_LENGTH;
+    use hyper::{Body, Request, StatusCode};
+    use speedrun_api::SpeedrunApiClientAsync;
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    const PING: &str = r#"{"id":"1","application_id":"2","type":1,"token":"t","version":1}"#;
+
+    fn state(signing_key: &SigningKey) -> WebState {
+        WebState {
+            database_url: ":memory:".to_string(),
+            categories: Arc::new(CategoriesRepository::new(vec![], vec![])),
+            max_poll_age: Duration::from_secs(180),
+            interactions: Some(InteractionsEndpoint::new(
+                signing_key.verifying_key(),
+                InteractionHandler::new(
+                    Arc::new(SRCClient::new(SpeedrunApiClientAsync::new().unwrap())),
+                    None,
+                    None,
+                ),
+            )),
+        }
+    }
+
+    fn signed_request(signing_key: &SigningKey, timestamp: u64, body: &str) -> Request<Body> {
+        let timestamp = timestamp.to_string();
+        let signature = signing_key.sign(format!("{}{}", timestamp, body).as_bytes());
+        Request::post("/interactions")
+            .header(SIGNATURE_HEADER, hex::encode(signature.to_bytes()))
+            .header(TIMESTAMP_HEADER, timestamp)
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    #[tokio::test]
+    async fn test_ping() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let resp = route(signed_request(&key, now(), PING), &state(&key)).await;
+        assert_eq!(StatusCode::OK, resp.status());
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(1, json["type"]);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_bad_signatures() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let other_key = SigningKey::from_bytes(&[8; 32]);
+        let state = state(&key);
+
+        let wrong_key = signed_request(&other_key, now(), PING);
+        assert_eq!(
+            StatusCode::UNAUTHORIZED,
+            route(wrong_key, &state).await.status()
+        );
+
+        let stale = signed_request(&key, now() - 60 * 60, PING);
+        assert_eq!(
+            StatusCode::UNAUTHORIZED,
+            route(stale, &state).await.status()
+        );
+
+        let mut tampered = signed_request(&key, now(), PING);
+        *tampered.body_mut() = Body::from(PING.replace("\"t\"", "\"u\""));
+        assert_eq!(
+            StatusCode::UNAUTHORIZED,
+            route(tampered, &state).await.status()
+        );
+
+        let unsigned = Request::post("/interactions")
+            .body(Body::from(PING))
+            .unwrap();
+        assert_eq!(
+            StatusCode::UNAUTHORIZED,
+            route(unsigned, &state).await.status()
+        );
+    }
+    #[tokio::test]
+    async fn test_rejects_huge_bodies() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let state = state(&key);
+        let huge = " ".repeat(MAX_BODY_BYTES + 1);
+
+        // signed, even, but we shouldn't read that far
+        let mut declared = signed_request(&key, now(), &huge);
+        declared
+            .headers_mut()
+            .insert(CONTENT_LENGTH, huge.len().into());
+        assert_eq!(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            route(declared, &state).await.status()
+        );
+
+        // no length up front, so it's only noticed partway through
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            for _ in 0..=MAX_BODY_BYTES / 1024 {
+                if sender.send_data(vec![b' '; 1024].into()).await.is_err() {
+                    break;
+                }
+            }
+        });
+        let mut streamed = signed_request(&key, now(), PING);
+        *streamed.body_mut() = body;
+        assert_eq!(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            route(streamed, &state).await.status()
+        );
+    }
+}
@@ -0,0 +1,126 @@
+pub mod api;
+pub mod interactions;
+mod status_page;
+
+use crate::error::BotError;
+use crate::metrics::METRICS;
+use crate::shutdown::stopped;
+use crate::src::CategoriesRepository;
+use chrono::Utc;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use interactions::InteractionsEndpoint;
+use log::info;
+use std::convert::Infallible;
+use std::env;
+use std::env::VarError;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// everything the HTTP handlers need
+pub struct WebState {
+    pub database_url: String,
+    /// for naming runs' categories in the API
+    pub categories: Arc<CategoriesRepository<'static>>,
+    /// `None` if we aren't receiving interactions over HTTP
+    pub interactions: Option<InteractionsEndpoint>,
+    /// `/healthz` fails if we haven't polled SRC successfully in this long
+    pub max_poll_age: Duration,
+}
+
+/// where the HTTP server listens. nginx proxies to this, so it should usually be on localhost.
+/// returns `None` if we shouldn't be serving HTTP at all (i.e. `HTTP_LISTEN_ADDR` is unset)
+pub fn listen_addr_from_env() -> Result<Option<SocketAddr>, BotError> {
+    let addr = match env::var("HTTP_LISTEN_ADDR") {
+        Ok(a) => a,
+        Err(VarError::NotPresent) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    addr.parse()
+        .map(Some)
+        .map_err(|e| BotError::InvalidConfig(format!("Invalid HTTP_LISTEN_ADDR {}: {}", addr, e)))
+}
+
+/// how stale the last successful poll can get before `/healthz` fails. defaults to three poll
+/// intervals, so that one slow or failed poll doesn't set anything off
+pub fn max_poll_age_from_env(poll_interval: Duration) -> Result<Duration, BotError> {
+    match env::var("HEALTHZ_MAX_POLL_AGE_SECS") {
+        Ok(s) => Ok(Duration::from_secs(s.parse::<u64>()?)),
+        Err(VarError::NotPresent) => Ok(poll_interval * 3),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// serves HTTP requests until `stop` says to stop (or something goes horribly wrong), then
+/// finishes off any that are in progress
+pub async fn serve(
+    addr: SocketAddr,
+    state: Arc<WebState>,
+    stop: watch::Receiver<bool>,
+) -> Result<(), hyper::Error> {
+    let make_svc = make_service_fn(move |_conn| {
+        let state = state.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let state = state.clone();
+                async move { Ok::<_, Infallible>(route(req, &state).await) }
+            }))
+        }
+    });
+    info!("Listening for HTTP requests on {}", addr);
+    Server::bind(&addr)
+        .serve(make_svc)
+        .with_graceful_shutdown(stopped(stop))
+        .await
+}
+
+pub(crate) async fn route(req: Request<Body>, state: &WebState) -> Response<Body> {
+    match (req.method(), req.uri().path()) {
+        (&Method::POST, "/interactions") => match &state.interactions {
+            Some(endpoint) => endpoint.handle(req, &state.database_url).await,
+            None => status_response(StatusCode::NOT_FOUND),
+        },
+        (&Method::GET, "/") => status_page::status_page(state),
+        (&Method::GET, "/healthz") => healthz(state),
+        (&Method::GET, "/metrics") => Response::builder()
+            .header(hyper::header::CONTENT_TYPE, "text/plain; version=0.0.4")
+            .body(Body::from(METRICS.render()))
+            .unwrap_or_else(|_| status_response(StatusCode::INTERNAL_SERVER_ERROR)),
+        (&Method::GET, "/api/queue") => api::queue(state),
+        (&Method::GET, "/api/stats") => api::stats(state),
+        (&Method::GET, path) => match path.strip_prefix("/api/runs/") {
+            Some(id) if !id.is_empty() && !id.contains('/') => api::run(state, id),
+            _ => status_response(StatusCode::NOT_FOUND),
+        },
+        _ => status_response(StatusCode::NOT_FOUND),
+    }
+}
+
+fn healthz(state: &WebState) -> Response<Body> {
+    if METRICS.is_healthy(Utc::now(), state.max_poll_age) {
+        Response::new(Body::from("ok"))
+    } else {
+        let mut resp = Response::new(Body::from("no successful poll recently"));
+        *resp.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+        resp
+    }
+}
+
+/// an empty response with the given status
+pub(crate) fn status_response(status: StatusCode) -> Response<Body> {
+    let mut resp = Response::new(Body::empty());
+    *resp.status_mut() = status;
+    resp
+}
+
+pub(crate) fn json_response<T: serde::Serialize>(body: &T) -> Response<Body> {
+    match serde_json::to_vec(body) {
+        Ok(b) => Response::builder()
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(b))
+            .unwrap_or_else(|_| status_response(StatusCode::INTERNAL_SERVER_ERROR)),
+        Err(_) => status_response(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
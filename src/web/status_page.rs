@@ -0,0 +1,112 @@
+// a bare-bones HTML page showing what's in the queue
+
+use crate::error::BotError;
+use crate::get_conn;
+use crate::utils::{format_timestamp, parse_timestamp};
+use crate::verifier_stats::format_wait;
+use crate::web::api::{internal_error, queued_runs, ApiRun};
+use crate::web::WebState;
+use chrono::{DateTime, Utc};
+use hyper::{Body, Response};
+
+/// just enough to stop runner names from turning into markup
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn render(runs: &[ApiRun], now: DateTime<Utc>) -> String {
+    let cell = |s: Option<&str>| escape_html(s.unwrap_or("?"));
+    let rows = runs
+        .iter()
+        .map(|r| {
+            let waiting = r
+                .submitted
+                .as_deref()
+                .and_then(parse_timestamp)
+                .map(|s| format_wait((now - s).num_seconds()));
+            let runner = match &r.weblink {
+                Some(link) => format!(
+                    "<a href=\"{}\">{}</a>",
+                    escape_html(link),
+                    cell(r.runner.as_deref())
+                ),
+                None => cell(r.runner.as_deref()),
+            };
+            format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                runner,
+                cell(r.category.as_deref()),
+                cell(r.time.as_deref()),
+                cell(waiting.as_deref()),
+                if r.claimed { "Yes" } else { "" },
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>ALttP verification queue</title>
+</head>
+<body>
+<h1>ALttP verification queue</h1>
+<p>{} runs waiting</p>
+<table>
+<tr><th>Runner</th><th>Category</th><th>Time</th><th>Waiting</th><th>Claimed</th></tr>
+{}
+</table>
+<p><small>As of {}</small></p>
+</body>
+</html>
+"#,
+        runs.len(),
+        rows,
+        format_timestamp(now)
+    )
+}
+
+/// `GET /`
+pub(crate) fn status_page(state: &WebState) -> Response<Body> {
+    let result = get_conn(&state.database_url)
+        .map_err(BotError::from)
+        .and_then(|mut conn| queued_runs(&mut conn));
+    let queued = match result {
+        Ok(q) => q,
+        Err(e) => return internal_error(e),
+    };
+    let runs = queued
+        .into_iter()
+        .map(|r| ApiRun::new(r, &state.categories))
+        .collect::<Vec<_>>();
+    Response::builder()
+        .header(hyper::header::CONTENT_TYPE, "text/html; charset=utf-8")
+        .body(Body::from(render(&runs, Utc::now())))
+        .unwrap_or_else(|e| internal_error(BotError::InvalidState(e.to_string())))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::web::status_page::escape_html;
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!("plain", escape_html("plain"));
+        assert_eq!(
+            "&lt;script&gt;alert(&quot;hi&quot;)&lt;/script&gt; &amp; co&#39;s",
+            escape_html("<script>alert(\"hi\")</script> & co's")
+        );
+    }
+}